@@ -0,0 +1,244 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use librespot_core::spotify_id::FileId;
+use log::{debug, info, warn};
+
+use crate::config::{self, Config};
+use crate::spotify::Spotify;
+
+/// Interval at which the cache is measured again, to pick up the audio files
+/// librespot stores
+const MEASURE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of measurements after which the cache is checked against its size
+/// limit
+const EVICTION_MEASUREMENTS: u32 = 10;
+
+lazy_static! {
+    /// Running total of the disk space used by the cache
+    static ref USAGE: RwLock<CacheUsage> = RwLock::new(CacheUsage::default());
+}
+
+/// Disk space used by cached files in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheUsage {
+    pub audio: u64,
+    pub covers: u64,
+}
+
+impl CacheUsage {
+    pub fn total(&self) -> u64 {
+        self.audio + self.covers
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+fn audio_dir() -> PathBuf {
+    config::cache_path("librespot").join("files")
+}
+
+fn covers_dir() -> PathBuf {
+    config::cache_path("covers")
+}
+
+/// Size limit of the cache in bytes, if one is configured.
+pub fn size_limit(cfg: &Config) -> Option<u64> {
+    cfg.values().cache_size_mb.map(|size| size * 1024 * 1024)
+}
+
+/// Lists the files in `dir` and its subdirectories.
+fn cached_files(dir: &Path) -> Vec<CachedFile> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!("could not read cached file {:?}: {}", path, e);
+                continue;
+            }
+        };
+        if metadata.is_dir() {
+            files.extend(cached_files(&path));
+        } else if metadata.is_file() {
+            let last_used = metadata
+                .accessed()
+                .or_else(|_| metadata.modified())
+                .unwrap_or_else(|_| SystemTime::now());
+            files.push(CachedFile {
+                path,
+                size: metadata.len(),
+                last_used,
+            });
+        }
+    }
+    files
+}
+
+/// Path librespot stores the audio file `file` at.
+fn audio_file_path(file: &FileId) -> Option<PathBuf> {
+    let name = file.to_base16().ok()?;
+    Some(audio_dir().join(&name[0..2]).join(&name[2..]))
+}
+
+/// Disk space used by the cache as of its last measurement, along with the
+/// covers added and removed since.
+pub fn usage() -> CacheUsage {
+    *USAGE.read().unwrap()
+}
+
+/// Measures the disk space used by the cache again.
+pub fn refresh() {
+    let size = |files: Vec<CachedFile>| files.iter().map(|file| file.size).sum();
+    *USAGE.write().unwrap() = CacheUsage {
+        audio: size(cached_files(&audio_dir())),
+        covers: size(cached_files(&covers_dir())),
+    };
+}
+
+/// Counts the cover downloaded to `path` towards the cache usage.
+pub fn cover_added(path: &Path) {
+    if let Ok(metadata) = fs::metadata(path) {
+        USAGE.write().unwrap().covers += metadata.len();
+    }
+}
+
+/// Picks the files to delete to bring the cache down to `limit` bytes. Audio
+/// files go first, each kind from the least recently used one on. The files in
+/// `in_use` are kept.
+fn eviction_candidates(
+    audio: Vec<CachedFile>,
+    covers: Vec<CachedFile>,
+    limit: u64,
+    in_use: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut total: u64 = audio
+        .iter()
+        .chain(covers.iter())
+        .map(|file| file.size)
+        .sum();
+    let mut candidates = Vec::new();
+    for mut files in [audio, covers] {
+        files.sort_by_key(|file| file.last_used);
+        for file in files {
+            if total <= limit {
+                return candidates;
+            }
+            if in_use.contains(&file.path) {
+                continue;
+            }
+            total -= file.size;
+            candidates.push(file.path);
+        }
+    }
+    candidates
+}
+
+/// Deletes cached files other than the ones in `in_use` until the cache fits
+/// into `limit` bytes. Returns how many files were deleted.
+pub fn evict(limit: u64, in_use: &[PathBuf]) -> usize {
+    let candidates = eviction_candidates(
+        cached_files(&audio_dir()),
+        cached_files(&covers_dir()),
+        limit,
+        in_use,
+    );
+    let removed = candidates
+        .iter()
+        .filter(|path| match fs::remove_file(path) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("could not remove cached file {:?}: {}", path, e);
+                false
+            }
+        })
+        .count();
+    if removed > 0 {
+        info!("removed {} files from the cache", removed);
+    }
+    removed
+}
+
+/// Measures the cache right away and then every `MEASURE_INTERVAL`, and keeps
+/// it within the configured size limit. The audio files of the loaded and
+/// preloaded items are never deleted.
+pub fn start_eviction(cfg: Arc<Config>, spotify: Spotify) {
+    std::thread::spawn(move || {
+        for measurement in 0.. {
+            if measurement % EVICTION_MEASUREMENTS == 0 {
+                if let Some(limit) = size_limit(&cfg) {
+                    debug!("checking the cache against its limit of {} bytes", limit);
+                    let in_use: Vec<PathBuf> = spotify
+                        .audio_files_in_use()
+                        .iter()
+                        .filter_map(audio_file_path)
+                        .collect();
+                    evict(limit, &in_use);
+                }
+            }
+            refresh();
+            std::thread::sleep(MEASURE_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use super::{eviction_candidates, CachedFile};
+
+    #[test]
+    fn test_eviction_candidates() {
+        let start = SystemTime::UNIX_EPOCH;
+        let file = |name: &str, size: u64, used_secs: u64| CachedFile {
+            path: PathBuf::from(name),
+            size,
+            last_used: start + Duration::from_secs(used_secs),
+        };
+        let audio = vec![
+            file("recent", 40, 30),
+            file("oldest", 40, 10),
+            file("old", 40, 20),
+        ];
+        let covers = vec![file("cover", 10, 0)];
+        let names = |paths: Vec<PathBuf>| -> Vec<String> {
+            paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect()
+        };
+
+        assert!(eviction_candidates(audio.clone(), covers.clone(), 130, &[]).is_empty());
+        // audio goes before covers, even if they were used longer ago
+        assert_eq!(
+            names(eviction_candidates(audio.clone(), covers.clone(), 90, &[])),
+            ["oldest"]
+        );
+        assert_eq!(
+            names(eviction_candidates(audio.clone(), covers.clone(), 50, &[])),
+            ["oldest", "old"]
+        );
+        // files that are in use are kept, even if the limit can't be met
+        let in_use = [PathBuf::from("oldest"), PathBuf::from("recent")];
+        assert_eq!(
+            names(eviction_candidates(audio, covers, 0, &in_use)),
+            ["old", "cover"]
+        );
+    }
+}
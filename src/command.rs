@@ -1,4 +1,5 @@
 use crate::queue::RepeatSetting;
+use crate::spotify::UriType;
 use crate::spotify_url::SpotifyUrl;
 use std::collections::HashMap;
 use std::fmt;
@@ -79,12 +80,24 @@ pub enum ShiftMode {
 pub enum GotoMode {
     Album,
     Artist,
+    Show,
+}
+
+#[derive(Display, Clone, Copy, Serialize, Deserialize, Debug)]
+#[strum(serialize_all = "snake_case")]
+pub enum AlbumGroup {
+    Albums,
+    Singles,
+    AppearsOn,
+    Compilations,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum SeekDirection {
     Relative(i32),
     Absolute(u32),
+    Forward,
+    Backward,
 }
 
 impl fmt::Display for SeekDirection {
@@ -94,6 +107,8 @@ impl fmt::Display for SeekDirection {
             SeekDirection::Relative(delta) => {
                 format!("{}{}", if delta > &0 { "+" } else { "" }, delta)
             }
+            SeekDirection::Forward => "forward".into(),
+            SeekDirection::Backward => "backward".into(),
         };
         write!(f, "{}", repr)
     }
@@ -124,8 +139,14 @@ pub enum Command {
     Stop,
     Previous,
     Next,
+    Restart,
     Clear,
     Queue,
+    DedupQueue,
+    ClearAfterCurrent,
+    CacheInfo,
+    ExportQueue(String),
+    ImportQueue(String),
     PlayNext,
     Play,
     UpdateLibrary,
@@ -140,10 +161,14 @@ pub enum Command {
     Shuffle(Option<bool>),
     #[cfg(feature = "share_clipboard")]
     Share(TargetMode),
+    CopyUrl,
+    History,
     Back,
     Open(TargetMode),
-    Goto(GotoMode),
+    Goto(GotoMode, TargetMode),
+    Discography(Option<AlbumGroup>, Option<String>),
     Move(MoveMode, MoveAmount),
+    Mark,
     Shift(ShiftMode, Option<i32>),
     Search(String),
     Jump(JumpMode),
@@ -157,6 +182,12 @@ pub enum Command {
     ShowRecommendations(TargetMode),
     Redraw,
     Execute(String),
+    PlaybackSpeed(f64),
+    Sleep(Option<u32>),
+    TrimStart(Option<u32>),
+    Normalize(Option<bool>),
+    #[cfg(feature = "mpris")]
+    MprisDump,
 }
 
 impl fmt::Display for Command {
@@ -178,7 +209,13 @@ impl fmt::Display for Command {
             #[cfg(feature = "share_clipboard")]
             Command::Share(mode) => vec![mode.to_string()],
             Command::Open(mode) => vec![mode.to_string()],
-            Command::Goto(mode) => vec![mode.to_string()],
+            Command::Goto(mode, target) => vec![mode.to_string(), target.to_string()],
+            Command::ExportQueue(path) | Command::ImportQueue(path) => vec![path.clone()],
+            Command::Discography(group, artist_id) => group
+                .map(|group| group.to_string())
+                .into_iter()
+                .chain(artist_id.iter().map(|id| format!("spotify:artist:{}", id)))
+                .collect(),
             Command::Move(mode, amount) => match (mode, amount) {
                 (MoveMode::Playing, _) => vec!["playing".to_string()],
                 (MoveMode::Up, MoveAmount::Extreme) => vec!["top".to_string()],
@@ -198,13 +235,30 @@ impl fmt::Display for Command {
             Command::Sort(key, direction) => vec![key.to_string(), direction.to_string()],
             Command::ShowRecommendations(mode) => vec![mode.to_string()],
             Command::Execute(cmd) => vec![cmd.to_owned()],
+            Command::PlaybackSpeed(rate) => vec![rate.to_string()],
+            Command::Normalize(on) => match on {
+                Some(b) => vec![(if *b { "on" } else { "off" }).into()],
+                None => vec![],
+            },
+            Command::Sleep(minutes) => match minutes {
+                Some(minutes) => vec![minutes.to_string()],
+                None => vec!["off".into()],
+            },
+            Command::TrimStart(seconds) => match seconds {
+                Some(seconds) => vec![seconds.to_string()],
+                None => vec!["off".into()],
+            },
             Command::Quit
             | Command::TogglePlay
             | Command::Stop
             | Command::Previous
             | Command::Next
+            | Command::Restart
             | Command::Clear
             | Command::Queue
+            | Command::DedupQueue
+            | Command::ClearAfterCurrent
+            | Command::CacheInfo
             | Command::PlayNext
             | Command::Play
             | Command::UpdateLibrary
@@ -216,7 +270,12 @@ impl fmt::Display for Command {
             | Command::ReloadConfig
             | Command::Noop
             | Command::Logout
-            | Command::Redraw => vec![],
+            | Command::Redraw
+            | Command::CopyUrl
+            | Command::History
+            | Command::Mark => vec![],
+            #[cfg(feature = "mpris")]
+            Command::MprisDump => vec![],
         };
         repr_tokens.append(&mut extras_args);
         write!(f, "{}", repr_tokens.join(" "))
@@ -231,8 +290,14 @@ impl Command {
             Command::Stop => "stop",
             Command::Previous => "previous",
             Command::Next => "next",
+            Command::Restart => "restart",
             Command::Clear => "clear",
             Command::Queue => "queue",
+            Command::DedupQueue => "queue dedup",
+            Command::ClearAfterCurrent => "queue clearafter",
+            Command::CacheInfo => "cacheinfo",
+            Command::ExportQueue(_) => "queue export",
+            Command::ImportQueue(_) => "queue import",
             Command::PlayNext => "playnext",
             Command::Play => "play",
             Command::UpdateLibrary => "update",
@@ -247,9 +312,13 @@ impl Command {
             Command::Shuffle(_) => "shuffle",
             #[cfg(feature = "share_clipboard")]
             Command::Share(_) => "share",
+            Command::CopyUrl => "copyurl",
+            Command::History => "history",
             Command::Back => "back",
             Command::Open(_) => "open",
-            Command::Goto(_) => "goto",
+            Command::Goto(_, _) => "goto",
+            Command::Mark => "mark",
+            Command::Discography(_, _) => "discography",
             Command::Move(_, _) => "move",
             Command::Shift(_, _) => "shift",
             Command::Search(_) => "search",
@@ -266,6 +335,12 @@ impl Command {
             Command::ShowRecommendations(_) => "similar",
             Command::Redraw => "redraw",
             Command::Execute(_) => "exec",
+            Command::PlaybackSpeed(_) => "playbackspeed",
+            Command::Sleep(_) => "sleep",
+            Command::TrimStart(_) => "trimstart",
+            Command::Normalize(_) => "normalize",
+            #[cfg(feature = "mpris")]
+            Command::MprisDump => "mpris-dump",
         }
     }
 }
@@ -303,6 +378,24 @@ fn handle_aliases(input: &str) -> &str {
     }
 }
 
+/// Parses timestamps like `1:23:45` or `23:45` into milliseconds.
+fn parse_timestamp(timestamp: &str) -> Option<u32> {
+    let parts = timestamp
+        .split(':')
+        .map(|part| part.trim().parse::<u32>().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    let (hours, minutes, seconds) = match parts[..] {
+        [minutes, seconds] => (0, minutes, seconds),
+        [hours, minutes, seconds] if minutes < 60 => (hours, minutes, seconds),
+        _ => return None,
+    };
+    if seconds >= 60 {
+        return None;
+    }
+    let seconds = (hours as u64 * 60 + minutes as u64) * 60 + seconds as u64;
+    (seconds * 1000).try_into().ok()
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum CommandParseError {
     NoSuchCommand { cmd: String },
@@ -375,8 +468,38 @@ pub fn parse(input: &str) -> Result<Vec<Command>, CommandParseError> {
                 "stop" => Command::Stop,
                 "previous" => Command::Previous,
                 "next" => Command::Next,
+                "restart" => Command::Restart,
                 "clear" => Command::Clear,
-                "queue" => Command::Queue,
+                "cacheinfo" => Command::CacheInfo,
+                "queue" => match args.first().cloned() {
+                    Some("dedup") => Ok(Command::DedupQueue),
+                    Some("clearafter") => Ok(Command::ClearAfterCurrent),
+                    Some("export") => match args[1..].join(" ") {
+                        path if path.is_empty() => Err(InsufficientArgs {
+                            cmd: "queue export".into(),
+                            hint: Some("path".into()),
+                        }),
+                        path => Ok(Command::ExportQueue(path)),
+                    },
+                    Some("import") => match args[1..].join(" ") {
+                        path if path.is_empty() => Err(InsufficientArgs {
+                            cmd: "queue import".into(),
+                            hint: Some("path".into()),
+                        }),
+                        path => Ok(Command::ImportQueue(path)),
+                    },
+                    Some(arg) => Err(BadEnumArg {
+                        arg: arg.into(),
+                        accept: vec![
+                            "**omit**".into(),
+                            "dedup".into(),
+                            "clearafter".into(),
+                            "export".into(),
+                            "import".into(),
+                        ],
+                    }),
+                    None => Ok(Command::Queue),
+                }?,
                 "playnext" => Command::PlayNext,
                 "play" => Command::Play,
                 "update" => Command::UpdateLibrary,
@@ -397,6 +520,8 @@ pub fn parse(input: &str) -> Result<Vec<Command>, CommandParseError> {
                     // TODO: this really should be strongly typed
                     Command::Focus(target.into())
                 }
+                "seek" if args == ["forward"] => Command::Seek(SeekDirection::Forward),
+                "seek" if args == ["backward"] => Command::Seek(SeekDirection::Backward),
                 "seek" => {
                     if args.is_empty() {
                         return Err(InsufficientArgs {
@@ -417,6 +542,11 @@ pub fn parse(input: &str) -> Result<Vec<Command>, CommandParseError> {
                     let unsigned_millis = match duration_raw.parse() {
                         // accept raw milliseconds
                         Ok(millis) => millis,
+                        Err(_) if duration_raw.contains(':') => parse_timestamp(&duration_raw)
+                            .ok_or_else(|| ArgParseError {
+                                arg: duration_raw.clone(),
+                                err: "Expected a timestamp like 1:23:45 or 23:45".into(),
+                            })?,
                         Err(_) => parse_duration::parse(&duration_raw) // accept fancy duration
                             .map_err(|err| ArgParseError {
                                 arg: duration_raw.clone(),
@@ -538,17 +668,54 @@ pub fn parse(input: &str) -> Result<Vec<Command>, CommandParseError> {
                 "goto" => {
                     let &goto_mode_raw = args.first().ok_or(InsufficientArgs {
                         cmd: command.into(),
-                        hint: Some("album|artist".into()),
+                        hint: Some("album|artist|show".into()),
                     })?;
                     let goto_mode = match goto_mode_raw {
                         "album" => Ok(GotoMode::Album),
                         "artist" => Ok(GotoMode::Artist),
+                        "show" => Ok(GotoMode::Show),
                         _ => Err(BadEnumArg {
                             arg: goto_mode_raw.into(),
-                            accept: vec!["album".into(), "artist".into()],
+                            accept: vec!["album".into(), "artist".into(), "show".into()],
+                        }),
+                    }?;
+                    let target_mode = match args.get(1).cloned() {
+                        Some("selected") | None => Ok(TargetMode::Selected),
+                        Some("current") => Ok(TargetMode::Current),
+                        Some(arg) => Err(BadEnumArg {
+                            arg: arg.into(),
+                            accept: vec!["**omit**".into(), "selected".into(), "current".into()],
                         }),
                     }?;
-                    Command::Goto(goto_mode)
+                    Command::Goto(goto_mode, target_mode)
+                }
+                "discography" => {
+                    let mut group = None;
+                    let mut artist_id = None;
+                    for &arg in args {
+                        match arg {
+                            "albums" => group = Some(AlbumGroup::Albums),
+                            "singles" => group = Some(AlbumGroup::Singles),
+                            "appears_on" => group = Some(AlbumGroup::AppearsOn),
+                            "compilations" => group = Some(AlbumGroup::Compilations),
+                            _ => {
+                                let id = arg
+                                    .strip_prefix("spotify:artist:")
+                                    .map(str::to_string)
+                                    .or_else(|| {
+                                        SpotifyUrl::from_url(arg)
+                                            .filter(|url| url.uri_type == UriType::Artist)
+                                            .map(|url| url.id)
+                                    })
+                                    .ok_or(ArgParseError {
+                                        arg: arg.into(),
+                                        err: "Expected albums, singles, appears_on, compilations or an artist URI".into(),
+                                    })?;
+                                artist_id = Some(id);
+                            }
+                        }
+                    }
+                    Command::Discography(group, artist_id)
                 }
                 "move" => {
                     let &move_mode_raw = args.first().ok_or(InsufficientArgs {
@@ -720,7 +887,73 @@ pub fn parse(input: &str) -> Result<Vec<Command>, CommandParseError> {
                     Command::ShowRecommendations(target_mode)
                 }
                 "redraw" => Command::Redraw,
+                "copyurl" => Command::CopyUrl,
+                "history" => Command::History,
+                "mark" => Command::Mark,
+                #[cfg(feature = "mpris")]
+                "mpris-dump" => Command::MprisDump,
                 "exec" => Command::Execute(args.join(" ")),
+                "playbackspeed" => {
+                    let &rate_raw = args.first().ok_or(InsufficientArgs {
+                        cmd: command.into(),
+                        hint: Some("a rate, e.g. 1.5".into()),
+                    })?;
+                    let rate = rate_raw.parse::<f64>().map_err(|err| ArgParseError {
+                        arg: rate_raw.into(),
+                        err: err.to_string(),
+                    })?;
+                    if !rate.is_finite() {
+                        return Err(ArgParseError {
+                            arg: rate_raw.into(),
+                            err: "the rate has to be a finite number".into(),
+                        });
+                    }
+                    Command::PlaybackSpeed(rate)
+                }
+                "normalize" => {
+                    let switch = match args.first().cloned() {
+                        Some("on") => Ok(Some(true)),
+                        Some("off") => Ok(Some(false)),
+                        Some(arg) => Err(BadEnumArg {
+                            arg: arg.into(),
+                            accept: vec!["**omit**".into(), "on".into(), "off".into()],
+                        }),
+                        None => Ok(None),
+                    }?;
+                    Command::Normalize(switch)
+                }
+                "sleep" => match args.first().cloned() {
+                    Some("off") => Command::Sleep(None),
+                    Some(minutes_raw) => {
+                        let minutes = minutes_raw.parse::<u32>().map_err(|err| ArgParseError {
+                            arg: minutes_raw.into(),
+                            err: err.to_string(),
+                        })?;
+                        Command::Sleep(Some(minutes))
+                    }
+                    None => {
+                        return Err(InsufficientArgs {
+                            cmd: command.into(),
+                            hint: Some("a number of minutes or \"off\"".into()),
+                        })
+                    }
+                },
+                "trimstart" => match args.first().cloned() {
+                    Some("off") => Command::TrimStart(None),
+                    Some(seconds_raw) => {
+                        let seconds = seconds_raw.parse::<u32>().map_err(|err| ArgParseError {
+                            arg: seconds_raw.into(),
+                            err: err.to_string(),
+                        })?;
+                        Command::TrimStart(Some(seconds).filter(|&seconds| seconds > 0))
+                    }
+                    None => {
+                        return Err(InsufficientArgs {
+                            cmd: command.into(),
+                            hint: Some("a number of seconds or \"off\"".into()),
+                        })
+                    }
+                },
                 _ => {
                     return Err(NoSuchCommand {
                         cmd: command.into(),
@@ -732,3 +965,84 @@ pub fn parse(input: &str) -> Result<Vec<Command>, CommandParseError> {
     }
     Ok(commands)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Command, SeekDirection};
+
+    #[test]
+    fn test_parse_seek_timestamps() {
+        let seek = |input: &str| match parse(input).ok()?.as_slice() {
+            [Command::Seek(direction)] => Some(direction.to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            seek("seek 1:23:45"),
+            Some(SeekDirection::Absolute(5_025_000).to_string())
+        );
+        assert_eq!(
+            seek("seek 2:05"),
+            Some(SeekDirection::Absolute(125_000).to_string())
+        );
+        assert_eq!(
+            seek("seek -0:30"),
+            Some(SeekDirection::Relative(-30_000).to_string())
+        );
+        assert_eq!(
+            seek("seek +90"),
+            Some(SeekDirection::Relative(90).to_string())
+        );
+        assert_eq!(seek("seek 1:75"), None);
+        assert_eq!(seek("seek 1:2:3:4"), None);
+    }
+
+    #[test]
+    fn test_parse_discography() {
+        let discography = |input: &str| match parse(input).ok()?.as_slice() {
+            [command @ Command::Discography(_, _)] => Some(command.to_string()),
+            _ => None,
+        };
+        assert_eq!(discography("discography").as_deref(), Some("discography"));
+        assert_eq!(
+            discography("discography appears_on spotify:artist:4tZwfgrHOc3mvqYlEYSvVi").as_deref(),
+            Some("discography appears_on spotify:artist:4tZwfgrHOc3mvqYlEYSvVi")
+        );
+        assert_eq!(
+            discography("discography https://open.spotify.com/artist/4tZwfgrHOc3mvqYlEYSvVi")
+                .as_deref(),
+            Some("discography spotify:artist:4tZwfgrHOc3mvqYlEYSvVi")
+        );
+        assert_eq!(
+            discography("discography https://open.spotify.com/album/2noRn2Aes5aoNVsU6iWThc"),
+            None
+        );
+        assert_eq!(discography("discography eps"), None);
+    }
+
+    #[test]
+    fn test_parse_goto() {
+        let goto = |input: &str| match parse(input).ok()?.as_slice() {
+            [command @ Command::Goto(_, _)] => Some(command.to_string()),
+            _ => None,
+        };
+        assert_eq!(goto("goto album").as_deref(), Some("goto album selected"));
+        assert_eq!(
+            goto("goto show current").as_deref(),
+            Some("goto show current")
+        );
+        assert_eq!(goto("goto artist playing"), None);
+        assert_eq!(goto("goto playlist"), None);
+    }
+
+    #[test]
+    fn test_parse_playbackspeed() {
+        let speed = |input: &str| match parse(input).ok()?.as_slice() {
+            [Command::PlaybackSpeed(rate)] => Some(*rate),
+            _ => None,
+        };
+        assert_eq!(speed("playbackspeed 1.5"), Some(1.5));
+        assert_eq!(speed("playbackspeed nan"), None);
+        assert_eq!(speed("playbackspeed inf"), None);
+        assert_eq!(speed("playbackspeed -inf"), None);
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -9,16 +10,25 @@ use crate::config::Config;
 use crate::events::EventManager;
 use crate::ext_traits::CursiveExt;
 use crate::library::Library;
-use crate::queue::{Queue, RepeatSetting};
+use crate::model::artist::Artist;
+use crate::model::playable::Playable;
+#[cfg(feature = "mpris")]
+use crate::mpris::MprisManager;
+use crate::queue::Queue;
 use crate::spotify::{Spotify, VOLUME_PERCENT};
-use crate::traits::{IntoBoxedViewExt, ViewExt};
+use crate::traits::{IntoBoxedViewExt, ListItem, ViewExt};
+use crate::ui::album::AlbumView;
+use crate::ui::artist::ArtistView;
 use crate::ui::contextmenu::{
     AddToPlaylistMenu, ContextMenu, SelectArtistActionMenu, SelectArtistMenu,
 };
 use crate::ui::help::HelpView;
 use crate::ui::layout::Layout;
+use crate::ui::listview::ListView;
 use crate::ui::modal::Modal;
 use crate::ui::search_results::SearchResultsView;
+use crate::ui::show::ShowView;
+use crate::utils::{copy_to_clipboard, format_size};
 use crate::UserData;
 use cursive::event::{Event, Key};
 use cursive::traits::View;
@@ -42,6 +52,8 @@ pub struct CommandManager {
     library: Arc<Library>,
     config: Arc<Config>,
     events: EventManager,
+    #[cfg(feature = "mpris")]
+    mpris: Arc<MprisManager>,
 }
 
 impl CommandManager {
@@ -51,6 +63,7 @@ impl CommandManager {
         library: Arc<Library>,
         config: Arc<Config>,
         events: EventManager,
+        #[cfg(feature = "mpris")] mpris: Arc<MprisManager>,
     ) -> CommandManager {
         let bindings = RefCell::new(Self::get_bindings(config.clone()));
         CommandManager {
@@ -61,6 +74,8 @@ impl CommandManager {
             library,
             config,
             events,
+            #[cfg(feature = "mpris")]
+            mpris,
         }
     }
 
@@ -112,16 +127,17 @@ impl CommandManager {
         match cmd {
             Command::Noop => Ok(None),
             Command::Quit => {
+                let current_track = self.queue.get_current_index();
                 let queue = self.queue.queue.read().expect("can't readlock queue");
                 self.config.with_state_mut(move |mut s| {
                     debug!(
                         "saving state, {} items, current track: {:?}",
                         queue.len(),
-                        self.queue.get_current_index()
+                        current_track
                     );
                     s.queuestate.queue = queue.clone();
                     s.queuestate.random_order = self.queue.get_random_order();
-                    s.queuestate.current_track = self.queue.get_current_index();
+                    s.queuestate.current_track = current_track;
                     s.queuestate.track_progress = self.spotify.get_current_progress();
                 });
                 self.config.save_state();
@@ -138,17 +154,74 @@ impl CommandManager {
                 Ok(None)
             }
             Command::Previous => {
-                if self.spotify.get_current_progress() < Duration::from_secs(5) {
-                    self.queue.previous();
-                } else {
-                    self.spotify.seek(0);
-                }
+                self.queue.previous_or_restart();
                 Ok(None)
             }
             Command::Next => {
                 self.queue.next(true);
                 Ok(None)
             }
+            Command::Restart => {
+                if self.queue.restart_context() {
+                    Ok(None)
+                } else {
+                    Err("Nothing to restart, no playlist, album or show is playing".into())
+                }
+            }
+            Command::DedupQueue => {
+                self.queue.dedup();
+                Ok(None)
+            }
+            Command::CacheInfo => {
+                let usage = crate::cache::usage();
+                let limit = match crate::cache::size_limit(&self.config) {
+                    Some(limit) => format!("of {}", format_size(limit)),
+                    None => "without a limit".into(),
+                };
+                Ok(Some(format!(
+                    "Cache: {} {} ({} audio, {} covers)",
+                    format_size(usage.total()),
+                    limit,
+                    format_size(usage.audio),
+                    format_size(usage.covers)
+                )))
+            }
+            Command::ExportQueue(path) => {
+                let exported = self.queue.export(Path::new(path))?;
+                Ok(Some(format!("Exported {} items to {}", exported, path)))
+            }
+            Command::ImportQueue(path) => {
+                let entries = self.queue.import(Path::new(path))?;
+                Ok(Some(format!("Importing {} entries from {}", entries, path)))
+            }
+            Command::ClearAfterCurrent => {
+                if self.queue.get_current_index().is_none() {
+                    return Err("Nothing is playing".into());
+                }
+                self.queue.clear_after_current();
+                Ok(None)
+            }
+            Command::Discography(group, artist_id) => match artist_id {
+                Some(artist_id) => {
+                    Artist::queue_discography(artist_id, *group, self.queue.clone());
+                    Ok(None)
+                }
+                None => Err("Open an artist or pass its URI to queue its discography".into()),
+            },
+            Command::CopyUrl => {
+                let url = self
+                    .queue
+                    .get_current()
+                    .and_then(|playable| playable.share_url())
+                    .ok_or("Nothing is playing")?;
+                if copy_to_clipboard(&url) {
+                    Ok(Some(format!("Copied {}", url)))
+                } else {
+                    // e.g. no clipboard available, show the url instead
+                    info!("could not copy {} to the clipboard", url);
+                    Ok(Some(url))
+                }
+            }
             Command::Clear => {
                 let queue = self.queue.clone();
                 let confirmation = Dialog::text("Clear queue?")
@@ -174,19 +247,17 @@ impl CommandManager {
                 Ok(None)
             }
             Command::Repeat(mode) => {
-                let mode = mode.unwrap_or_else(|| match self.queue.get_repeat() {
-                    RepeatSetting::None => RepeatSetting::RepeatPlaylist,
-                    RepeatSetting::RepeatPlaylist => RepeatSetting::RepeatTrack,
-                    RepeatSetting::RepeatTrack => RepeatSetting::None,
-                });
+                let mode = mode.unwrap_or_else(|| self.queue.get_repeat().next());
 
                 self.queue.set_repeat(mode);
                 Ok(None)
             }
             Command::Seek(direction) => {
                 match *direction {
-                    SeekDirection::Relative(rel) => self.spotify.seek_relative(rel),
-                    SeekDirection::Absolute(abs) => self.spotify.seek(abs),
+                    SeekDirection::Relative(rel) => self.queue.seek_relative(rel as i64),
+                    SeekDirection::Absolute(abs) => self.queue.seek_to(abs),
+                    SeekDirection::Forward => self.queue.seek_forward(),
+                    SeekDirection::Backward => self.queue.seek_backward(),
                 }
                 Ok(None)
             }
@@ -212,6 +283,49 @@ impl CommandManager {
                 s.call_on_name("main", move |v: &mut Layout| v.push_view(view));
                 Ok(None)
             }
+            Command::Goto(mode, _) => {
+                let current = self.queue.get_current().ok_or("Nothing is playing")?;
+                let queue = self.queue.clone();
+                let library = self.library.clone();
+                let view = match mode {
+                    GotoMode::Album => current
+                        .album(queue.clone())
+                        .map(|album| AlbumView::new(queue, library, &album).into_boxed_view_ext())
+                        .ok_or("The current item has no album")?,
+                    GotoMode::Artist => {
+                        let mut artists = current.artists().unwrap_or_default();
+                        match artists.len() {
+                            0 => return Err("The current item has no artist".into()),
+                            1 => ArtistView::new(queue, library, &artists.remove(0))
+                                .into_boxed_view_ext(),
+                            _ => {
+                                let dialog =
+                                    ContextMenu::select_artist_dialog(library, queue, artists);
+                                s.add_layer(dialog);
+                                return Ok(None);
+                            }
+                        }
+                    }
+                    GotoMode::Show => current
+                        .show(queue.clone())
+                        .map(|show| ShowView::new(queue, library, &show).into_boxed_view_ext())
+                        .ok_or("The current item isn't part of a show")?,
+                };
+                s.call_on_name("main", move |v: &mut Layout| v.push_view(view));
+                Ok(None)
+            }
+            Command::History => {
+                let view = ListView::new(
+                    self.library.history.clone(),
+                    self.queue.clone(),
+                    self.library.clone(),
+                )
+                .with_title("History");
+                s.call_on_name("main", move |v: &mut Layout| {
+                    v.push_view(view.into_boxed_view_ext())
+                });
+                Ok(None)
+            }
             Command::ReloadConfig => {
                 self.config.reload();
 
@@ -226,6 +340,12 @@ impl CommandManager {
                 self.register_keybindings(s);
                 Ok(None)
             }
+            #[cfg(feature = "mpris")]
+            Command::MprisDump => {
+                let metadata = self.mpris.dump_metadata();
+                info!("current MPRIS metadata: {:#}", metadata);
+                Ok(Some(metadata.to_string()))
+            }
             Command::NewPlaylist(name) => {
                 match self.spotify.api.create_playlist(name, None, None) {
                     Some(_) => self.library.update_library(),
@@ -262,6 +382,29 @@ impl CommandManager {
                 s.quit();
                 Ok(None)
             }
+            Command::PlaybackSpeed(rate) => {
+                self.queue.set_playback_rate(*rate);
+                Ok(None)
+            }
+            Command::Normalize(mode) => {
+                let mode = mode.unwrap_or_else(|| !self.spotify.normalisation());
+                self.queue.set_normalisation(mode);
+                Ok(None)
+            }
+            Command::Sleep(minutes) => {
+                let duration = minutes.map(|minutes| Duration::from_secs(minutes as u64 * 60));
+                self.spotify.set_sleep_timer(duration);
+                Ok(None)
+            }
+            Command::TrimStart(seconds) => {
+                let show_id = match self.queue.get_current() {
+                    Some(Playable::Episode(episode)) => episode.show_id,
+                    _ => return Err("The playing item isn't a podcast episode".into()),
+                }
+                .ok_or("The show of the playing episode is unknown")?;
+                self.queue.set_show_start_offset(&show_id, *seconds);
+                Ok(None)
+            }
             Command::Execute(cmd) => {
                 log::info!("Executing command: {}", cmd);
                 let cmd = std::ffi::CString::new(cmd.clone()).unwrap();
@@ -279,8 +422,8 @@ impl CommandManager {
             | Command::Focus(_)
             | Command::Back
             | Command::Open(_)
-            | Command::Goto(_)
             | Command::Move(_, _)
+            | Command::Mark
             | Command::Shift(_, _)
             | Command::Jump(_)
             | Command::Insert(_)
@@ -387,7 +530,7 @@ impl CommandManager {
         kb.insert(">".into(), vec![Command::Next]);
         kb.insert("c".into(), vec![Command::Clear]);
         kb.insert(
-            "Space".into(),
+            "e".into(),
             vec![
                 Command::Queue,
                 Command::Move(MoveMode::Down, Default::default()),
@@ -401,6 +544,7 @@ impl CommandManager {
             ],
         );
         kb.insert("Enter".into(), vec![Command::Play]);
+        kb.insert("Space".into(), vec![Command::Mark]);
         kb.insert("n".into(), vec![Command::Jump(JumpMode::Next)]);
         kb.insert("Shift+n".into(), vec![Command::Jump(JumpMode::Previous)]);
         kb.insert("s".into(), vec![Command::Save]);
@@ -446,8 +590,14 @@ impl CommandManager {
 
         kb.insert("o".into(), vec![Command::Open(TargetMode::Selected)]);
         kb.insert("Shift+o".into(), vec![Command::Open(TargetMode::Current)]);
-        kb.insert("a".into(), vec![Command::Goto(GotoMode::Album)]);
-        kb.insert("Shift+a".into(), vec![Command::Goto(GotoMode::Artist)]);
+        kb.insert(
+            "a".into(),
+            vec![Command::Goto(GotoMode::Album, TargetMode::Selected)],
+        );
+        kb.insert(
+            "Shift+a".into(),
+            vec![Command::Goto(GotoMode::Artist, TargetMode::Selected)],
+        );
 
         kb.insert(
             "m".into(),
@@ -34,6 +34,31 @@ pub enum LibraryTab {
     Browse,
 }
 
+/// Order the episodes of a show are played in
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShowPlayOrder {
+    NewestFirst,
+    OldestFirst,
+}
+
+/// What happens when playback reaches the end of the queue
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueEnd {
+    Stop,
+    RepeatAll,
+    Radio,
+}
+
+/// What happens when the sleep timer runs out
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SleepTimerAction {
+    Pause,
+    Quit,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct TrackFormat {
     pub left: Option<String>,
@@ -77,15 +102,22 @@ pub struct ConfigValues {
     pub flip_status_indicators: Option<bool>,
     pub audio_cache: Option<bool>,
     pub audio_cache_size: Option<u32>,
+    pub cache_size_mb: Option<u64>,
     pub backend: Option<String>,
     pub backend_device: Option<String>,
+    #[serde(alias = "volume_normalization")]
     pub volnorm: Option<bool>,
     pub volnorm_pregain: Option<f64>,
     pub notify: Option<bool>,
     pub bitrate: Option<u32>,
     pub gapless: Option<bool>,
+    pub crossfade_secs: Option<u32>,
     pub shuffle: Option<bool>,
     pub repeat: Option<queue::RepeatSetting>,
+    pub restore_queue: Option<bool>,
+    pub autoplay: Option<bool>,
+    pub on_queue_end: Option<QueueEnd>,
+    pub mouse: Option<bool>,
     pub cover_max_scale: Option<f32>,
     pub playback_state: Option<PlaybackState>,
     pub track_format: Option<TrackFormat>,
@@ -93,6 +125,19 @@ pub struct ConfigValues {
     pub statusbar_format: Option<String>,
     pub library_tabs: Option<Vec<LibraryTab>>,
     pub hide_display_names: Option<bool>,
+    pub raise_command: Option<String>,
+    pub mpris_cache_size: Option<usize>,
+    pub mpris_idle_interval_ms: Option<u32>,
+    pub mpris_local_art: Option<bool>,
+    pub mpris_name: Option<String>,
+    pub open_track_loads_context: Option<bool>,
+    pub show_play_order: Option<ShowPlayOrder>,
+    pub previous_track_threshold_secs: Option<u64>,
+    pub seek_forward_secs: Option<u32>,
+    pub seek_backward_secs: Option<u32>,
+    pub sleep_timer_action: Option<SleepTimerAction>,
+    pub sleep_timer_fade: Option<bool>,
+    pub fade_ms: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -140,6 +185,12 @@ pub struct UserState {
     pub playlist_orders: HashMap<String, SortingOrder>,
     pub cache_version: u16,
     pub playback_state: PlaybackState,
+    /// Crossfade duration, missing from older state files
+    #[serde(default)]
+    pub crossfade_ms: u32,
+    /// Seconds to skip at the start of the episodes of a show, by show id
+    #[serde(default)]
+    pub show_start_offsets: HashMap<String, u32>,
 }
 
 impl Default for UserState {
@@ -152,6 +203,8 @@ impl Default for UserState {
             playlist_orders: HashMap::new(),
             cache_version: 0,
             playback_state: PlaybackState::Default,
+            crossfade_ms: 0,
+            show_start_offsets: HashMap::new(),
         }
     }
 }
@@ -187,6 +240,10 @@ impl Config {
             userstate.repeat = repeat;
         }
 
+        if let Some(crossfade_secs) = values.crossfade_secs {
+            userstate.crossfade_ms = crossfade_secs * 1000;
+        }
+
         if let Some(playback_state) = values.playback_state.clone() {
             userstate.playback_state = playback_state;
         }
@@ -0,0 +1,261 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+
+use librespot_playback::audio_backend::{Sink, SinkResult};
+use librespot_playback::convert::Converter;
+use librespot_playback::decoder::AudioPacket;
+use librespot_playback::{NUM_CHANNELS, SAMPLE_RATE};
+
+/// Where in the current track playback is, how long its end overlaps with the
+/// start of the next one and the faded out end itself, all in frames.
+#[derive(Debug, Default)]
+pub struct TrackFade {
+    pub fade: u64,
+    pub position: u64,
+    pub length: u64,
+    /// Set once librespot reached the end of the track, so its end is mixed
+    /// into the next track instead of being dropped
+    pub ended: bool,
+    /// Faded out samples of the end of the track, held back until the next
+    /// track starts
+    tail: VecDeque<f64>,
+    /// Frames at the front of `tail` left over from the previous track
+    mixing: u64,
+    /// Frames the start of the track is faded in over while it is mixed
+    fade_in: u64,
+}
+
+impl TrackFade {
+    /// Resets the fade for a newly loaded track. The end of the previous
+    /// track is kept to be mixed into this one if it was played to its end.
+    pub fn load(&mut self, fade_ms: u32, position_ms: u32, length_ms: u32) {
+        if !self.ended || position_ms != 0 {
+            self.tail.clear();
+        }
+        self.fade = frames(fade_ms);
+        self.position = frames(position_ms);
+        self.length = frames(length_ms);
+        self.ended = false;
+        self.mixing = (self.tail.len() / NUM_CHANNELS as usize) as u64;
+        self.fade_in = self.mixing;
+    }
+
+    /// Moves to `position_ms` in the current track, dropping whatever was
+    /// held back for the previous position.
+    pub fn seek(&mut self, position_ms: u32) {
+        self.position = frames(position_ms);
+        self.ended = false;
+        self.tail.clear();
+        self.mixing = 0;
+    }
+
+    /// Drops the end of the track, unless it was played completely and is
+    /// still to be heard.
+    pub fn stop(&mut self) {
+        if !self.ended {
+            self.tail.clear();
+            self.mixing = 0;
+        }
+    }
+
+    /// Mixes `frame` with the end of the previous track and either appends it
+    /// to `output` or holds it back if it belongs to the end of this track.
+    fn process(&mut self, frame: &[f64], output: &mut Vec<f64>) {
+        let mut frame = frame.to_vec();
+        if self.mixing > 0 {
+            // episodes are not faded, the previous track fades out below them
+            let gain = if self.fade == 0 {
+                1.0
+            } else {
+                self.position as f64 / self.fade_in as f64
+            };
+            for sample in frame.iter_mut() {
+                *sample = *sample * gain + self.tail.pop_front().unwrap_or(0.0);
+            }
+            self.mixing -= 1;
+        }
+
+        if self.fade > 0 && self.position + self.fade >= self.length {
+            let gain = self.length.saturating_sub(self.position) as f64 / self.fade as f64;
+            self.tail
+                .extend(frame.iter().map(|sample| sample * gain.min(1.0)));
+        } else {
+            output.extend(frame);
+        }
+        self.position += 1;
+    }
+
+    /// Takes the held back end of the track.
+    fn drain(&mut self) -> Vec<f64> {
+        self.mixing = 0;
+        self.tail.drain(..).collect()
+    }
+}
+
+/// Number of frames played in `ms` milliseconds.
+pub fn frames(ms: u32) -> u64 {
+    ms as u64 * SAMPLE_RATE as u64 / 1000
+}
+
+/// Audio sink wrapper that crossfades consecutive tracks.
+///
+/// librespot decodes a single track at a time, so the faded out end of a track
+/// is held back and mixed into the start of the next one as it comes in. The
+/// worker resets the shared `TrackFade` whenever a track is loaded or seeked
+/// in, the sink keeps track of the position by counting the frames passing
+/// through it.
+pub struct FadeSink {
+    sink: Box<dyn Sink>,
+    fade: Arc<RwLock<TrackFade>>,
+}
+
+impl FadeSink {
+    pub fn new(sink: Box<dyn Sink>, fade: Arc<RwLock<TrackFade>>) -> FadeSink {
+        FadeSink { sink, fade }
+    }
+
+    fn apply(&self, samples: &[f64]) -> Vec<f64> {
+        let mut fade = self.fade.write().expect("could not acquire lock on fade");
+        let mut output = Vec::with_capacity(samples.len());
+        for frame in samples.chunks(NUM_CHANNELS as usize) {
+            fade.process(frame, &mut output);
+        }
+        output
+    }
+}
+
+impl Sink for FadeSink {
+    fn start(&mut self) -> SinkResult<()> {
+        self.sink.start()
+    }
+
+    fn stop(&mut self) -> SinkResult<()> {
+        // nothing follows the last track, so its end is played on its own
+        let tail = {
+            let mut fade = self.fade.write().expect("could not acquire lock on fade");
+            if fade.ended {
+                fade.drain()
+            } else {
+                Vec::new()
+            }
+        };
+        if !tail.is_empty() {
+            let mut converter = Converter::new(None);
+            self.sink
+                .write(AudioPacket::Samples(tail), &mut converter)?;
+        }
+        self.sink.stop()
+    }
+
+    fn write(&mut self, packet: AudioPacket, converter: &mut Converter) -> SinkResult<()> {
+        match packet {
+            AudioPacket::Samples(samples) => {
+                let output = self.apply(&samples);
+                if output.is_empty() {
+                    return Ok(());
+                }
+                self.sink.write(AudioPacket::Samples(output), converter)
+            }
+            packet => self.sink.write(packet, converter),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex, RwLock};
+
+    use librespot_playback::audio_backend::{Sink, SinkResult};
+    use librespot_playback::convert::Converter;
+    use librespot_playback::decoder::AudioPacket;
+    use librespot_playback::NUM_CHANNELS;
+
+    use super::{frames, FadeSink, TrackFade};
+
+    /// Sink recording the first channel of every frame written to it.
+    struct RecordingSink(Arc<Mutex<Vec<f64>>>);
+
+    impl Sink for RecordingSink {
+        fn write(&mut self, packet: AudioPacket, _: &mut Converter) -> SinkResult<()> {
+            if let AudioPacket::Samples(samples) = packet {
+                let mut written = self.0.lock().unwrap();
+                written.extend(samples.iter().step_by(NUM_CHANNELS as usize));
+            }
+            Ok(())
+        }
+    }
+
+    fn play(sink: &mut FadeSink, value: f64, length: u64) {
+        let samples = vec![value; length as usize * NUM_CHANNELS as usize];
+        let mut converter = Converter::new(None);
+        sink.write(AudioPacket::Samples(samples), &mut converter)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_crossfade() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let fade = Arc::new(RwLock::new(TrackFade::default()));
+        let mut sink = FadeSink::new(Box::new(RecordingSink(written.clone())), fade.clone());
+
+        // frames(1) is 44 frames, tracks of 10ms fade over the last 1ms
+        let (fade_frames, length) = (frames(1), frames(10));
+        fade.write().unwrap().load(1, 0, 10);
+        play(&mut sink, 1.0, length);
+        // the end of the track is held back for the next one
+        assert_eq!(written.lock().unwrap().len() as u64, length - fade_frames);
+        assert!(written.lock().unwrap().iter().all(|&s| s == 1.0));
+
+        fade.write().unwrap().ended = true;
+        fade.write().unwrap().load(1, 0, 10);
+        play(&mut sink, 0.5, length);
+        {
+            let written = written.lock().unwrap();
+            // the tracks overlap, so the second one starts before the end of
+            // the first one was written
+            assert_eq!(written.len() as u64, 2 * (length - fade_frames));
+            let overlap = &written[(length - fade_frames) as usize..length as usize];
+            assert_eq!(overlap[0], 1.0);
+            assert!(overlap.iter().all(|&s| s > 0.5 && s <= 1.0));
+            assert!(overlap.windows(2).all(|w| w[1] <= w[0]));
+            assert!(written[length as usize..].iter().all(|&s| s == 0.5));
+        }
+
+        // the end of the last track is played once playback stops
+        fade.write().unwrap().ended = true;
+        sink.stop().unwrap();
+        assert_eq!(
+            written.lock().unwrap().len() as u64,
+            2 * length - fade_frames
+        );
+        assert_eq!(
+            *written.lock().unwrap().last().unwrap(),
+            0.5 / fade_frames as f64
+        );
+    }
+
+    #[test]
+    fn test_crossfade_skipped() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let fade = Arc::new(RwLock::new(TrackFade::default()));
+        let mut sink = FadeSink::new(Box::new(RecordingSink(written.clone())), fade.clone());
+
+        fade.write().unwrap().load(1, 0, 10);
+        play(&mut sink, 1.0, frames(10) - 1);
+
+        // a track skipped before its end is not mixed into the next one
+        fade.write().unwrap().load(1, 0, 10);
+        play(&mut sink, 0.5, frames(5));
+        let written = written.lock().unwrap();
+        assert!(written[(frames(10) - frames(1)) as usize..]
+            .iter()
+            .all(|&s| s == 0.5));
+
+        // without a fade the samples are left alone
+        let mut fade = TrackFade::default();
+        fade.load(0, 0, 10);
+        let mut output = Vec::new();
+        fade.process(&[1.0, 1.0], &mut output);
+        assert_eq!(output, vec![1.0, 1.0]);
+    }
+}
@@ -1,12 +1,26 @@
 use crossbeam_channel::{unbounded, Receiver, Sender, TryIter};
 use cursive::{CbSink, Cursive};
 
-use crate::queue::QueueEvent;
+use crate::command::Command;
+use crate::library::LibraryChange;
+use crate::model::playlist::Playlist;
+use crate::queue::{QueueChange, QueueEvent};
 use crate::spotify::PlayerEvent;
 
 pub enum Event {
     Player(PlayerEvent),
     Queue(QueueEvent),
+    QueueChanged(QueueChange),
+    Command(Command),
+    Seeked(u32),
+    VolumeChanged,
+    PlaybackModeChanged,
+    #[cfg(feature = "mpris")]
+    Fullscreen(bool),
+    ScreenChanged(String),
+    LibraryLoaded,
+    LibraryChanged(LibraryChange),
+    PlaylistChanged(Playlist),
     SessionDied,
 }
 
@@ -0,0 +1,72 @@
+/// Matches `query` against `text` ignoring case: as a substring if possible,
+/// otherwise as a subsequence of its characters, so that e.g. "dfpk" finds
+/// "Daft Punk". Returns the byte ranges of the matched parts of `text`.
+pub fn fuzzy_match(text: &str, query: &str) -> Option<Vec<(usize, usize)>> {
+    let query: Vec<char> = query.chars().map(lowercase).collect();
+    if query.iter().all(|c| c.is_whitespace()) {
+        return None;
+    }
+    let chars: Vec<(usize, char)> = text
+        .char_indices()
+        .map(|(index, c)| (index, lowercase(c)))
+        .collect();
+
+    // a contiguous match is the most relevant one
+    for start in 0..chars.len().saturating_sub(query.len() - 1) {
+        let candidate = &chars[start..start + query.len()];
+        if candidate.iter().map(|(_, c)| c).eq(query.iter()) {
+            let end = chars
+                .get(start + query.len())
+                .map_or(text.len(), |(index, _)| *index);
+            return Some(vec![(chars[start].0, end)]);
+        }
+    }
+
+    let mut query = query.iter().filter(|c| !c.is_whitespace()).peekable();
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (index, c) in text.char_indices() {
+        let next = match query.peek() {
+            Some(&&next) => next,
+            None => break,
+        };
+        if lowercase(c) != next {
+            continue;
+        }
+        query.next();
+        let end = index + c.len_utf8();
+        match ranges.last_mut() {
+            Some(last) if last.1 == index => last.1 = end,
+            _ => ranges.push((index, end)),
+        }
+    }
+    query.peek().is_none().then_some(ranges)
+}
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fuzzy_match;
+
+    #[test]
+    fn test_fuzzy_match() {
+        let text = "Daft Punk - Something About Us";
+        assert_eq!(fuzzy_match(text, "punk"), Some(vec![(5, 9)]));
+        assert_eq!(fuzzy_match(text, "about us"), Some(vec![(22, 30)]));
+        assert_eq!(
+            fuzzy_match(text, "dfpk"),
+            Some(vec![(0, 1), (2, 3), (5, 6), (8, 9)])
+        );
+        assert_eq!(
+            fuzzy_match(text, "daft us"),
+            Some(vec![(0, 4), (6, 7), (12, 13)])
+        );
+        assert_eq!(fuzzy_match(text, "pund"), None);
+        assert_eq!(fuzzy_match(text, " "), None);
+
+        // matched ranges are byte ranges of the original text
+        assert_eq!(fuzzy_match("Björk", "ÖR"), Some(vec![(2, 5)]));
+    }
+}
@@ -1,10 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::iter::Iterator;
 use std::ops::Deref;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock, RwLockReadGuard};
 use std::thread;
+use std::time::Duration;
 
+use chrono::Utc;
 use log::{debug, error, info};
 use rspotify::model::Id;
 use serde::de::DeserializeOwned;
@@ -12,9 +15,10 @@ use serde::Serialize;
 
 use crate::config::Config;
 use crate::config::{self, CACHE_VERSION};
-use crate::events::EventManager;
+use crate::events::{Event, EventManager};
 use crate::model::album::Album;
 use crate::model::artist::Artist;
+use crate::model::history::HistoryEntry;
 use crate::model::playable::Playable;
 use crate::model::playlist::Playlist;
 use crate::model::show::Show;
@@ -25,6 +29,22 @@ const CACHE_TRACKS: &str = "tracks.db";
 const CACHE_ALBUMS: &str = "albums.db";
 const CACHE_ARTISTS: &str = "artists.db";
 const CACHE_PLAYLISTS: &str = "playlists.db";
+const CACHE_HISTORY: &str = "history.db";
+
+/// Number of recently played items kept in the history
+const HISTORY_SIZE: usize = 100;
+
+/// Delay before the history is saved, so that skipping through several items
+/// writes it once
+const HISTORY_SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// An item saved to or removed from the library, broadcast so that external
+/// tools (e.g. via MPRIS) can react to it.
+#[derive(Clone, Debug)]
+pub enum LibraryChange {
+    Added(String),
+    Removed(String),
+}
 
 #[derive(Clone)]
 pub struct Library {
@@ -33,7 +53,12 @@ pub struct Library {
     pub artists: Arc<RwLock<Vec<Artist>>>,
     pub playlists: Arc<RwLock<Vec<Playlist>>>,
     pub shows: Arc<RwLock<Vec<Show>>>,
+    /// Recently played items, most recent first
+    pub history: Arc<RwLock<Vec<HistoryEntry>>>,
+    history_save_pending: Arc<AtomicBool>,
     pub is_done: Arc<RwLock<bool>>,
+    /// Ids of the saved tracks, for quick lookups
+    saved_track_ids: Arc<RwLock<HashSet<String>>>,
     pub user_id: Option<String>,
     pub display_name: Option<String>,
     ev: EventManager,
@@ -47,22 +72,42 @@ impl Library {
         let user_id = current_user.as_ref().map(|u| u.id.id().to_string());
         let display_name = current_user.as_ref().and_then(|u| u.display_name.clone());
 
-        let library = Self {
+        let library = Self::empty(ev, spotify, cfg, user_id, display_name);
+        library.load_cache(config::cache_path(CACHE_HISTORY), library.history.clone());
+        library.update_library();
+        library
+    }
+
+    /// Creates a library without any content, for tests that must not talk to
+    /// the Web API.
+    #[cfg(test)]
+    pub fn new_detached(ev: &EventManager, spotify: Spotify, cfg: Arc<Config>) -> Self {
+        Self::empty(ev, spotify, cfg, None, None)
+    }
+
+    fn empty(
+        ev: &EventManager,
+        spotify: Spotify,
+        cfg: Arc<Config>,
+        user_id: Option<String>,
+        display_name: Option<String>,
+    ) -> Self {
+        Self {
             tracks: Arc::new(RwLock::new(Vec::new())),
             albums: Arc::new(RwLock::new(Vec::new())),
             artists: Arc::new(RwLock::new(Vec::new())),
             playlists: Arc::new(RwLock::new(Vec::new())),
             shows: Arc::new(RwLock::new(Vec::new())),
+            history: Arc::new(RwLock::new(Vec::new())),
+            history_save_pending: Arc::new(AtomicBool::new(false)),
             is_done: Arc::new(RwLock::new(false)),
+            saved_track_ids: Arc::new(RwLock::new(HashSet::new())),
             user_id,
             display_name,
             ev: ev.clone(),
             spotify,
             cfg,
-        };
-
-        library.update_library();
-        library
+        }
     }
 
     pub fn playlists(&self) -> RwLockReadGuard<Vec<Playlist>> {
@@ -130,6 +175,30 @@ impl Library {
         store.len() - 1
     }
 
+    /// Puts `playable` on top of the history of recently played items, which
+    /// is saved in the background after `HISTORY_SAVE_DELAY`.
+    pub fn add_to_history(&self, playable: &Playable) {
+        let entry = HistoryEntry {
+            played_at: Utc::now(),
+            playable: playable.clone(),
+        };
+        {
+            let mut history = self.history.write().expect("can't writelock history");
+            history.insert(0, entry);
+            history.truncate(HISTORY_SIZE);
+        }
+
+        if self.history_save_pending.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let library = self.clone();
+        thread::spawn(move || {
+            thread::sleep(HISTORY_SAVE_DELAY);
+            library.history_save_pending.store(false, Ordering::SeqCst);
+            library.save_cache(config::cache_path(CACHE_HISTORY), library.history.clone());
+        });
+    }
+
     pub fn delete_playlist(&self, id: &str) {
         if !*self.is_done.read().unwrap() {
             return;
@@ -157,6 +226,11 @@ impl Library {
 
         self.fetch_playlists();
         self.save_cache(config::cache_path(CACHE_PLAYLISTS), self.playlists.clone());
+
+        let updated = self.playlists().iter().find(|p| p.id == id).cloned();
+        if let Some(playlist) = updated {
+            self.ev.send(Event::PlaylistChanged(playlist));
+        }
     }
 
     pub fn save_playlist(&self, name: &str, tracks: &[Playable]) {
@@ -177,6 +251,7 @@ impl Library {
                 thread::spawn(move || {
                     library.load_cache(config::cache_path(CACHE_TRACKS), library.tracks.clone());
                     library.fetch_tracks();
+                    library.index_saved_tracks();
                     library.save_cache(config::cache_path(CACHE_TRACKS), library.tracks.clone());
                 })
             };
@@ -230,10 +305,9 @@ impl Library {
             t_playlists.join().unwrap();
             t_shows.join().unwrap();
 
-            let mut is_done = library.is_done.write().unwrap();
-            *is_done = true;
+            *library.is_done.write().unwrap() = true;
 
-            library.ev.trigger();
+            library.ev.send(Event::LibraryLoaded);
         });
     }
 
@@ -527,6 +601,7 @@ impl Library {
             }
         }
         self.save_cache(config::cache_path(CACHE_PLAYLISTS), self.playlists.clone());
+        self.ev.send(Event::PlaylistChanged(updated.clone()));
     }
 
     pub fn is_saved_track(&self, track: &Playable) -> bool {
@@ -534,8 +609,31 @@ impl Library {
             return false;
         }
 
-        let tracks = self.tracks.read().unwrap();
-        tracks.iter().any(|t| t.id == track.id())
+        let ids = self.saved_track_ids.read().unwrap();
+        track.id().map_or(false, |id| ids.contains(&id))
+    }
+
+    /// Checks whether a track is saved without waiting for the library, returns
+    /// `None` if it is still loading or being updated.
+    #[cfg(feature = "mpris")]
+    pub fn try_is_saved_track(&self, track: &Playable) -> Option<bool> {
+        if !*self.is_done.try_read().ok()? {
+            return None;
+        }
+
+        let ids = self.saved_track_ids.try_read().ok()?;
+        Some(track.id().map_or(false, |id| ids.contains(&id)))
+    }
+
+    fn index_saved_tracks(&self) {
+        let ids = self
+            .tracks
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|t| t.id.clone())
+            .collect();
+        *self.saved_track_ids.write().unwrap() = ids;
     }
 
     pub fn save_tracks(&self, tracks: Vec<&Track>, api: bool) {
@@ -565,8 +663,12 @@ impl Library {
 
                 store.insert(i, track.clone());
                 i += 1;
+                self.ev.send(Event::LibraryChanged(LibraryChange::Added(
+                    track.uri.clone(),
+                )));
             }
         }
+        self.index_saved_tracks();
 
         self.populate_artists();
 
@@ -599,6 +701,12 @@ impl Library {
                 .cloned()
                 .collect();
         }
+        self.index_saved_tracks();
+        for track in tracks {
+            self.ev.send(Event::LibraryChanged(LibraryChange::Removed(
+                track.uri.clone(),
+            )));
+        }
 
         self.populate_artists();
 
@@ -640,6 +748,10 @@ impl Library {
                 store.sort_unstable_by_key(|a| format!("{}{}{}", a.artists[0], a.year, a.title));
             }
         }
+        if let Some(context) = album.playback_context() {
+            self.ev
+                .send(Event::LibraryChanged(LibraryChange::Added(context.uri)));
+        }
 
         self.save_cache(config::cache_path(CACHE_ALBUMS), self.albums.clone());
     }
@@ -664,6 +776,10 @@ impl Library {
             let mut store = self.albums.write().unwrap();
             *store = store.iter().filter(|a| a.id != album.id).cloned().collect();
         }
+        if let Some(context) = album.playback_context() {
+            self.ev
+                .send(Event::LibraryChanged(LibraryChange::Removed(context.uri)));
+        }
 
         self.save_cache(config::cache_path(CACHE_ALBUMS), self.albums.clone());
     }
@@ -801,6 +917,9 @@ impl Library {
                     store.insert(0, show.clone());
                 }
             }
+            self.ev.send(Event::LibraryChanged(LibraryChange::Added(
+                show.uri.clone(),
+            )));
         }
     }
 
@@ -814,6 +933,9 @@ impl Library {
                 let mut store = self.shows.write().unwrap();
                 *store = store.iter().filter(|s| s.id != show.id).cloned().collect();
             }
+            self.ev.send(Event::LibraryChanged(LibraryChange::Removed(
+                show.uri.clone(),
+            )));
         }
     }
 
@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::Path;
+
+use crate::model::playable::Playable;
+
+/// Formats `items` as an extended M3U playlist with their Spotify URIs as
+/// entries, preceded by their duration, artists and title.
+fn format(items: &[Playable]) -> String {
+    let mut playlist = String::from("#EXTM3U\n");
+    for item in items {
+        let (artists, title) = match item {
+            Playable::Track(track) => (track.artists.join(", "), track.title.clone()),
+            Playable::Episode(episode) => (
+                episode.show_name.clone().unwrap_or_default(),
+                episode.name.clone(),
+            ),
+        };
+        let info = match artists.is_empty() {
+            true => title,
+            false => format!("{} - {}", artists, title),
+        };
+        // entries have to stay on a single line
+        let info = info.replace(['\r', '\n'], " ");
+        playlist.push_str(&format!(
+            "#EXTINF:{},{}\n{}\n",
+            item.duration() / 1000,
+            info,
+            item.uri()
+        ));
+    }
+    playlist
+}
+
+pub fn write<P: AsRef<Path>>(path: P, items: &[Playable]) -> Result<(), String> {
+    fs::write(path.as_ref(), format(items)).map_err(|e| {
+        format!(
+            "Failed writing playlist to {}: {}",
+            path.as_ref().display(),
+            e
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+    use crate::model::episode::Episode;
+    use crate::model::playable::Playable;
+    use crate::model::track::Track;
+
+    #[test]
+    fn test_format() {
+        let track = Playable::Track(Track {
+            title: "Never Gonna Give\nYou Up".into(),
+            duration: 213_573,
+            artists: vec!["Rick Astley".into(), "Someone, Else".into()],
+            ..Track::with_id("4uLU6hMCjMI75M1A2tKUQC")
+        });
+        let episode = Playable::Episode(Episode {
+            id: "512ojhOuo1ktJprKbVcKyQ".into(),
+            uri: "spotify:episode:512ojhOuo1ktJprKbVcKyQ".into(),
+            duration: 60_000,
+            name: "Episode".into(),
+            description: String::new(),
+            release_date: String::new(),
+            show_name: None,
+            show_publisher: None,
+            show_id: None,
+            cover_url: None,
+            added_at: None,
+            list_index: 0,
+        });
+
+        assert_eq!(format(&[]), "#EXTM3U\n");
+        assert_eq!(
+            format(&[track, episode]),
+            "#EXTM3U\n\
+             #EXTINF:213,Rick Astley, Someone, Else - Never Gonna Give You Up\n\
+             spotify:track:4uLU6hMCjMI75M1A2tKUQC\n\
+             #EXTINF:60,Episode\n\
+             spotify:episode:512ojhOuo1ktJprKbVcKyQ\n"
+        );
+    }
+}
@@ -22,13 +22,18 @@ use log::{error, info, trace};
 use signal_hook::{consts::SIGHUP, consts::SIGTERM, iterator::Signals};
 
 mod authentication;
+mod cache;
 mod command;
 mod commands;
 mod config;
+mod crossfade;
 mod events;
 mod ext_traits;
+mod fuzzy;
 mod library;
+mod m3u;
 mod model;
+mod playback_rate;
 mod queue;
 mod serialization;
 mod sharing;
@@ -50,6 +55,7 @@ use crate::config::Config;
 use crate::events::{Event, EventManager};
 use crate::ext_traits::CursiveExt;
 use crate::library::Library;
+use crate::queue::QueueEvent;
 use crate::spotify::PlayerEvent;
 use crate::ui::contextmenu::ContextMenu;
 
@@ -129,6 +135,12 @@ async fn main() -> Result<(), String> {
                 .help("Filename of config file in basepath")
                 .default_value("config.toml"),
         )
+        .arg(
+            Arg::new("mpris-name")
+                .long("mpris-name")
+                .value_name("NAME")
+                .help("Suffix of the MPRIS bus name, to tell several instances apart"),
+        )
         .get_matches();
 
     if let Some(filename) = matches.get_one::<String>("debug") {
@@ -185,17 +197,27 @@ async fn main() -> Result<(), String> {
     let library = Arc::new(Library::new(&event_manager, spotify.clone(), cfg.clone()));
 
     let queue = Arc::new(queue::Queue::new(
+        &event_manager,
         spotify.clone(),
         cfg.clone(),
         library.clone(),
     ));
 
+    if cfg.values().restore_queue.unwrap_or(true) {
+        let queue = queue.clone();
+        std::thread::spawn(move || queue.remove_unavailable());
+    }
+
+    cache::start_eviction(cfg.clone(), spotify.clone());
+
     #[cfg(feature = "mpris")]
     let mpris_manager = Arc::new(mpris::MprisManager::new(
         event_manager.clone(),
         spotify.clone(),
         queue.clone(),
         library.clone(),
+        cfg.clone(),
+        matches.get_one::<String>("mpris-name").cloned(),
     ));
 
     let mut cmd_manager = CommandManager::new(
@@ -204,6 +226,8 @@ async fn main() -> Result<(), String> {
         library.clone(),
         cfg.clone(),
         event_manager.clone(),
+        #[cfg(feature = "mpris")]
+        mpris_manager.clone(),
     );
 
     cmd_manager.register_all();
@@ -223,7 +247,7 @@ async fn main() -> Result<(), String> {
 
     let status = ui::statusbar::StatusBar::new(queue.clone(), library);
 
-    let mut layout = ui::layout::Layout::new(status, &event_manager, theme)
+    let mut layout = ui::layout::Layout::new(status, &event_manager, theme, cfg.clone())
         .screen("search", search.with_name("search"))
         .screen("library", libraryview.with_name("library"))
         .screen("queue", queueview);
@@ -284,6 +308,13 @@ async fn main() -> Result<(), String> {
                 v.clear_cmdline();
             }
         });
+        // jump to matches while the query is typed
+        if let Some(query) = cmd.strip_prefix('/').filter(|query| !query.is_empty()) {
+            if let Some(data) = s.user_data::<UserData>().cloned() {
+                data.cmd
+                    .handle(s, Command::Jump(JumpMode::Query(query.to_string())));
+            }
+        }
     });
 
     {
@@ -345,8 +376,76 @@ async fn main() -> Result<(), String> {
                     }
                 }
                 Event::Queue(event) => {
+                    if let QueueEvent::TrackUnavailable(uri) = &event {
+                        let message = format!("Skipped unavailable track {}", uri);
+                        cursive.call_on_name("main", |v: &mut ui::layout::Layout| {
+                            v.set_result(Err(message));
+                        });
+                    }
+                    if let QueueEvent::ImportFinished { imported, skipped } = &event {
+                        let result = match skipped.len() {
+                            0 => Ok(Some(format!("Imported {} items", imported))),
+                            _ => Err(format!(
+                                "Imported {} items, skipped {} invalid lines: {}",
+                                imported,
+                                skipped.len(),
+                                skipped
+                                    .iter()
+                                    .map(usize::to_string)
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            )),
+                        };
+                        cursive.call_on_name("main", |v: &mut ui::layout::Layout| {
+                            v.set_result(result);
+                        });
+                    }
                     queue.handle_event(event);
                 }
+                #[cfg_attr(not(feature = "mpris"), allow(unused_variables))]
+                Event::QueueChanged(change) => {
+                    #[cfg(feature = "mpris")]
+                    mpris_manager.update_tracklist(change);
+                }
+                #[cfg_attr(not(feature = "mpris"), allow(unused_variables))]
+                Event::Seeked(position_ms) => {
+                    #[cfg(feature = "mpris")]
+                    mpris_manager.seeked(position_ms);
+                }
+                Event::VolumeChanged | Event::PlaybackModeChanged => {
+                    #[cfg(feature = "mpris")]
+                    mpris_manager.update();
+                }
+                #[cfg(feature = "mpris")]
+                Event::Fullscreen(fullscreen) => {
+                    cursive.call_on_name("main", |v: &mut ui::layout::Layout| {
+                        v.set_fullscreen(fullscreen);
+                    });
+                }
+                #[cfg_attr(not(feature = "mpris"), allow(unused_variables))]
+                Event::ScreenChanged(screen) => {
+                    #[cfg(feature = "mpris")]
+                    mpris_manager.screen_changed(&screen);
+                }
+                Event::LibraryLoaded => {
+                    #[cfg(feature = "mpris")]
+                    mpris_manager.refresh_metadata();
+                }
+                #[cfg_attr(not(feature = "mpris"), allow(unused_variables))]
+                Event::LibraryChanged(change) => {
+                    #[cfg(feature = "mpris")]
+                    mpris_manager.library_changed(change);
+                }
+                #[cfg_attr(not(feature = "mpris"), allow(unused_variables))]
+                Event::PlaylistChanged(playlist) => {
+                    #[cfg(feature = "mpris")]
+                    mpris_manager.playlist_changed(playlist);
+                }
+                Event::Command(command) => {
+                    if let Some(data) = cursive.user_data::<UserData>().cloned() {
+                        data.cmd.handle(&mut cursive, command);
+                    }
+                }
                 Event::SessionDied => spotify.start_worker(None),
             }
         }
@@ -11,7 +11,7 @@ use crate::library::Library;
 use crate::model::artist::Artist;
 use crate::model::playable::Playable;
 use crate::model::track::Track;
-use crate::queue::Queue;
+use crate::queue::{PlaybackContext, Queue};
 use crate::spotify::Spotify;
 use crate::traits::{IntoBoxedViewExt, ListItem, ViewExt};
 use crate::ui::{album::AlbumView, listview::ListView};
@@ -31,6 +31,13 @@ pub struct Album {
 }
 
 impl Album {
+    pub fn playback_context(&self) -> Option<PlaybackContext> {
+        self.id.as_ref().map(|id| PlaybackContext {
+            name: self.title.clone(),
+            uri: format!("spotify:album:{}", id),
+        })
+    }
+
     pub fn load_all_tracks(&mut self, spotify: Spotify) {
         if self.tracks.is_some() && self.tracks.as_ref().map(|t| t.len()) == self.total_tracks {
             return;
@@ -194,6 +201,7 @@ impl ListItem for Album {
                 .iter()
                 .map(|track| Playable::Track(track.clone()))
                 .collect();
+            queue.set_context(self.playback_context());
             let index = queue.append_next(&tracks);
             queue.play(index, true, true);
         }
@@ -1,13 +1,17 @@
 use std::fmt;
 use std::sync::{Arc, RwLock};
+use std::thread;
 
+use log::{debug, info};
 use rspotify::model::artist::{FullArtist, SimplifiedArtist};
-use rspotify::model::Id;
+use rspotify::model::{AlbumType, Id};
 
+use crate::command::AlbumGroup;
 use crate::library::Library;
+use crate::model::album::Album;
 use crate::model::playable::Playable;
 use crate::model::track::Track;
-use crate::queue::Queue;
+use crate::queue::{PlaybackContext, Queue};
 use crate::spotify::Spotify;
 use crate::traits::{IntoBoxedViewExt, ListItem, ViewExt};
 use crate::ui::{artist::ArtistView, listview::ListView};
@@ -32,6 +36,61 @@ impl Artist {
         }
     }
 
+    pub fn playback_context(&self) -> Option<PlaybackContext> {
+        self.id.as_ref().map(|id| PlaybackContext {
+            name: self.name.clone(),
+            uri: format!("spotify:artist:{}", id),
+        })
+    }
+
+    /// Tracks similar to the ones of this artist, i.e. the artist's radio.
+    pub fn recommendations(&self, spotify: Spotify) -> Option<Vec<Track>> {
+        let id = self.id.as_ref()?;
+        spotify
+            .api
+            .recommendations(Some(vec![id]), None, None)
+            .map(|r| r.tracks.iter().map(Track::from).collect())
+    }
+
+    /// Appends the tracks of all releases of the artist in `group`, or of its
+    /// albums and singles, to the queue in chronological order. Releases are
+    /// loaded in the background and appended one at a time, each in a batch.
+    pub fn queue_discography(artist_id: &str, group: Option<AlbumGroup>, queue: Arc<Queue>) {
+        let album_types = match group {
+            Some(AlbumGroup::Albums) => vec![AlbumType::Album],
+            Some(AlbumGroup::Singles) => vec![AlbumType::Single],
+            Some(AlbumGroup::AppearsOn) => vec![AlbumType::AppearsOn],
+            Some(AlbumGroup::Compilations) => vec![AlbumType::Compilation],
+            None => vec![AlbumType::Album, AlbumType::Single],
+        };
+        let artist_id = artist_id.to_string();
+        thread::spawn(move || {
+            let spotify = queue.get_spotify();
+            let mut albums: Vec<Album> = Vec::new();
+            for album_type in album_types {
+                let result = spotify.api.artist_albums(&artist_id, Some(album_type));
+                while result.next().is_some() {}
+                let mut items = result.items.read().unwrap().clone();
+                // the newest releases come first
+                items.reverse();
+                albums.extend(items);
+            }
+            albums.sort_by(|a, b| a.year.cmp(&b.year));
+            info!("queueing {} releases of artist {}", albums.len(), artist_id);
+
+            for mut album in albums {
+                album.load_all_tracks(spotify.clone());
+                let tracks = album.tracks.unwrap_or_default();
+                debug!("queueing {} tracks of {}", tracks.len(), album.title);
+                queue.batch(|| {
+                    for track in tracks {
+                        queue.append(Playable::Track(track));
+                    }
+                });
+            }
+        });
+    }
+
     fn load_top_tracks(&mut self, spotify: Spotify) {
         if let Some(artist_id) = &self.id {
             if self.tracks.is_none() {
@@ -126,6 +185,7 @@ impl ListItem for Artist {
                 .iter()
                 .map(|track| Playable::Track(track.clone()))
                 .collect();
+            queue.set_context(self.playback_context());
             let index = queue.append_next(&tracks);
             queue.play(index, true, true);
         }
@@ -176,14 +236,7 @@ impl ListItem for Artist {
         queue: Arc<Queue>,
         library: Arc<Library>,
     ) -> Option<Box<dyn ViewExt>> {
-        let id = self.id.as_ref()?.to_string();
-
-        let spotify = queue.get_spotify();
-        let recommendations: Option<Vec<Track>> = spotify
-            .api
-            .recommendations(Some(vec![&id]), None, None)
-            .map(|r| r.tracks)
-            .map(|tracks| tracks.iter().map(Track::from).collect());
+        let recommendations = self.recommendations(queue.get_spotify());
 
         recommendations.map(|tracks| {
             ListView::new(
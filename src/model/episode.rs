@@ -1,8 +1,11 @@
 use crate::library::Library;
 use crate::model::playable::Playable;
+use crate::model::show::Show;
 use crate::queue::Queue;
 use crate::traits::{ListItem, ViewExt};
 use chrono::{DateTime, Utc};
+#[cfg(any(feature = "mpris", test))]
+use regex::Regex;
 use rspotify::model::show::{FullEpisode, SimplifiedEpisode};
 use rspotify::model::Id;
 use std::fmt;
@@ -16,6 +19,11 @@ pub struct Episode {
     pub name: String,
     pub description: String,
     pub release_date: String,
+    pub show_name: Option<String>,
+    pub show_publisher: Option<String>,
+    /// Missing from older caches, and from episodes listed without their show
+    #[serde(default)]
+    pub show_id: Option<String>,
     pub cover_url: Option<String>,
     pub added_at: Option<DateTime<Utc>>,
     pub list_index: usize,
@@ -27,6 +35,40 @@ impl Episode {
         let seconds = (self.duration / 1000) % 60;
         format!("{:02}:{:02}", minutes, seconds)
     }
+
+    /// Chapters listed in the description, as their start in milliseconds and
+    /// title.
+    #[cfg(feature = "mpris")]
+    pub fn chapters(&self) -> Vec<(u32, String)> {
+        parse_chapters(&self.description, self.duration)
+    }
+}
+
+/// Parses chapter markers from lines starting with a timestamp, e.g.
+/// `12:34 - Topic` or `(1:02:03) Topic`. Markers have to be in ascending order
+/// and within the episode, otherwise the timestamps are unlikely to be chapters.
+#[cfg(any(feature = "mpris", test))]
+fn parse_chapters(description: &str, duration: u32) -> Vec<(u32, String)> {
+    lazy_static! {
+        static ref CHAPTER: Regex = Regex::new(
+            r"(?m)^[ \t]*\(?((?:\d{1,2}:)?\d{1,2}:\d{2})\)?[ \t]*(?:[-–—|:][ \t]*)?(\S.*?)\s*$"
+        )
+        .unwrap();
+    }
+
+    let mut chapters: Vec<(u32, String)> = Vec::new();
+    for captures in CHAPTER.captures_iter(description) {
+        let position = captures[1]
+            .split(':')
+            .filter_map(|part| part.parse::<u32>().ok())
+            .fold(0, |acc, part| acc * 60 + part)
+            * 1000;
+        if position >= duration || chapters.last().map_or(false, |(last, _)| position <= *last) {
+            return Vec::new();
+        }
+        chapters.push((position, captures[2].to_string()));
+    }
+    chapters
 }
 
 impl From<&SimplifiedEpisode> for Episode {
@@ -38,6 +80,9 @@ impl From<&SimplifiedEpisode> for Episode {
             name: episode.name.clone(),
             description: episode.description.clone(),
             release_date: episode.release_date.clone(),
+            show_name: None,
+            show_publisher: None,
+            show_id: None,
             cover_url: episode.images.get(0).map(|img| img.url.clone()),
             added_at: None,
             list_index: 0,
@@ -54,6 +99,9 @@ impl From<&FullEpisode> for Episode {
             name: episode.name.clone(),
             description: episode.description.clone(),
             release_date: episode.release_date.clone(),
+            show_name: Some(episode.show.name.clone()),
+            show_publisher: Some(episode.show.publisher.clone()),
+            show_id: Some(episode.show.id.id().to_string()),
             cover_url: episode.images.get(0).map(|img| img.url.clone()),
             added_at: None,
             list_index: 0,
@@ -110,6 +158,15 @@ impl ListItem for Episode {
         Some(format!("https://open.spotify.com/episode/{}", self.id))
     }
 
+    fn show(&self, queue: Arc<Queue>) -> Option<Show> {
+        let spotify = queue.get_spotify();
+
+        match self.show_id {
+            Some(ref show_id) => spotify.api.get_show(show_id).map(|ref fs| fs.into()),
+            None => None,
+        }
+    }
+
     #[inline]
     fn is_playable(&self) -> bool {
         true
@@ -119,3 +176,27 @@ impl ListItem for Episode {
         Box::new(self.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parse_chapters;
+
+    #[test]
+    fn test_parse_chapters() {
+        let description =
+            "Today's episode\n\n00:00 Intro\n5:30 - First topic\n(1:02:03) Second topic\n";
+        assert_eq!(
+            parse_chapters(description, 2 * 60 * 60 * 1000),
+            vec![
+                (0, "Intro".to_string()),
+                (330_000, "First topic".to_string()),
+                (3_723_000, "Second topic".to_string()),
+            ]
+        );
+
+        // timestamps beyond the end or out of order aren't chapters
+        assert!(parse_chapters(description, 60 * 60 * 1000).is_empty());
+        assert!(parse_chapters("10:00 Later\n05:00 Earlier", 60 * 60 * 1000).is_empty());
+        assert!(parse_chapters("No chapters at 10:00 here", 60 * 60 * 1000).is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+use chrono::{DateTime, Utc};
+
+use crate::library::Library;
+use crate::model::album::Album;
+use crate::model::artist::Artist;
+use crate::model::playable::Playable;
+use crate::model::show::Show;
+use crate::model::track::Track;
+use crate::queue::Queue;
+use crate::traits::{ListItem, ViewExt};
+use std::sync::Arc;
+
+/// An item of the history of recently played items. The time it was played
+/// is kept apart from the item, whose `added_at` is when it was saved.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub played_at: DateTime<Utc>,
+    pub playable: Playable,
+}
+
+impl ListItem for HistoryEntry {
+    fn is_playing(&self, queue: Arc<Queue>) -> bool {
+        self.playable.is_playing(queue)
+    }
+
+    fn display_left(&self, library: Arc<Library>) -> String {
+        self.playable.display_left(library)
+    }
+
+    fn display_center(&self, library: Arc<Library>) -> String {
+        self.playable.display_center(library)
+    }
+
+    fn display_right(&self, library: Arc<Library>) -> String {
+        self.playable.display_right(library)
+    }
+
+    fn search_text(&self, library: Arc<Library>) -> String {
+        self.playable.search_text(library)
+    }
+
+    fn play(&mut self, queue: Arc<Queue>) {
+        self.playable.play(queue)
+    }
+
+    fn play_next(&mut self, queue: Arc<Queue>) {
+        self.playable.play_next(queue)
+    }
+
+    fn queue(&mut self, queue: Arc<Queue>) {
+        self.playable.queue(queue)
+    }
+
+    fn toggle_saved(&mut self, library: Arc<Library>) {
+        self.playable.toggle_saved(library)
+    }
+
+    fn save(&mut self, library: Arc<Library>) {
+        self.playable.save(library)
+    }
+
+    fn unsave(&mut self, library: Arc<Library>) {
+        self.playable.unsave(library)
+    }
+
+    fn open(&self, queue: Arc<Queue>, library: Arc<Library>) -> Option<Box<dyn ViewExt>> {
+        self.playable.open(queue, library)
+    }
+
+    fn share_url(&self) -> Option<String> {
+        self.playable.share_url()
+    }
+
+    fn album(&self, queue: Arc<Queue>) -> Option<Album> {
+        self.playable.album(queue)
+    }
+
+    fn artists(&self) -> Option<Vec<Artist>> {
+        self.playable.artists()
+    }
+
+    fn show(&self, queue: Arc<Queue>) -> Option<Show> {
+        self.playable.show(queue)
+    }
+
+    fn track(&self) -> Option<Track> {
+        self.playable.track()
+    }
+
+    fn as_listitem(&self) -> Box<dyn ListItem> {
+        self.playable.as_listitem()
+    }
+}
@@ -5,6 +5,7 @@ use crate::library::Library;
 use crate::model::album::Album;
 use crate::model::artist::Artist;
 use crate::model::episode::Episode;
+use crate::model::show::Show;
 use crate::model::track::Track;
 use crate::queue::Queue;
 use crate::traits::{ListItem, ViewExt};
@@ -167,6 +168,10 @@ impl ListItem for Playable {
         self.as_listitem().display_right(library)
     }
 
+    fn search_text(&self, library: Arc<Library>) -> String {
+        self.as_listitem().search_text(library)
+    }
+
     fn play(&mut self, queue: Arc<Queue>) {
         self.as_listitem().play(queue)
     }
@@ -207,6 +212,10 @@ impl ListItem for Playable {
         self.as_listitem().artists()
     }
 
+    fn show(&self, queue: Arc<Queue>) -> Option<Show> {
+        self.as_listitem().show(queue)
+    }
+
     fn track(&self) -> Option<Track> {
         self.as_listitem().track()
     }
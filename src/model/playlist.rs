@@ -10,7 +10,7 @@ use rspotify::model::Id;
 
 use crate::model::playable::Playable;
 use crate::model::track::Track;
-use crate::queue::Queue;
+use crate::queue::{PlaybackContext, Queue};
 use crate::spotify::Spotify;
 use crate::traits::{IntoBoxedViewExt, ListItem, ViewExt};
 use crate::ui::{listview::ListView, playlist::PlaylistView};
@@ -29,6 +29,13 @@ pub struct Playlist {
 }
 
 impl Playlist {
+    pub fn playback_context(&self) -> PlaybackContext {
+        PlaybackContext {
+            name: self.name.clone(),
+            uri: format!("spotify:playlist:{}", self.id),
+        }
+    }
+
     pub fn load_tracks(&mut self, spotify: Spotify) {
         if self.tracks.is_some() {
             return;
@@ -223,6 +230,7 @@ impl ListItem for Playlist {
         self.load_tracks(queue.get_spotify());
 
         if let Some(tracks) = &self.tracks {
+            queue.set_context(Some(self.playback_context()));
             let index = queue.append_next(tracks);
             queue.play(index, true, true);
         }
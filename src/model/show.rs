@@ -1,7 +1,8 @@
+use crate::config::ShowPlayOrder;
 use crate::library::Library;
 use crate::model::episode::Episode;
 use crate::model::playable::Playable;
-use crate::queue::Queue;
+use crate::queue::{PlaybackContext, Queue};
 use crate::spotify::Spotify;
 use crate::traits::{IntoBoxedViewExt, ListItem, ViewExt};
 use crate::ui::show::ShowView;
@@ -22,6 +23,13 @@ pub struct Show {
 }
 
 impl Show {
+    pub fn playback_context(&self) -> PlaybackContext {
+        PlaybackContext {
+            name: self.name.clone(),
+            uri: self.uri.clone(),
+        }
+    }
+
     pub fn load_all_episodes(&mut self, spotify: Spotify) {
         if self.episodes.is_some() {
             return;
@@ -35,6 +43,17 @@ impl Show {
         let episodes = episodes_result.items.read().unwrap().clone();
         self.episodes = Some(episodes);
     }
+
+    /// The loaded episodes in the order they are played in. Spotify lists them
+    /// newest first.
+    pub fn playables(&self, order: ShowPlayOrder) -> Vec<Playable> {
+        let episodes = self.episodes.iter().flatten();
+        let playables = episodes.map(|ep| Playable::Episode(ep.clone()));
+        match order {
+            ShowPlayOrder::NewestFirst => playables.collect(),
+            ShowPlayOrder::OldestFirst => playables.rev().collect(),
+        }
+    }
 }
 
 impl From<&SimplifiedShow> for Show {
@@ -96,14 +115,9 @@ impl ListItem for Show {
     fn play(&mut self, queue: Arc<Queue>) {
         self.load_all_episodes(queue.get_spotify());
 
-        let playables = self
-            .episodes
-            .as_ref()
-            .unwrap_or(&Vec::new())
-            .iter()
-            .map(|ep| Playable::Episode(ep.clone()))
-            .collect();
+        let playables = self.playables(queue.show_play_order(ShowPlayOrder::NewestFirst));
 
+        queue.set_context(Some(self.playback_context()));
         let index = queue.append_next(&playables);
         queue.play(index, true, true);
     }
@@ -111,18 +125,18 @@ impl ListItem for Show {
     fn play_next(&mut self, queue: Arc<Queue>) {
         self.load_all_episodes(queue.get_spotify());
 
-        if let Some(episodes) = self.episodes.as_ref() {
-            for ep in episodes.iter().rev() {
-                queue.insert_after_current(Playable::Episode(ep.clone()));
-            }
+        let order = queue.show_play_order(ShowPlayOrder::NewestFirst);
+        for playable in self.playables(order).into_iter().rev() {
+            queue.insert_after_current(playable);
         }
     }
 
     fn queue(&mut self, queue: Arc<Queue>) {
         self.load_all_episodes(queue.get_spotify());
 
-        for ep in self.episodes.as_ref().unwrap_or(&Vec::new()) {
-            queue.append(Playable::Episode(ep.clone()));
+        let order = queue.show_play_order(ShowPlayOrder::NewestFirst);
+        for playable in self.playables(order) {
+            queue.append(playable);
         }
     }
 
@@ -28,8 +28,11 @@ pub struct Track {
     pub album: Option<String>,
     pub album_id: Option<String>,
     pub album_artists: Vec<String>,
+    pub album_release_date: Option<String>,
     pub cover_url: Option<String>,
     pub url: String,
+    /// Popularity from 0 to 100, only known for tracks fetched in full
+    pub popularity: Option<u32>,
     pub added_at: Option<DateTime<Utc>>,
     pub list_index: usize,
 }
@@ -64,8 +67,10 @@ impl Track {
             album: Some(album.name.clone()),
             album_id: Some(album.id.id().to_string()),
             album_artists,
+            album_release_date: Some(album.release_date.clone()),
             cover_url: album.images.get(0).map(|img| img.url.clone()),
             url: track.id.as_ref().map(|id| id.url()).unwrap_or_default(),
+            popularity: None,
             added_at: None,
             list_index: 0,
         }
@@ -103,8 +108,10 @@ impl From<&SimplifiedTrack> for Track {
             album: None,
             album_id: None,
             album_artists: Vec::new(),
+            album_release_date: None,
             cover_url: None,
             url: track.id.as_ref().map(|id| id.url()).unwrap_or_default(),
+            popularity: None,
             added_at: None,
             list_index: 0,
         }
@@ -142,8 +149,10 @@ impl From<&FullTrack> for Track {
             album: Some(track.album.name.clone()),
             album_id: track.album.id.as_ref().map(|a| a.id().to_string()),
             album_artists,
+            album_release_date: track.album.release_date.clone(),
             cover_url: track.album.images.get(0).map(|img| img.url.clone()),
             url: track.id.as_ref().map(|id| id.url()).unwrap_or_default(),
+            popularity: Some(track.popularity),
             added_at: None,
             list_index: 0,
         }
@@ -158,6 +167,32 @@ impl From<&SavedTrack> for Track {
     }
 }
 
+#[cfg(test)]
+impl Track {
+    /// A track known by nothing but its id, for tests to fill in the rest of.
+    pub fn with_id(id: &str) -> Track {
+        Track {
+            id: Some(id.to_string()),
+            uri: format!("spotify:track:{}", id),
+            title: id.to_string(),
+            track_number: 1,
+            disc_number: 1,
+            duration: 213_000,
+            artists: Vec::new(),
+            artist_ids: Vec::new(),
+            album: None,
+            album_id: None,
+            album_artists: Vec::new(),
+            album_release_date: None,
+            cover_url: None,
+            url: String::new(),
+            popularity: None,
+            added_at: None,
+            list_index: 0,
+        }
+    }
+}
+
 impl fmt::Display for Track {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{} - {}", self.artists.join(", "), self.title)
@@ -239,6 +274,10 @@ impl ListItem for Track {
         }
     }
 
+    fn search_text(&self, _library: Arc<Library>) -> String {
+        format!("{} - {}", self.artists.join(", "), self.title)
+    }
+
     fn play(&mut self, queue: Arc<Queue>) {
         let index = queue.append_next(&vec![Playable::Track(self.clone())]);
         queue.play(index, true, false);
@@ -1,763 +1,5055 @@
-use std::collections::HashMap;
-use std::rc::Rc;
-use std::sync::{mpsc, Arc};
-use std::time::Duration;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use dbus::arg::{RefArg, Variant};
+use chrono::{DateTime, Datelike, SecondsFormat, Utc};
+use dbus::arg::{ArgType, RefArg, Variant};
 use dbus::ffidisp::stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged;
-use dbus::message::SignalArgs;
+use dbus::message::{MessageType, SignalArgs};
 use dbus::strings::Path;
-use dbus_tree::{Access, Factory};
-use log::{debug, warn};
+use dbus::Message;
+use dbus_tree::{Access, Factory, MethodErr};
+use log::{debug, error, info, warn};
 
-use crate::events::EventManager;
-use crate::library::Library;
+use crate::command::Command;
+use crate::config::Config;
+use crate::events::{Event, EventManager};
+use crate::library::{Library, LibraryChange};
 use crate::model::album::Album;
-use crate::model::episode::Episode;
+use crate::model::artist::Artist;
+use crate::model::history::HistoryEntry;
 use crate::model::playable::Playable;
 use crate::model::playlist::Playlist;
-use crate::model::show::Show;
 use crate::model::track::Track;
-use crate::queue::{Queue, RepeatSetting};
+use crate::playback_rate::{MAX_PLAYBACK_RATE, MIN_PLAYBACK_RATE};
+use crate::queue::{seek_target, PlaybackContext, Queue, QueueChange, RepeatSetting};
 use crate::spotify::{PlayerEvent, Spotify, UriType, VOLUME_PERCENT};
 use crate::traits::ListItem;
+use crate::ui::pagination::ApiResult;
+use crate::utils::copy_to_clipboard;
 use regex::Regex;
+use url::Url;
 
 type Metadata = HashMap<String, Variant<Box<dyn RefArg>>>;
 
-struct MprisState(String, Option<Playable>);
+const TRACKLIST_NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
 
-fn get_playbackstatus(spotify: Spotify) -> String {
-    match spotify.get_current_status() {
-        PlayerEvent::Playing(_) | PlayerEvent::FinishedTrack => "Playing",
+/// Upper bound for the delay between attempts to reconnect to the session bus
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How long to wait for the server thread to stop before leaving it behind
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Number of playables kept in the metadata cache if not configured otherwise
+const DEFAULT_METADATA_CACHE_SIZE: usize = 50;
+
+/// Interval in milliseconds to wait for dbus messages and commands for while playing
+const PLAYING_POLL_INTERVAL_MS: u32 = 200;
+
+/// Interval in milliseconds to wait for while not playing, if not configured otherwise
+const DEFAULT_IDLE_POLL_INTERVAL_MS: u32 = 1000;
+
+/// Small least recently used cache, used to keep the details fetched by
+/// `get_metadata` around so that clients polling `Metadata` don't cause an API
+/// request every time.
+struct LruCache<T> {
+    capacity: usize,
+    entries: VecDeque<(String, T)>,
+}
+
+impl<T: Clone> LruCache<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<T> {
+        let index = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(index)?;
+        let value = entry.1.clone();
+        self.entries.push_front(entry);
+        Some(value)
+    }
+
+    fn insert(&mut self, key: String, value: T) {
+        self.remove(&key);
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.truncate(self.capacity - 1);
+        self.entries.push_front((key, value));
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.entries.retain(|(k, _)| k != key);
+    }
+}
+
+/// Details fetched by `get_metadata`, by the id of the track or episode. Audio
+/// features that could not be fetched are cached as `None`, to not request
+/// them again.
+struct MetadataCache {
+    playables: LruCache<Playable>,
+    features: LruCache<Option<TrackFeatures>>,
+}
+
+impl MetadataCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            playables: LruCache::new(capacity),
+            features: LruCache::new(capacity),
+        }
+    }
+
+    fn remove(&mut self, id: &str) {
+        self.playables.remove(id);
+        self.features.remove(id);
+    }
+}
+
+type PlayableCache = Mutex<MetadataCache>;
+
+/// Items need to be listened to for half their duration, but at most this long,
+/// to be scrobbled
+const MAX_SCROBBLE_THRESHOLD: Duration = Duration::from_secs(4 * 60);
+
+/// Items that are shorter than this are never scrobbled
+const MIN_SCROBBLE_DURATION: Duration = Duration::from_secs(30);
+
+/// Progress below which jumping back to the start of an item that has been
+/// scrobbled counts as playing it again, e.g. when it is on repeat
+const REPLAY_PROGRESS: Duration = Duration::from_secs(2);
+
+/// Maximum number of characters of an episode description to report
+const MAX_COMMENT_LENGTH: usize = 500;
+
+/// Snapshot of the player state that is broadcast via `PropertiesChanged`.
+struct MprisState {
+    status: String,
+    playable: Option<Playable>,
+    /// Id of the queue entry of `playable`
+    entry_id: Option<u64>,
+    /// Volume between 0.0 and 1.0
+    volume: f64,
+    /// Playback rate, 1.0 at normal speed
+    rate: f64,
+    repeat: RepeatSetting,
+    shuffle: bool,
+    can_go_next: bool,
+    can_go_previous: bool,
+    controls: Controls,
+    /// Reported through the `org.ncspot` and `Playlists` interfaces, not part of
+    /// `changed_properties`
+    context: Option<PlaybackContext>,
+    /// Number of items in the queue and the duration in milliseconds of those
+    /// after the current one, reported through the `org.ncspot` interface
+    queue_length: usize,
+    upcoming_ms: u64,
+    /// Reported through the `org.ncspot` interface
+    crossfade_ms: u32,
+    /// Name of the audio device, reported through the `org.ncspot` interface
+    device: String,
+}
+
+impl MprisState {
+    /// Returns the names of the `Player` properties that differ from the
+    /// `previous` state, or all of them if nothing has been sent yet.
+    fn changed_properties(&self, previous: Option<&MprisState>) -> Vec<&'static str> {
+        let previous = match previous {
+            Some(previous) => previous,
+            None => {
+                return vec![
+                    "Metadata",
+                    "PlaybackStatus",
+                    "Volume",
+                    "Rate",
+                    "LoopStatus",
+                    "Shuffle",
+                    "CanGoNext",
+                    "CanGoPrevious",
+                    "CanControl",
+                    "CanPlay",
+                    "CanPause",
+                    "CanSeek",
+                ]
+            }
+        };
+
+        let mut changed = Vec::new();
+        if self.playable.as_ref().map(Playable::uri)
+            != previous.playable.as_ref().map(Playable::uri)
+            || self.entry_id != previous.entry_id
+        {
+            changed.push("Metadata");
+        }
+        if self.status != previous.status {
+            changed.push("PlaybackStatus");
+        }
+        if self.volume != previous.volume {
+            changed.push("Volume");
+        }
+        if self.rate != previous.rate {
+            changed.push("Rate");
+        }
+        if self.repeat != previous.repeat {
+            changed.push("LoopStatus");
+        }
+        if self.shuffle != previous.shuffle {
+            changed.push("Shuffle");
+        }
+        if self.can_go_next != previous.can_go_next {
+            changed.push("CanGoNext");
+        }
+        if self.can_go_previous != previous.can_go_previous {
+            changed.push("CanGoPrevious");
+        }
+        if self.controls.control != previous.controls.control {
+            changed.push("CanControl");
+        }
+        if self.controls.play != previous.controls.play {
+            changed.push("CanPlay");
+        }
+        if self.controls.pause != previous.controls.pause {
+            changed.push("CanPause");
+        }
+        if self.controls.seek != previous.controls.seek {
+            changed.push("CanSeek");
+        }
+        changed
+    }
+}
+
+enum MprisCommand {
+    UpdateState(Box<MprisState>),
+    UpdateTrackList(QueueChange),
+    Seeked(u32),
+    /// The metadata of the current item changed without it being replaced
+    RefreshMetadata,
+    LibraryChanged(LibraryChange),
+    PlaylistChanged(Playlist),
+}
+
+/// Maps the queue's repeat setting to an MPRIS `LoopStatus` value.
+fn loop_status(repeat: RepeatSetting) -> &'static str {
+    match repeat {
+        RepeatSetting::None => "None",
+        RepeatSetting::RepeatTrack => "Track",
+        RepeatSetting::RepeatPlaylist => "Playlist",
+    }
+}
+
+/// Maps an MPRIS `LoopStatus` value to a repeat setting, or `None` if it is not valid.
+fn repeat_setting(loop_status: &str) -> Option<RepeatSetting> {
+    match loop_status {
+        "None" => Some(RepeatSetting::None),
+        "Track" => Some(RepeatSetting::RepeatTrack),
+        "Playlist" => Some(RepeatSetting::RepeatPlaylist),
+        _ => None,
+    }
+}
+
+/// Returns the position in milliseconds `offset_us` microseconds away from
+/// `position_ms`, clamped to the start, or `None` if it is past `duration_ms`.
+fn seek_position(position_ms: i64, offset_us: i64, duration_ms: u32) -> Option<u32> {
+    seek_target(position_ms, offset_us / 1000, duration_ms)
+}
+
+/// Maps the player volume to the MPRIS `Volume` range of 0.0 to 1.0.
+fn mpris_volume(volume: u16) -> f64 {
+    volume as f64 / u16::MAX as f64
+}
+
+/// Inverse of `mpris_volume`, rounding to the nearest player volume so that
+/// setting the value read from `Volume` doesn't change it.
+fn player_volume(volume: f64) -> u16 {
+    (volume.clamp(0.0, 1.0) * u16::MAX as f64).round() as u16
+}
+
+/// Changes `volume` by `percent` steps of `VOLUME_PERCENT`, staying within the
+/// valid range.
+fn step_volume(volume: u16, percent: i64) -> u16 {
+    let volume = (volume as i64).saturating_add(percent.saturating_mul(VOLUME_PERCENT as i64));
+    volume.clamp(0, u16::MAX as i64) as u16
+}
+
+/// Returns the shuffle setting to apply for a requested `Shuffle` value, or
+/// `None` if it is ignored because there is nothing to shuffle.
+fn shuffle_setting(requested: bool, queue_len: usize) -> Option<bool> {
+    (queue_len > 0).then_some(requested)
+}
+
+/// Applies a `LoopStatus` value set by a client.
+fn set_loop_status(queue: &Queue, loop_status: Option<&str>) {
+    // Repeat and shuffle are independent settings, so this must leave the
+    // shuffle state (and its random order) untouched.
+    match loop_status.and_then(repeat_setting) {
+        Some(setting) => queue.set_repeat(setting),
+        None => warn!("mpris: ignoring invalid LoopStatus"),
+    }
+}
+
+/// Applies a `Shuffle` value set by a client.
+fn set_shuffle(queue: &Queue, shuffle: Option<bool>) {
+    if let Some(shuffle) = shuffle {
+        match shuffle_setting(shuffle, queue.len()) {
+            Some(shuffle) => queue.set_shuffle(shuffle),
+            None => info!("mpris: ignoring Shuffle change, the queue is empty"),
+        }
+    }
+}
+
+/// Whether the queue is actually played in random order, which requires the
+/// order to have been generated in addition to the shuffle setting.
+fn is_shuffled(queue: &Queue) -> bool {
+    queue.get_shuffle() && queue.get_random_order().is_some()
+}
+
+/// Extracts the type and id from an `open.spotify.com` link.
+fn parse_open_spotify_url(url: &str) -> Option<(&str, &str)> {
+    lazy_static! {
+        static ref OPEN_SPOTIFY_URL: Regex = Regex::new(
+            r"https?://open\.spotify\.com(?:/intl-[\w-]+)?(/user/[^/\s]+)?(?:/embed)?/(album|track|playlist|show|episode)/([^/?#\s]+)"
+        )
+        .unwrap();
+    }
+
+    let captures = OPEN_SPOTIFY_URL.captures(url)?;
+    Some((captures.get(2)?.as_str(), captures.get(3)?.as_str()))
+}
+
+/// Rewrites legacy `spotify:user:USERNAME:playlist:ID` URIs to the modern
+/// `spotify:playlist:ID` form, other URIs are returned unchanged.
+fn normalize_uri(uri: &str) -> String {
+    lazy_static! {
+        static ref LEGACY_PLAYLIST_URI: Regex =
+            Regex::new(r"^spotify:user:[^:]+:playlist:([^:]+)$").unwrap();
+    }
+
+    LEGACY_PLAYLIST_URI
+        .replace(uri, "spotify:playlist:$1")
+        .into_owned()
+}
+
+/// Turns the URI or `open.spotify.com` link passed to `OpenUri` into a
+/// Spotify URI.
+fn spotify_uri(uri: &str) -> Option<String> {
+    if !uri.contains("open.spotify.com") {
+        return Some(normalize_uri(uri));
+    }
+    match parse_open_spotify_url(uri) {
+        Some((uri_type, id)) => Some(format!("spotify:{}:{}", uri_type, id)),
+        None => {
+            warn!("mpris: could not parse Spotify URL {}", uri);
+            None
+        }
+    }
+}
+
+/// Replaces the queue with the tracks of a playlist. Playback starts as soon as
+/// the first page of tracks is loaded, the remaining pages are appended in the
+/// background so large playlists don't block the dbus thread.
+fn open_playlist(player: &impl Player, queue: &Arc<Queue>, uri: &str) {
+    let id = &uri[uri.rfind(':').unwrap_or(0) + 1..];
+    let (context, tracks) = match player.playlist(id) {
+        Some(playlist) => playlist,
+        None => return,
+    };
+
+    let first_page = tracks.items.read().unwrap().clone();
+    if first_page.is_empty() {
+        return;
+    }
+
+    let should_shuffle = queue.get_shuffle();
+    queue.clear();
+    queue.set_context(Some(context));
+    let index = queue.append_next(&first_page);
+    queue.play(index, should_shuffle, should_shuffle);
+
+    let queue = queue.clone();
+    std::thread::spawn(move || {
+        let mut expected_len = first_page.len();
+        while let Some(page) = tracks.next() {
+            // stop once the queue has been changed by anything else in the meantime
+            if queue.len() != expected_len {
+                debug!("queue changed, not appending remaining playlist tracks");
+                return;
+            }
+            expected_len += page.len();
+            for playable in page {
+                queue.append(playable);
+            }
+        }
+
+        // include the appended tracks in the random order
+        if should_shuffle {
+            queue.set_shuffle(true);
+        }
+    });
+}
+
+/// Returns the tracks of the album `track` is on, along with its position.
+fn album_context(player: &impl Player, track: &Track) -> Option<(Vec<Track>, usize)> {
+    let tracks = player.album_tracks(track.album_id.as_ref()?)?;
+    let position = tracks.iter().position(|t| t.id == track.id)?;
+    Some((tracks, position))
+}
+
+/// The player state reported via MPRIS, implemented by `Spotify` and by fakes in
+/// tests.
+trait PlaybackControl {
+    fn status(&self) -> PlayerEvent;
+    /// Bitrate the player streams at, in kbit/s
+    fn bitrate(&self) -> u32;
+    /// Whether the player accepts commands at the moment
+    fn can_control(&self) -> bool;
+}
+
+impl PlaybackControl for Spotify {
+    fn status(&self) -> PlayerEvent {
+        self.get_current_status()
+    }
+
+    fn can_control(&self) -> bool {
+        self.is_connected()
+    }
+
+    fn bitrate(&self) -> u32 {
+        Spotify::bitrate(self)
+    }
+}
+
+/// Audio features of a track reported as vendor metadata
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TrackFeatures {
+    /// Perceived intensity from 0.0 to 1.0
+    energy: f64,
+    /// Estimated tempo in beats per minute
+    tempo: f64,
+}
+
+/// Looks up the details missing from simplified tracks and episodes, which
+/// lack the cover and show respectively.
+trait MetadataSource {
+    /// Fetches the full version of `playable`, whose id is `id`.
+    fn full_playable(&self, playable: &Playable, id: &str) -> Option<Playable>;
+    /// Fetches the audio features of the track with the id `id`.
+    fn audio_features(&self, id: &str) -> Option<TrackFeatures>;
+    /// Whether lookups should be skipped to back off from a rate limit.
+    fn rate_limited(&self) -> bool;
+    /// Lyrics of `track`, if they have been loaded. ncspot doesn't fetch
+    /// lyrics itself, so there are none unless a source provides them.
+    fn lyrics(&self, _track: &Track) -> Option<String> {
+        None
+    }
+}
+
+impl MetadataSource for Spotify {
+    fn full_playable(&self, playable: &Playable, id: &str) -> Option<Playable> {
+        Some(match playable {
+            Playable::Track(_) => Playable::Track(self.api.track(id).as_ref()?.into()),
+            Playable::Episode(_) => Playable::Episode(self.api.episode(id).as_ref()?.into()),
+        })
+    }
+
+    fn audio_features(&self, id: &str) -> Option<TrackFeatures> {
+        let features = self.api.track_features(id)?;
+        Some(TrackFeatures {
+            energy: features.energy as f64,
+            tempo: features.tempo as f64,
+        })
+    }
+
+    fn rate_limited(&self) -> bool {
+        self.api.rate_limited()
+    }
+}
+
+/// What the library knows about an item: whether it is a saved track, reported
+/// as its rating, and when it was last played.
+trait LibraryState {
+    fn is_saved(&self, playable: &Playable) -> bool;
+    fn last_played(&self, playable: &Playable) -> Option<DateTime<Utc>>;
+}
+
+impl LibraryState for Library {
+    fn is_saved(&self, playable: &Playable) -> bool {
+        // reported as unsaved while loading, the metadata is refreshed afterwards
+        self.try_is_saved_track(playable).unwrap_or(false)
+    }
+
+    fn last_played(&self, playable: &Playable) -> Option<DateTime<Utc>> {
+        let uri = playable.uri();
+        self.history
+            .read()
+            .unwrap()
+            .iter()
+            .find(|entry| entry.playable.uri() == uri)
+            .map(|entry| entry.played_at)
+    }
+}
+
+/// The player controlled over MPRIS, along with the Web API lookups needed to
+/// open URIs. Implemented by `Spotify` and by stubs in tests.
+trait Player: PlaybackControl + MetadataSource + Clone + Send + Sync + 'static {
+    fn volume(&self) -> u16;
+    fn set_volume(&self, volume: u16);
+    fn progress(&self) -> Duration;
+    fn play(&self);
+    fn pause(&self);
+    fn stop(&self);
+    fn seek(&self, position_ms: u32);
+    fn playback_rate(&self) -> f64;
+    fn normalisation(&self) -> bool;
+    fn crossfade_ms(&self) -> u32;
+    fn set_crossfade_ms(&self, crossfade_ms: u32);
+    fn device_name(&self) -> String;
+    fn sleep_timer_remaining(&self) -> Option<Duration>;
+    fn set_sleep_timer(&self, duration: Option<Duration>);
+    /// Items to play for a track, album, show or episode URI.
+    fn playables_for_uri(&self, uri: &str) -> Vec<Playable>;
+    /// The context of the playlist with the id `id` and its tracks.
+    fn playlist(&self, id: &str) -> Option<(PlaybackContext, ApiResult<Playable>)>;
+    /// The tracks of the album with the id `album_id`.
+    fn album_tracks(&self, album_id: &str) -> Option<Vec<Track>>;
+    fn artist(&self, id: &str) -> Option<Artist>;
+    fn recommendations(&self, artist: &Artist) -> Option<Vec<Track>>;
+}
+
+impl Player for Spotify {
+    fn volume(&self) -> u16 {
+        Spotify::volume(self)
+    }
+
+    fn set_volume(&self, volume: u16) {
+        Spotify::set_volume(self, volume)
+    }
+
+    fn progress(&self) -> Duration {
+        self.get_current_progress()
+    }
+
+    fn play(&self) {
+        Spotify::play(self)
+    }
+
+    fn pause(&self) {
+        Spotify::pause(self)
+    }
+
+    fn stop(&self) {
+        Spotify::stop(self)
+    }
+
+    fn seek(&self, position_ms: u32) {
+        Spotify::seek(self, position_ms)
+    }
+
+    fn playback_rate(&self) -> f64 {
+        Spotify::playback_rate(self)
+    }
+
+    fn normalisation(&self) -> bool {
+        Spotify::normalisation(self)
+    }
+
+    fn crossfade_ms(&self) -> u32 {
+        Spotify::crossfade_ms(self)
+    }
+
+    fn set_crossfade_ms(&self, crossfade_ms: u32) {
+        Spotify::set_crossfade_ms(self, crossfade_ms)
+    }
+
+    fn device_name(&self) -> String {
+        Spotify::device_name(self)
+    }
+
+    fn sleep_timer_remaining(&self) -> Option<Duration> {
+        Spotify::sleep_timer_remaining(self)
+    }
+
+    fn set_sleep_timer(&self, duration: Option<Duration>) {
+        Spotify::set_sleep_timer(self, duration)
+    }
+
+    fn playables_for_uri(&self, uri: &str) -> Vec<Playable> {
+        Spotify::playables_for_uri(self, uri)
+    }
+
+    fn playlist(&self, id: &str) -> Option<(PlaybackContext, ApiResult<Playable>)> {
+        let context = Playlist::from(&self.api.playlist(id)?).playback_context();
+        Some((context, self.api.user_playlist_tracks(id)))
+    }
+
+    fn album_tracks(&self, album_id: &str) -> Option<Vec<Track>> {
+        Album::from(&self.api.album(album_id)?).tracks
+    }
+
+    fn artist(&self, id: &str) -> Option<Artist> {
+        self.api.artist(id).map(|a| Artist::from(&a))
+    }
+
+    fn recommendations(&self, artist: &Artist) -> Option<Vec<Track>> {
+        artist.recommendations(self.clone())
+    }
+}
+
+/// The parts of the library exposed over MPRIS, implemented by `Library` and
+/// by stubs in tests.
+trait MprisLibrary: LibraryState + Send + Sync + 'static {
+    fn with_playlists<T>(&self, f: impl FnOnce(&[Playlist]) -> T) -> T;
+    /// Played items, the most recent first
+    fn history(&self) -> Vec<HistoryEntry>;
+    fn save_track(&self, track: &Track);
+    fn unsave_track(&self, track: &Track);
+}
+
+impl MprisLibrary for Library {
+    fn with_playlists<T>(&self, f: impl FnOnce(&[Playlist]) -> T) -> T {
+        f(&self.playlists())
+    }
+
+    fn history(&self) -> Vec<HistoryEntry> {
+        self.history.read().unwrap().clone()
+    }
+
+    fn save_track(&self, track: &Track) {
+        self.save_tracks(vec![track], true)
+    }
+
+    fn unsave_track(&self, track: &Track) {
+        self.unsave_tracks(vec![track], true)
+    }
+}
+
+/// Maps the player status to an MPRIS `PlaybackStatus` value. A finished track
+/// only counts as playing if the queue is going to continue with another one.
+fn playback_status(status: &PlayerEvent, continues: bool) -> &'static str {
+    match status {
+        PlayerEvent::Playing(_) => "Playing",
+        PlayerEvent::FinishedTrack if continues => "Playing",
         PlayerEvent::Paused(_) => "Paused",
         _ => "Stopped",
     }
-    .to_string()
-}
+}
+
+fn get_playbackstatus(player: &impl PlaybackControl, queue: &Queue) -> String {
+    // the player may still report the last item after the queue was cleared
+    if queue.get_current_index().is_none() {
+        return playback_status(&PlayerEvent::Stopped, false).to_string();
+    }
+    let continues = queue.has_next() || queue.get_repeat() == RepeatSetting::RepeatTrack;
+    playback_status(&player.status(), continues).to_string()
+}
+
+/// Whether `Previous` would go back to another item, or restart the current one.
+fn can_go_previous(queue: &Queue) -> bool {
+    queue.get_current_index().is_some() && (queue.has_previous() || queue.restarts_current())
+}
+
+/// Fails calls of methods that control playback while the player can't accept
+/// commands, e.g. while the session is restarting, so clients report an error
+/// instead of the call silently having no effect.
+fn ensure_ready(player: &impl PlaybackControl) -> Result<(), MethodErr> {
+    match player.can_control() {
+        true => Ok(()),
+        false => Err(MethodErr::failed("The player is not ready")),
+    }
+}
+
+/// Accumulates how long the current item has actually been listened to, so
+/// that neither pausing nor seeking counts towards the scrobble threshold.
+#[derive(Default)]
+struct ListenTracker {
+    uri: Option<String>,
+    /// Progress of the item and time of the last sample taken while playing
+    progress: Duration,
+    sampled_at: Option<Instant>,
+    listened: Duration,
+    scrobbled: bool,
+}
+
+impl ListenTracker {
+    /// Updates the listened time with a sample of the player state. Returns
+    /// the URI and listened time once per play, when the item has been
+    /// listened to long enough to be scrobbled.
+    fn sample(
+        &mut self,
+        uri: Option<String>,
+        duration: Duration,
+        progress: Duration,
+        playing: bool,
+        now: Instant,
+    ) -> Option<(String, Duration)> {
+        let replayed = self.scrobbled && progress < self.progress && progress < REPLAY_PROGRESS;
+        if uri != self.uri || replayed {
+            *self = ListenTracker {
+                uri,
+                ..Default::default()
+            };
+        } else if let Some(sampled_at) = self.sampled_at.filter(|_| playing) {
+            // seeking ahead only counts the time that actually passed
+            if let Some(advanced) = progress.checked_sub(self.progress) {
+                self.listened += advanced.min(now.saturating_duration_since(sampled_at));
+            }
+        }
+        self.progress = progress;
+        self.sampled_at = playing.then_some(now);
+
+        let threshold = (duration / 2).min(MAX_SCROBBLE_THRESHOLD);
+        if self.scrobbled || duration < MIN_SCROBBLE_DURATION || self.listened < threshold {
+            return None;
+        }
+        self.scrobbled = true;
+        Some((self.uri.clone()?, self.listened))
+    }
+}
+
+/// Availability of the controls reported by `CanControl`, `CanPlay`, `CanPause`
+/// and `CanSeek`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Controls {
+    control: bool,
+    play: bool,
+    pause: bool,
+    seek: bool,
+}
+
+/// Derives the available controls from the player state: nothing can be
+/// controlled while the player is unavailable, pausing and seeking need an
+/// item to be loaded, and seeking also needs its length to be known.
+fn get_controls(
+    player: &impl PlaybackControl,
+    queue_len: usize,
+    length_ms: Option<u32>,
+) -> Controls {
+    let control = player.can_control();
+    let loaded = matches!(
+        player.status(),
+        PlayerEvent::Playing(_) | PlayerEvent::Paused(_)
+    );
+    Controls {
+        control,
+        play: control && queue_len > 0,
+        pause: control && loaded,
+        seek: control && loaded && length_ms.is_some(),
+    }
+}
+
+fn queue_controls(player: &impl PlaybackControl, queue: &Queue) -> Controls {
+    let length_ms = known_length(queue.get_current().as_ref());
+    get_controls(player, queue.len(), length_ms)
+}
+
+/// Returns the duration of `playable` in milliseconds, or `None` if it is
+/// unknown, as for live content, which Spotify reports with a duration of 0.
+fn known_length(playable: Option<&Playable>) -> Option<u32> {
+    playable
+        .map(Playable::duration)
+        .filter(|duration| *duration > 0)
+}
+
+fn get_state(player: &impl Player, queue: &Queue) -> MprisState {
+    let current = queue.get_current_entry();
+    MprisState {
+        status: get_playbackstatus(player, queue),
+        entry_id: current.as_ref().map(|(id, _)| *id),
+        playable: current.map(|(_, playable)| playable),
+        volume: mpris_volume(player.volume()),
+        rate: player.playback_rate(),
+        repeat: queue.get_repeat(),
+        shuffle: is_shuffled(queue),
+        can_go_next: queue.has_next(),
+        can_go_previous: can_go_previous(queue),
+        controls: queue_controls(player, queue),
+        context: queue.get_context(),
+        queue_length: queue.len(),
+        upcoming_ms: queue.upcoming_duration(),
+        crossfade_ms: player.crossfade_ms(),
+        device: player.device_name(),
+    }
+}
+
+/// Time in microseconds until the end of the queue: the rest of the current
+/// item, if there is one, and the items after it.
+fn queue_remaining_us(duration_ms: Option<u32>, progress: Duration, upcoming_ms: u64) -> i64 {
+    let current = duration_ms.map_or(Duration::ZERO, |duration| {
+        Duration::from_millis(duration as u64).saturating_sub(progress)
+    });
+    (current + Duration::from_millis(upcoming_ms)).as_micros() as i64
+}
+
+/// Returns how long the server loop waits for dbus messages. While playing it
+/// has to wake up often to pass on changes and watch the progress, otherwise
+/// it may wait for `idle_ms`, as calls from the bus wake it up anyway.
+fn poll_interval(state: Option<&MprisState>, idle_ms: u32) -> u32 {
+    match state {
+        Some(state) if state.status == "Playing" => PLAYING_POLL_INTERVAL_MS,
+        _ => idle_ms.max(PLAYING_POLL_INTERVAL_MS),
+    }
+}
+
+/// Prefix of ncspot's object paths. Playlists are identified by their Spotify
+/// URI after it, with its `:` separators replaced by `/`.
+const TRACKID_PREFIX: &str = "/org/ncspot/";
+
+/// Prefix of the track ids of queue entries, followed by the entry id
+const QUEUE_TRACKID_PREFIX: &str = "/org/ncspot/queue/";
+
+/// Maps a Spotify URI to its track id, or `None` if it can't be expressed as
+/// an object path.
+fn uri_to_trackid(uri: &str) -> Option<Path<'static>> {
+    if !uri.starts_with("spotify:") {
+        return None;
+    }
+    Path::new(format!("{}{}", TRACKID_PREFIX, uri.replace(':', "/"))).ok()
+}
+
+/// Maps a track id created by `uri_to_trackid` back to its Spotify URI.
+fn trackid_to_uri(trackid: &Path) -> Option<String> {
+    trackid
+        .strip_prefix(TRACKID_PREFIX)
+        .filter(|id| id.starts_with("spotify/"))
+        .map(|id| id.replace('/', ":"))
+}
+
+/// Track id of the queue entry with the id `entry_id`, which tells apart
+/// several entries of the same item, or the track list's `NoTrack` id.
+fn get_trackid(entry_id: Option<u64>) -> Path<'static> {
+    match entry_id {
+        Some(id) => Path::from(format!("{}{}", QUEUE_TRACKID_PREFIX, id)),
+        None => Path::from(TRACKLIST_NO_TRACK),
+    }
+}
+
+/// Maps a track id created by `get_trackid` back to its entry id.
+fn trackid_to_entry(trackid: &Path) -> Option<u64> {
+    trackid.strip_prefix(QUEUE_TRACKID_PREFIX)?.parse().ok()
+}
+
+fn get_tracklist(queue: &Queue) -> Vec<Path<'static>> {
+    queue
+        .entries()
+        .into_iter()
+        .map(|(id, _)| get_trackid(Some(id)))
+        .collect()
+}
+
+fn find_in_queue(queue: &Queue, trackid: &Path) -> Option<usize> {
+    queue.entry_index(trackid_to_entry(trackid)?)
+}
+
+/// Returns a `file://` URL for the cached copy of the cover at `url`. Until
+/// it has been downloaded in the background `url` itself is reported, so
+/// property calls don't wait for the download.
+fn local_art_url(url: String) -> String {
+    lazy_static! {
+        static ref DOWNLOADING: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+    }
+
+    let path = crate::utils::cache_path_for_url(url.clone());
+    let mut downloading = DOWNLOADING.lock().unwrap();
+    if downloading.contains(&url) {
+        return url;
+    }
+    if path.exists() {
+        return Url::from_file_path(&path).map_or(url, String::from);
+    }
+
+    // the cover cache is kept within its limit by `cache::evict`
+    downloading.insert(url.clone());
+    {
+        let url = url.clone();
+        std::thread::spawn(move || {
+            crate::utils::cached_cover(url.clone());
+            DOWNLOADING.lock().unwrap().remove(&url);
+        });
+    }
+    url
+}
+
+/// Validates a release date, which Spotify reports with a precision of a year,
+/// month or day, as a partial ISO 8601 date. Returns `None` if it is unknown,
+/// e.g. for the year `0000` given for some old recordings.
+fn iso_date(date: &str) -> Option<String> {
+    let date = date.trim();
+    let lengths: Vec<usize> = date.split('-').map(str::len).collect();
+    // the missing parts are filled in to validate the ones that are given
+    let full_date = match lengths[..] {
+        [4] => format!("{}-01-01", date),
+        [4, 2] => format!("{}-01", date),
+        [4, 2, 2] => date.to_string(),
+        _ => return None,
+    };
+    let parsed = chrono::NaiveDate::parse_from_str(&full_date, "%Y-%m-%d").ok()?;
+    (parsed.year() > 0).then(|| date.to_string())
+}
+
+/// Metadata of `playable`, identified by the id of its queue entry.
+fn get_metadata(
+    playable: Option<Playable>,
+    entry_id: Option<u64>,
+    source: &impl MetadataSource,
+    library: &impl LibraryState,
+    cache: &PlayableCache,
+    local_art: bool,
+) -> Metadata {
+    let mut hm: Metadata = HashMap::new();
+
+    // without a current item only the track id is reported, so players don't show
+    // an empty track
+    if playable.is_none() {
+        hm.insert(
+            "mpris:trackid".to_string(),
+            Variant(Box::new(Path::from(TRACKLIST_NO_TRACK))),
+        );
+        return hm;
+    }
+
+    // Fetch full track details in case this playable is based on a SimplifiedTrack
+    // This is necessary because SimplifiedTrack objects don't contain a cover_url
+    // or popularity. Likewise, SimplifiedEpisode objects don't contain the show they belong to, and
+    // episodes from search results may lack their duration
+    let playable_full = playable.and_then(|p| match p {
+        Playable::Track(ref track) if track.cover_url.is_some() && track.popularity.is_some() => {
+            Some(p)
+        }
+        // local files in playlists have no id to look them up with
+        Playable::Track(ref track) if track.id.is_none() => Some(p),
+        Playable::Episode(ref episode) if episode.show_name.is_some() && episode.duration > 0 => {
+            Some(p)
+        }
+        _ => {
+            let id = p.id().unwrap_or_default();
+            let cached = cache.lock().unwrap().playables.get(&id);
+            if cached.is_none() && source.rate_limited() {
+                // an applet polling metadata must not get the session throttled further
+                debug!("rate limited, skipping metadata lookup of {}", id);
+                return Some(p);
+            }
+            cached.or_else(|| {
+                let full = source.full_playable(&p, &id)?;
+                cache.lock().unwrap().playables.insert(id, full.clone());
+                Some(full)
+            })
+        }
+    });
+    let playable = playable_full.as_ref();
+    let (track, episode) = match playable {
+        Some(Playable::Track(track)) => (Some(track), None),
+        Some(Playable::Episode(episode)) => (None, Some(episode)),
+        None => (None, None),
+    };
+
+    // lets players pick podcast style controls for episodes
+    let media_type = match playable {
+        Some(Playable::Track(_)) => Some("track"),
+        Some(Playable::Episode(_)) => Some("episode"),
+        None => None,
+    };
+    if let Some(media_type) = media_type {
+        hm.insert(
+            "ncspot:mediaType".to_string(),
+            Variant(Box::new(media_type.to_string())),
+        );
+    }
+
+    hm.insert(
+        "mpris:trackid".to_string(),
+        Variant(Box::new(get_trackid(entry_id))),
+    );
+    // players treat a length of 0 as finished, so it is left out if unknown
+    if let Some(length_ms) = known_length(playable) {
+        hm.insert(
+            "mpris:length".to_string(),
+            Variant(Box::new(length_ms as i64 * 1_000)),
+        );
+    }
+    let art_url = playable
+        .and_then(|p| p.cover_url())
+        .map(|url| match local_art {
+            true => local_art_url(url),
+            false => url,
+        });
+    hm.insert(
+        "mpris:artUrl".to_string(),
+        Variant(Box::new(art_url.unwrap_or_default())),
+    );
+
+    if let Some(episode) = episode {
+        if let Some(show_name) = &episode.show_name {
+            hm.insert(
+                "ncspot:show".to_string(),
+                Variant(Box::new(show_name.clone())),
+            );
+            hm.insert(
+                "xesam:album".to_string(),
+                Variant(Box::new(show_name.clone())),
+            );
+        }
+        if let Some(publisher) = &episode.show_publisher {
+            hm.insert(
+                "xesam:artist".to_string(),
+                Variant(Box::new(vec![publisher.clone()])),
+            );
+        }
+        if !episode.description.is_empty() {
+            let comment: String = episode
+                .description
+                .chars()
+                .take(MAX_COMMENT_LENGTH)
+                .collect();
+            hm.insert(
+                "xesam:comment".to_string(),
+                Variant(Box::new(vec![comment])),
+            );
+        }
+        hm.insert(
+            "xesam:genre".to_string(),
+            Variant(Box::new(vec!["Podcast".to_string()])),
+        );
+    } else {
+        hm.insert(
+            "xesam:album".to_string(),
+            Variant(Box::new(
+                track.and_then(|t| t.album.clone()).unwrap_or_default(),
+            )),
+        );
+        if let Some(track) = track {
+            if let Some(lyrics) = source.lyrics(track) {
+                hm.insert("xesam:asText".to_string(), Variant(Box::new(lyrics)));
+            }
+            // empty artist lists are omitted, so clients treat them as unknown
+            if !track.album_artists.is_empty() {
+                hm.insert(
+                    "xesam:albumArtist".to_string(),
+                    Variant(Box::new(track.album_artists.clone())),
+                );
+            }
+            if !track.artists.is_empty() {
+                hm.insert(
+                    "xesam:artist".to_string(),
+                    Variant(Box::new(track.artists.clone())),
+                );
+            }
+            if let Some(popularity) = track.popularity {
+                hm.insert(
+                    "ncspot:popularity".to_string(),
+                    Variant(Box::new(popularity as i32)),
+                );
+            }
+            // the features are fetched separately and only once per track
+            let features = track.id.clone().and_then(|id| {
+                let cached = cache.lock().unwrap().features.get(&id);
+                if cached.is_none() && source.rate_limited() {
+                    debug!("rate limited, skipping audio features lookup of {}", id);
+                    return None;
+                }
+                cached.unwrap_or_else(|| {
+                    let features = source.audio_features(&id);
+                    cache.lock().unwrap().features.insert(id, features);
+                    features
+                })
+            });
+            if let Some(features) = features {
+                hm.insert(
+                    "ncspot:energy".to_string(),
+                    Variant(Box::new(features.energy)),
+                );
+                hm.insert(
+                    "ncspot:tempo".to_string(),
+                    Variant(Box::new(features.tempo)),
+                );
+            }
+        }
+    }
+    let content_created = track
+        .and_then(|t| t.album_release_date.as_deref())
+        .or_else(|| episode.map(|ep| ep.release_date.as_str()))
+        .and_then(iso_date);
+    if let Some(date) = content_created {
+        hm.insert("xesam:contentCreated".to_string(), Variant(Box::new(date)));
+    }
+    if let Some(played_at) = playable.and_then(|p| library.last_played(p)) {
+        hm.insert(
+            "xesam:lastUsed".to_string(),
+            Variant(Box::new(
+                played_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            )),
+        );
+    }
+    hm.insert(
+        "xesam:discNumber".to_string(),
+        Variant(Box::new(track.map(|t| t.disc_number).unwrap_or(0))),
+    );
+    hm.insert(
+        "xesam:title".to_string(),
+        Variant(Box::new(
+            playable
+                .map(|t| match t {
+                    Playable::Track(t) => t.title.clone(),
+                    Playable::Episode(ep) => ep.name.clone(),
+                })
+                .unwrap_or_default(),
+        )),
+    );
+    hm.insert(
+        "xesam:trackNumber".to_string(),
+        Variant(Box::new(track.map(|t| t.track_number).unwrap_or(0) as i32)),
+    );
+    hm.insert(
+        "xesam:url".to_string(),
+        Variant(Box::new(
+            playable
+                .map(|t| t.share_url().unwrap_or_default())
+                .unwrap_or_default(),
+        )),
+    );
+    hm.insert(
+        "xesam:userRating".to_string(),
+        Variant(Box::new(match playable {
+            Some(p @ Playable::Track(_)) if library.is_saved(p) => 1.0,
+            _ => 0.0,
+        })),
+    );
+
+    hm
+}
+
+/// Like `get_metadata`, with additional details about the playback of the
+/// current item.
+fn get_current_metadata(
+    playable: Option<Playable>,
+    entry_id: Option<u64>,
+    player: &(impl PlaybackControl + MetadataSource),
+    library: &impl LibraryState,
+    cache: &PlayableCache,
+    local_art: bool,
+) -> Metadata {
+    let loaded = playable.is_some()
+        && matches!(
+            player.status(),
+            PlayerEvent::Playing(_) | PlayerEvent::Paused(_)
+        );
+    let mut hm = get_metadata(playable, entry_id, player, library, cache, local_art);
+    if loaded {
+        hm.insert(
+            "ncspot:bitrate".to_string(),
+            Variant(Box::new(player.bitrate() as i32)),
+        );
+    }
+    hm
+}
+
+/// Converts the metadata to JSON, e.g. to inspect what clients are being sent.
+fn metadata_to_json(metadata: &Metadata) -> serde_json::Value {
+    metadata
+        .iter()
+        .map(|(key, value)| (key.clone(), refarg_to_json(&*value.0)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Converts the types of values used in the metadata, anything else becomes `null`.
+fn refarg_to_json(arg: &dyn RefArg) -> serde_json::Value {
+    match arg.arg_type() {
+        ArgType::String | ArgType::ObjectPath => arg.as_str().into(),
+        ArgType::Boolean => arg.as_i64().map(|b| b != 0).into(),
+        ArgType::Byte
+        | ArgType::Int16
+        | ArgType::UInt16
+        | ArgType::Int32
+        | ArgType::UInt32
+        | ArgType::Int64 => arg.as_i64().into(),
+        ArgType::UInt64 => arg.as_u64().into(),
+        ArgType::Double => arg.as_f64().into(),
+        ArgType::Array => arg
+            .as_iter()
+            .map(|items| items.map(refarg_to_json).collect())
+            .unwrap_or_default(),
+        ArgType::Variant => arg
+            .as_iter()
+            .and_then(|mut inner| inner.next().map(refarg_to_json))
+            .unwrap_or_default(),
+        _ => serde_json::Value::Null,
+    }
+}
+
+/// Bus name claimed by ncspot if no instance name is given
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.ncspot";
+
+/// Returns the bus name for the given instance name, replacing the characters
+/// that aren't allowed in bus names.
+fn bus_name(instance: Option<&str>) -> String {
+    match instance.filter(|instance| !instance.is_empty()) {
+        Some(instance) => {
+            let mut suffix: String = instance
+                .chars()
+                .map(|c| match c {
+                    'A'..='Z' | 'a'..='z' | '0'..='9' | '_' | '-' => c,
+                    _ => '_',
+                })
+                .collect();
+            // elements of bus names must not start with a digit
+            if suffix.starts_with(|c: char| c.is_ascii_digit()) {
+                suffix.insert(0, '_');
+            }
+            format!("{}.{}", BUS_NAME, suffix)
+        }
+        None => BUS_NAME.to_string(),
+    }
+}
+
+/// Connects to the session bus and claims the MPRIS name for ncspot. If the
+/// name is already taken, e.g. by another instance, a name unique to this
+/// process is claimed instead.
+fn connect(instance: Option<&str>) -> Result<(dbus::ffidisp::Connection, String), dbus::Error> {
+    let conn = dbus::ffidisp::Connection::get_private(dbus::ffidisp::BusType::Session)?;
+    let flags = dbus::ffidisp::NameFlag::ReplaceExisting as u32
+        | dbus::ffidisp::NameFlag::DoNotQueue as u32;
+    let name = bus_name(instance);
+    if conn.register_name(&name, flags)? != dbus::ffidisp::RequestNameReply::PrimaryOwner {
+        let fallback = format!("{}.instance{}", BUS_NAME, std::process::id());
+        warn!("mpris: {} is already taken, using {}", name, fallback);
+        conn.register_name(&fallback, flags)?;
+        return Ok((conn, fallback));
+    }
+    Ok((conn, name))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_dbus_server<P: Player, L: MprisLibrary>(
+    conn: &dbus::ffidisp::Connection,
+    ev: EventManager,
+    spotify: P,
+    queue: Arc<Queue>,
+    library: Arc<L>,
+    cfg: Arc<Config>,
+    rx: &mpsc::Receiver<MprisCommand>,
+    metadata_cache: Arc<PlayableCache>,
+    fullscreen: Arc<AtomicBool>,
+    shutdown: &AtomicBool,
+) {
+    let local_art = cfg.values().mpris_local_art.unwrap_or(false);
+    let idle_interval = cfg
+        .values()
+        .mpris_idle_interval_ms
+        .unwrap_or(DEFAULT_IDLE_POLL_INTERVAL_MS);
+
+    let f = Factory::new_fn::<()>();
+
+    let property_canquit = f
+        .property::<bool, _>("CanQuit", ())
+        .access(Access::Read)
+        .on_get(|iter, _| {
+            iter.append(true);
+            Ok(())
+        });
+
+    let property_canraise = {
+        let cfg = cfg.clone();
+        f.property::<bool, _>("CanRaise", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(cfg.values().raise_command.is_some());
+                Ok(())
+            })
+    };
+
+    let property_cansetfullscreen = f
+        .property::<bool, _>("CanSetFullscreen", ())
+        .access(Access::Read)
+        .on_get(|iter, _| {
+            iter.append(cfg!(feature = "cover"));
+            Ok(())
+        });
+
+    let property_fullscreen = {
+        let ev = ev.clone();
+        f.property::<bool, _>("Fullscreen", ())
+            .access(Access::ReadWrite)
+            .on_get(move |iter, _| {
+                iter.append(fullscreen.load(Ordering::Relaxed));
+                Ok(())
+            })
+            .on_set(move |iter, _| {
+                // fullscreen is the cover screen, which only exists with the `cover` feature
+                match iter.get::<bool>() {
+                    Some(fullscreen) if cfg!(feature = "cover") => {
+                        ev.send(Event::Fullscreen(fullscreen))
+                    }
+                    _ => warn!("mpris: ignoring Fullscreen change"),
+                }
+                Ok(())
+            })
+    };
+
+    let property_hastracklist = f
+        .property::<bool, _>("HasTrackList", ())
+        .access(Access::Read)
+        .on_get(|iter, _| {
+            iter.append(true);
+            Ok(())
+        });
+
+    let property_identity = f
+        .property::<String, _>("Identity", ())
+        .access(Access::Read)
+        .on_get(|iter, _| {
+            iter.append("ncspot".to_string());
+            Ok(())
+        });
+
+    let property_urischemes = f
+        .property::<Vec<String>, _>("SupportedUriSchemes", ())
+        .access(Access::Read)
+        .on_get(|iter, _| {
+            iter.append(vec![
+                "spotify".to_string(),
+                "http".to_string(),
+                "https".to_string(),
+            ]);
+            Ok(())
+        });
+
+    let property_mimetypes = f
+        .property::<Vec<String>, _>("SupportedMimeTypes", ())
+        .access(Access::Read)
+        .on_get(|iter, _| {
+            iter.append(Vec::new() as Vec<String>);
+            Ok(())
+        });
+
+    let method_quit = {
+        let ev = ev.clone();
+        f.method("Quit", (), move |m| {
+            ev.send(Event::Command(Command::Quit));
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_raise = {
+        let cfg = cfg.clone();
+        f.method("Raise", (), move |m| {
+            if let Some(raise_command) = cfg.values().raise_command.clone() {
+                if let Err(e) = std::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(&raise_command)
+                    .spawn()
+                {
+                    error!("could not run raise command '{}': {}", raise_command, e);
+                }
+            }
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    // https://specifications.freedesktop.org/mpris-spec/latest/Media_Player.html
+    let interface = f
+        .interface("org.mpris.MediaPlayer2", ())
+        .add_p(property_canquit)
+        .add_p(property_canraise)
+        .add_p(property_cansetfullscreen)
+        .add_p(property_fullscreen)
+        .add_p(property_hastracklist)
+        .add_p(property_identity)
+        .add_p(property_urischemes)
+        .add_p(property_mimetypes)
+        .add_m(method_quit)
+        .add_m(method_raise);
+
+    let property_playbackstatus = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.property::<String, _>("PlaybackStatus", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                let status = get_playbackstatus(&spotify, &queue);
+                iter.append(status);
+                Ok(())
+            })
+    };
+
+    let property_loopstatus = {
+        let queue1 = queue.clone();
+        let queue2 = queue.clone();
+        let ev = ev.clone();
+        f.property::<String, _>("LoopStatus", ())
+            .access(Access::ReadWrite)
+            .on_get(move |iter, _| {
+                iter.append(loop_status(queue1.get_repeat()).to_string());
+                Ok(())
+            })
+            .on_set(move |iter, _| {
+                set_loop_status(&queue2, iter.get());
+                ev.trigger();
+                Ok(())
+            })
+    };
+
+    let property_metadata = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        let library = library.clone();
+        let metadata_cache = metadata_cache.clone();
+        f.property::<HashMap<String, Variant<Box<dyn RefArg>>>, _>("Metadata", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                let current = queue.get_current_entry();
+                let hm = get_current_metadata(
+                    current.as_ref().map(|(_, playable)| playable.clone()),
+                    current.map(|(id, _)| id),
+                    &spotify,
+                    &*library,
+                    &metadata_cache,
+                    local_art,
+                );
+
+                iter.append(hm);
+                Ok(())
+            })
+    };
+
+    let property_position = {
+        let spotify = spotify.clone();
+        f.property::<i64, _>("Position", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                let progress = spotify.progress();
+                iter.append(progress.as_micros() as i64);
+                Ok(())
+            })
+    };
+
+    let property_volume = {
+        let spotify1 = spotify.clone();
+        let spotify2 = spotify.clone();
+        let event = ev.clone();
+        f.property::<f64, _>("Volume", ())
+            .access(Access::ReadWrite)
+            .on_get(move |i, _| {
+                i.append(mpris_volume(spotify1.volume()));
+                Ok(())
+            })
+            .on_set(move |i, _| {
+                ensure_ready(&spotify2)?;
+                let cur = mpris_volume(spotify2.volume());
+                let req = i.get::<f64>().unwrap_or(cur);
+                if (0.0..=1.0).contains(&req) {
+                    spotify2.set_volume(player_volume(req));
+                }
+                event.trigger();
+                Ok(())
+            })
+    };
+
+    let property_rate = {
+        let spotify1 = spotify.clone();
+        let spotify2 = spotify.clone();
+        let queue = queue.clone();
+        f.property::<f64, _>("Rate", ())
+            .access(Access::ReadWrite)
+            .on_get(move |iter, _| {
+                iter.append(spotify1.playback_rate());
+                Ok(())
+            })
+            .on_set(move |iter, _| {
+                match iter.get::<f64>().filter(|rate| rate.is_finite()) {
+                    // as per the specification a rate of 0 pauses playback
+                    Some(rate) if rate == 0.0 => spotify2.pause(),
+                    Some(rate) => queue.set_playback_rate(rate),
+                    None => {}
+                }
+                Ok(())
+            })
+    };
+
+    let property_minrate = f
+        .property::<f64, _>("MinimumRate", ())
+        .access(Access::Read)
+        .on_get(|iter, _| {
+            iter.append(MIN_PLAYBACK_RATE);
+            Ok(())
+        });
+
+    let property_maxrate = f
+        .property::<f64, _>("MaximumRate", ())
+        .access(Access::Read)
+        .on_get(|iter, _| {
+            iter.append(MAX_PLAYBACK_RATE);
+            Ok(())
+        });
+
+    let property_canplay = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.property::<bool, _>("CanPlay", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(queue_controls(&spotify, &queue).play);
+                Ok(())
+            })
+    };
+
+    let property_canpause = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.property::<bool, _>("CanPause", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(queue_controls(&spotify, &queue).pause);
+                Ok(())
+            })
+    };
+
+    let property_canseek = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.property::<bool, _>("CanSeek", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(queue_controls(&spotify, &queue).seek);
+                Ok(())
+            })
+    };
+
+    let property_cancontrol = {
+        let spotify = spotify.clone();
+        f.property::<bool, _>("CanControl", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(spotify.can_control());
+                Ok(())
+            })
+    };
+
+    let property_cangonext = {
+        let queue = queue.clone();
+        f.property::<bool, _>("CanGoNext", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(queue.has_next());
+                Ok(())
+            })
+    };
+
+    let property_cangoprevious = {
+        let queue = queue.clone();
+        f.property::<bool, _>("CanGoPrevious", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(can_go_previous(&queue));
+                Ok(())
+            })
+    };
+
+    let property_shuffle = {
+        let queue_get = queue.clone();
+        let queue_set = queue.clone();
+        let ev = ev.clone();
+        f.property::<bool, _>("Shuffle", ())
+            .access(Access::ReadWrite)
+            .on_get(move |iter, _| {
+                iter.append(is_shuffled(&queue_get));
+                Ok(())
+            })
+            .on_set(move |iter, _| {
+                set_shuffle(&queue_set, iter.get());
+                ev.trigger();
+                Ok(())
+            })
+    };
+
+    let property_cangoforward = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.property::<bool, _>("CanGoForward", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(queue_controls(&spotify, &queue).seek);
+                Ok(())
+            })
+    };
+
+    let property_canrewind = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.property::<bool, _>("CanRewind", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(queue_controls(&spotify, &queue).seek);
+                Ok(())
+            })
+    };
+
+    let method_playpause = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.method("PlayPause", (), move |m| {
+            ensure_ready(&spotify)?;
+            queue.toggleplayback();
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_play = {
+        let spotify = spotify.clone();
+        f.method("Play", (), move |m| {
+            ensure_ready(&spotify)?;
+            spotify.play();
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_pause = {
+        let spotify = spotify.clone();
+        f.method("Pause", (), move |m| {
+            ensure_ready(&spotify)?;
+            spotify.pause();
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_stop = {
+        let spotify = spotify.clone();
+        f.method("Stop", (), move |m| {
+            ensure_ready(&spotify)?;
+            spotify.stop();
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_next = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.method("Next", (), move |m| {
+            ensure_ready(&spotify)?;
+            // a skip requested by the user, not the end of the track
+            queue.next(true);
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_previous = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.method("Previous", (), move |m| {
+            ensure_ready(&spotify)?;
+            queue.previous_or_restart();
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_forward = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.method("Forward", (), move |m| {
+            ensure_ready(&spotify)?;
+            queue.seek_forward();
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_rewind = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.method("Rewind", (), move |m| {
+            ensure_ready(&spotify)?;
+            queue.seek_backward();
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_seek = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.method("Seek", (), move |m| {
+            ensure_ready(&spotify)?;
+            // seeking within an item of unknown length would skip it
+            if !queue_controls(&spotify, &queue).seek {
+                return Ok(vec![m.msg.method_return()]);
+            }
+            let offset = m.msg.get1::<i64>().unwrap_or(0); // micros
+            queue.seek_relative(offset / 1000);
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_set_position = {
+        let queue = queue.clone();
+        let spotify = spotify.clone();
+        f.method("SetPosition", (), move |m| {
+            ensure_ready(&spotify)?;
+            if let Some(length_ms) = known_length(queue.get_current().as_ref()) {
+                let (_, position) = m.msg.get2::<Path, i64>(); // micros
+
+                // negative positions are ignored as per the specification
+                if let Some(position) = position.filter(|p| *p >= 0) {
+                    match seek_position(0, position, length_ms) {
+                        Some(position) => spotify.seek(position),
+                        None => queue.next(true),
+                    }
+                }
+            }
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let property_activedevice = {
+        let spotify = spotify.clone();
+        f.property::<(String, bool), _>("ActiveDevice", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                // playback never moves to a remote device
+                iter.append((spotify.device_name(), true));
+                Ok(())
+            })
+    };
+
+    let property_cacheusage = {
+        let cfg = cfg.clone();
+        f.property::<(u64, u64, u64), _>("CacheUsage", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                // audio and cover bytes, and the limit with 0 meaning none
+                let usage = crate::cache::usage();
+                let limit = crate::cache::size_limit(&cfg).unwrap_or(0);
+                iter.append((usage.audio, usage.covers, limit));
+                Ok(())
+            })
+    };
+
+    let method_openuri = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.method("OpenUri", (), move |m| {
+            ensure_ready(&spotify)?;
+            let uri = match m.msg.get1().and_then(spotify_uri) {
+                Some(uri) => uri,
+                None => return Ok(vec![m.msg.method_return()]),
+            };
+            if UriType::is_local(&uri) {
+                return Err(MethodErr::failed("Local files can't be played"));
+            }
+            if UriType::from_uri(&uri) == Some(UriType::Playlist) {
+                open_playlist(&spotify, &queue, &uri);
+                return Ok(vec![m.msg.method_return()]);
+            }
+            let playables = spotify.playables_for_uri(&uri);
+            match (UriType::from_uri(&uri), playables.first()) {
+                (_, None) => {}
+                (Some(UriType::Track), Some(Playable::Track(track))) => {
+                    // play the album the track is on, starting at the track
+                    let context = if cfg.values().open_track_loads_context.unwrap_or(false) {
+                        album_context(&spotify, track)
+                    } else {
+                        None
+                    };
+
+                    queue.clear();
+                    match context {
+                        Some((tracks, position)) => {
+                            let index = queue.append_next(
+                                &tracks.into_iter().map(Playable::Track).collect::<Vec<_>>(),
+                            );
+                            queue.play(index + position, true, false)
+                        }
+                        None => {
+                            queue.append(Playable::Track(track.clone()));
+                            queue.play(0, false, false)
+                        }
+                    }
+                }
+                (Some(UriType::Track), Some(playable))
+                | (Some(UriType::Episode), Some(playable)) => {
+                    queue.clear();
+                    queue.append(playable.clone());
+                    queue.play(0, false, false)
+                }
+                _ => {
+                    let should_shuffle = queue.get_shuffle();
+                    queue.clear();
+                    let index = queue.append_next(&playables);
+                    queue.play(index, should_shuffle, should_shuffle)
+                }
+            }
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    // https://specifications.freedesktop.org/mpris-spec/latest/Player_Interface.html
+    let interface_player = f
+        .interface("org.mpris.MediaPlayer2.Player", ())
+        .add_p(property_playbackstatus)
+        .add_p(property_loopstatus)
+        .add_p(property_metadata)
+        .add_p(property_position)
+        .add_p(property_volume)
+        .add_p(property_rate)
+        .add_p(property_minrate)
+        .add_p(property_maxrate)
+        .add_p(property_canplay)
+        .add_p(property_canpause)
+        .add_p(property_canseek)
+        .add_p(property_cancontrol)
+        .add_p(property_cangonext)
+        .add_p(property_cangoprevious)
+        .add_p(property_shuffle)
+        .add_p(property_cangoforward)
+        .add_p(property_canrewind)
+        .add_m(method_playpause)
+        .add_m(method_play)
+        .add_m(method_pause)
+        .add_m(method_stop)
+        .add_m(method_next)
+        .add_m(method_previous)
+        .add_m(method_forward)
+        .add_m(method_rewind)
+        .add_m(method_seek)
+        .add_m(method_set_position)
+        .add_m(method_openuri)
+        .add_s(f.signal("Seeked", ()));
+
+    let property_tracks = {
+        let queue = queue.clone();
+        f.property::<Vec<Path<'static>>, _>("Tracks", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(get_tracklist(&queue));
+                Ok(())
+            })
+    };
+
+    let property_canedittracks = f
+        .property::<bool, _>("CanEditTracks", ())
+        .access(Access::Read)
+        .on_get(|iter, _| {
+            iter.append(true);
+            Ok(())
+        });
+
+    let method_gettracksmetadata = {
+        let queue = queue.clone();
+        let spotify = spotify.clone();
+        let library = library.clone();
+        let metadata_cache = metadata_cache.clone();
+        f.method("GetTracksMetadata", (), move |m| {
+            let trackids: Vec<Path> = m.msg.get1().unwrap_or_default();
+            let entries = queue.entries();
+            let metadata: Vec<Metadata> = trackids
+                .iter()
+                .filter_map(|trackid| {
+                    let id = trackid_to_entry(trackid)?;
+                    let (_, playable) = entries.iter().find(|(entry_id, _)| *entry_id == id)?;
+                    Some(get_metadata(
+                        Some(playable.clone()),
+                        Some(id),
+                        &spotify,
+                        &*library,
+                        &metadata_cache,
+                        local_art,
+                    ))
+                })
+                .collect();
+            Ok(vec![m.msg.method_return().append1(metadata)])
+        })
+    };
+
+    let method_addtrack = {
+        let queue = queue.clone();
+        let spotify = spotify.clone();
+        f.method("AddTrack", (), move |m| {
+            let (uri, after, set_as_current) = m.msg.get3::<&str, Path, bool>();
+            let uri = uri.unwrap_or_default();
+            let playable = match UriType::from_uri(uri) {
+                Some(UriType::Track | UriType::Episode) => spotify.playables_for_uri(uri).pop(),
+                _ => None,
+            };
+            let playable = match playable {
+                Some(playable) => playable,
+                None => {
+                    warn!("AddTrack: could not resolve uri {}", uri);
+                    return Ok(vec![m.msg.method_return()]);
+                }
+            };
+            let index = match after {
+                Some(after) if &*after == TRACKLIST_NO_TRACK => 0,
+                Some(after) => match find_in_queue(&queue, &after) {
+                    Some(index) => index + 1,
+                    None => {
+                        debug!("AddTrack: {} is not in the queue, appending", after);
+                        queue.len()
+                    }
+                },
+                None => queue.len(),
+            };
+            queue.insert(index, playable);
+            if set_as_current.unwrap_or(false) {
+                queue.play(index, false, false);
+            }
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_removetrack = {
+        let queue = queue.clone();
+        f.method("RemoveTrack", (), move |m| {
+            if let Some(trackid) = m.msg.get1::<Path>() {
+                if let Some(index) = find_in_queue(&queue, &trackid) {
+                    queue.remove(index);
+                }
+            }
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_goto = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.method("GoTo", (), move |m| {
+            ensure_ready(&spotify)?;
+            if let Some(trackid) = m.msg.get1::<Path>() {
+                if let Some(index) = find_in_queue(&queue, &trackid) {
+                    queue.play(index, false, false);
+                }
+            }
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    // https://specifications.freedesktop.org/mpris-spec/latest/Track_List_Interface.html
+    let interface_tracklist = f
+        .interface("org.mpris.MediaPlayer2.TrackList", ())
+        .add_p(property_tracks)
+        .add_p(property_canedittracks)
+        .add_m(method_gettracksmetadata)
+        .add_m(method_addtrack)
+        .add_m(method_removetrack)
+        .add_m(method_goto)
+        .add_s(f.signal("TrackListReplaced", ()))
+        .add_s(f.signal("TrackAdded", ()))
+        .add_s(f.signal("TrackRemoved", ()));
+
+    // Saves the track to the library if the rating is above 0.0 and removes it
+    // otherwise, so only 0.0 and 1.0 are meaningful ratings.
+    let method_setrating = {
+        let queue = queue.clone();
+        let library = library.clone();
+        f.method("SetRating", (), move |m| {
+            let (trackid, rating): (Option<Path>, Option<f64>) = m.msg.get2();
+            if let (Some(trackid), Some(rating)) = (trackid, rating) {
+                let track = trackid_to_entry(&trackid)
+                    .and_then(|id| queue.entries().into_iter().find(|(entry, _)| *entry == id))
+                    .and_then(|(_, playable)| playable.track());
+                match track {
+                    Some(track) if rating > 0.0 => library.save_track(&track),
+                    Some(track) => library.unsave_track(&track),
+                    None => warn!("SetRating: no track in queue with id {}", trackid),
+                }
+            }
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_savecurrent = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        let library = library.clone();
+        let metadata_cache = metadata_cache.clone();
+        f.method("SaveCurrent", (), move |m| {
+            let track = current_track(&queue)?;
+            library.save_track(&track);
+            let metadata = get_current_metadata(
+                Some(Playable::Track(track)),
+                queue.get_current_entry().map(|(id, _)| id),
+                &spotify,
+                &*library,
+                &metadata_cache,
+                local_art,
+            );
+            Ok(vec![
+                m.msg.method_return(),
+                metadata_changed(m.path.get_name(), metadata),
+            ])
+        })
+    };
+
+    let method_unsavecurrent = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        let library = library.clone();
+        let metadata_cache = metadata_cache.clone();
+        f.method("UnsaveCurrent", (), move |m| {
+            let track = current_track(&queue)?;
+            library.unsave_track(&track);
+            let metadata = get_current_metadata(
+                Some(Playable::Track(track)),
+                queue.get_current_entry().map(|(id, _)| id),
+                &spotify,
+                &*library,
+                &metadata_cache,
+                local_art,
+            );
+            Ok(vec![
+                m.msg.method_return(),
+                metadata_changed(m.path.get_name(), metadata),
+            ])
+        })
+    };
+
+    let property_currentcontext = {
+        let queue = queue.clone();
+        f.property::<(String, String), _>("CurrentContext", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(context_value(queue.get_context()));
+                Ok(())
+            })
+    };
+
+    let property_queuelength = {
+        let queue = queue.clone();
+        f.property::<u32, _>("QueueLength", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(queue.len() as u32);
+                Ok(())
+            })
+    };
+
+    let property_skippedunavailable = {
+        let queue = queue.clone();
+        f.property::<u32, _>("SkippedUnavailable", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(queue.skipped_unavailable() as u32);
+                Ok(())
+            })
+    };
+
+    let property_history = {
+        let library = library.clone();
+        f.property::<Vec<(String, i64)>, _>("History", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                let history: Vec<(String, i64)> = library
+                    .history()
+                    .iter()
+                    .map(|entry| (entry.playable.uri(), entry.played_at.timestamp()))
+                    .collect();
+                iter.append(history);
+                Ok(())
+            })
+    };
+
+    let property_queueremaining = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.property::<i64, _>("QueueRemainingUs", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(queue_remaining_us(
+                    queue.get_current().map(|p| p.duration()),
+                    spotify.progress(),
+                    queue.upcoming_duration(),
+                ));
+                Ok(())
+            })
+    };
+
+    let property_crossfade = {
+        let spotify1 = spotify.clone();
+        let spotify2 = spotify.clone();
+        f.property::<u32, _>("CrossfadeMs", ())
+            .access(Access::ReadWrite)
+            .on_get(move |iter, _| {
+                iter.append(spotify1.crossfade_ms());
+                Ok(())
+            })
+            .on_set(move |iter, _| {
+                if let Some(crossfade_ms) = iter.get::<u32>() {
+                    spotify2.set_crossfade_ms(crossfade_ms);
+                }
+                Ok(())
+            })
+    };
+
+    let property_normalization = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.property::<bool, _>("VolumeNormalization", ())
+            .access(Access::ReadWrite)
+            .on_get(move |iter, _| {
+                iter.append(spotify.normalisation());
+                Ok(())
+            })
+            .on_set(move |iter, _| {
+                if let Some(enabled) = iter.get::<bool>() {
+                    queue.set_normalisation(enabled);
+                }
+                Ok(())
+            })
+    };
+
+    let property_sleeptimer = {
+        let spotify = spotify.clone();
+        f.property::<i64, _>("SleepTimerRemainingUs", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                let remaining = spotify.sleep_timer_remaining().unwrap_or_default();
+                iter.append(remaining.as_micros() as i64);
+                Ok(())
+            })
+    };
+
+    let property_chapters = {
+        let queue = queue.clone();
+        f.property::<Vec<(i64, String)>, _>("Chapters", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                let chapters: Vec<(i64, String)> = current_chapters(&queue)
+                    .into_iter()
+                    .map(|(position_ms, title)| (position_ms as i64 * 1000, title))
+                    .collect();
+                iter.append(chapters);
+                Ok(())
+            })
+    };
+
+    let method_seektochapter = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.method("SeekToChapter", (), move |m| {
+            ensure_ready(&spotify)?;
+            let index: u32 = m.msg.read1()?;
+            match current_chapters(&queue).get(index as usize) {
+                Some((position_ms, _)) => spotify.seek(*position_ms),
+                None => return Err(MethodErr::failed(&format!("No chapter {}", index))),
+            }
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_playartistradio = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.method("PlayArtistRadio", (), move |m| {
+            ensure_ready(&spotify)?;
+            let uri = m.msg.read1::<&str>().map(spotify_uri)?;
+            let artist = uri
+                .filter(|uri| UriType::from_uri(uri) == Some(UriType::Artist))
+                .and_then(|uri| spotify.artist(&uri[uri.rfind(':').unwrap_or(0) + 1..]))
+                .ok_or_else(|| MethodErr::failed("Could not resolve artist"))?;
+            let tracks = spotify
+                .recommendations(&artist)
+                .filter(|tracks| !tracks.is_empty())
+                .ok_or_else(|| MethodErr::failed("Could not load artist radio"))?;
+
+            queue.clear();
+            queue.set_context(artist.playback_context().map(|context| PlaybackContext {
+                name: format!("{} Radio", context.name),
+                ..context
+            }));
+            let index =
+                queue.append_next(&tracks.into_iter().map(Playable::Track).collect::<Vec<_>>());
+            queue.play(index, false, false);
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    // Both take an optional step in percent, which defaults to 1%.
+    let method_volumeup = {
+        let spotify = spotify.clone();
+        let ev = ev.clone();
+        f.method("VolumeUp", (), move |m| {
+            ensure_ready(&spotify)?;
+            let percent = m.msg.get1::<u32>().unwrap_or(1) as i64;
+            spotify.set_volume(step_volume(spotify.volume(), percent));
+            ev.trigger();
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_volumedown = {
+        let spotify = spotify.clone();
+        let ev = ev.clone();
+        f.method("VolumeDown", (), move |m| {
+            ensure_ready(&spotify)?;
+            let percent = m.msg.get1::<u32>().unwrap_or(1) as i64;
+            spotify.set_volume(step_volume(spotify.volume(), -percent));
+            ev.trigger();
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_enqueueuri = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.method("EnqueueUri", (), move |m| {
+            if let Some(uri) = m.msg.get1().and_then(spotify_uri) {
+                for playable in spotify.playables_for_uri(&uri) {
+                    queue.append(playable);
+                }
+            }
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    // Both return the new setting, like it is reported by `Shuffle` and `LoopStatus`.
+    let method_toggleshuffle = {
+        let queue = queue.clone();
+        let ev = ev.clone();
+        f.method("ToggleShuffle", (), move |m| {
+            match shuffle_setting(!queue.get_shuffle(), queue.len()) {
+                Some(shuffle) => queue.set_shuffle(shuffle),
+                None => info!("mpris: ignoring ToggleShuffle, the queue is empty"),
+            }
+            ev.trigger();
+            Ok(vec![m.msg.method_return().append1(is_shuffled(&queue))])
+        })
+    };
+
+    let method_restartcontext = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        f.method("RestartContext", (), move |m| {
+            ensure_ready(&spotify)?;
+            if !queue.restart_context() {
+                return Err(MethodErr::failed("No playlist, album or show is playing"));
+            }
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_setsleeptimer = {
+        let spotify = spotify.clone();
+        f.method("SetSleepTimer", (), move |m| {
+            let minutes: u32 = m.msg.read1()?;
+            let duration = (minutes > 0).then(|| Duration::from_secs(minutes as u64 * 60));
+            spotify.set_sleep_timer(duration);
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_movetrack = {
+        let queue = queue.clone();
+        f.method("MoveTrack", (), move |m| {
+            let (from, to): (u32, u32) = m.msg.read2()?;
+            if !queue.move_to(from as usize, to as usize) {
+                return Err(MethodErr::invalid_arg(&"index out of range"));
+            }
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    let method_copycurrenturl = {
+        let queue = queue.clone();
+        f.method("CopyCurrentUrl", (), move |m| {
+            let url = queue
+                .get_current()
+                .and_then(|playable| playable.share_url())
+                .ok_or_else(|| MethodErr::failed(&"Nothing is playing"))?;
+            if !copy_to_clipboard(&url) {
+                info!("could not copy {} to the clipboard", url);
+            }
+            Ok(vec![m.msg.method_return().append1(url)])
+        })
+    };
+
+    let method_cyclerepeat = {
+        let queue = queue.clone();
+        f.method("CycleRepeat", (), move |m| {
+            let repeat = queue.get_repeat().next();
+            queue.set_repeat(repeat);
+            ev.trigger();
+            Ok(vec![m.msg.method_return().append1(loop_status(repeat))])
+        })
+    };
+
+    let method_dumpmetadata = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        let library = library.clone();
+        let metadata_cache = metadata_cache.clone();
+        f.method("DumpMetadata", (), move |m| {
+            let current = queue.get_current_entry();
+            let metadata = get_current_metadata(
+                current.as_ref().map(|(_, playable)| playable.clone()),
+                current.map(|(id, _)| id),
+                &spotify,
+                &*library,
+                &metadata_cache,
+                local_art,
+            );
+            let json = serde_json::to_string_pretty(&metadata_to_json(&metadata))
+                .map_err(|e| MethodErr::failed(&e))?;
+            Ok(vec![m.msg.method_return().append1(json)])
+        })
+    };
+
+    // ncspot specific extensions
+    let interface_ncspot = f
+        .interface("org.ncspot", ())
+        .add_p(property_currentcontext)
+        .add_p(property_queuelength)
+        .add_p(property_queueremaining)
+        .add_p(property_skippedunavailable)
+        .add_p(property_history)
+        .add_p(property_crossfade)
+        .add_p(property_normalization)
+        .add_p(property_sleeptimer)
+        .add_p(property_chapters)
+        .add_p(property_activedevice)
+        .add_p(property_cacheusage)
+        .add_m(method_seektochapter)
+        .add_m(method_enqueueuri)
+        .add_m(method_playartistradio)
+        .add_m(method_volumeup)
+        .add_m(method_volumedown)
+        .add_m(method_setrating)
+        .add_m(method_savecurrent)
+        .add_m(method_unsavecurrent)
+        .add_m(method_toggleshuffle)
+        .add_m(method_cyclerepeat)
+        .add_m(method_restartcontext)
+        .add_m(method_setsleeptimer)
+        .add_m(method_movetrack)
+        .add_m(method_copycurrenturl)
+        .add_m(method_dumpmetadata)
+        .add_s(
+            f.signal("LibraryChanged", ())
+                .sarg::<&str, _>("uri")
+                .sarg::<bool, _>("added"),
+        )
+        .add_s(
+            f.signal("Scrobble", ())
+                .sarg::<&str, _>("uri")
+                .sarg::<i64, _>("listened"),
+        );
+
+    let property_playlistcount = {
+        let library = library.clone();
+        f.property::<u32, _>("PlaylistCount", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                iter.append(library.with_playlists(|playlists| playlists.len() as u32));
+                Ok(())
+            })
+    };
+
+    let property_orderings = f
+        .property::<Vec<String>, _>("Orderings", ())
+        .access(Access::Read)
+        .on_get(|iter, _| {
+            iter.append(PLAYLIST_ORDERINGS.map(String::from).to_vec());
+            Ok(())
+        });
+
+    let property_activeplaylist = {
+        let queue = queue.clone();
+        let library = library.clone();
+        f.property::<(bool, MprisPlaylist), _>("ActivePlaylist", ())
+            .access(Access::Read)
+            .on_get(move |iter, _| {
+                let context = queue.get_context();
+                iter.append(
+                    library
+                        .with_playlists(|playlists| active_playlist(playlists, context.as_ref())),
+                );
+                Ok(())
+            })
+    };
+
+    let method_getplaylists = {
+        let library = library.clone();
+        f.method("GetPlaylists", (), move |m| {
+            let (index, max_count, order, reverse_order) = m.msg.read4::<u32, u32, &str, bool>()?;
+            let playlists = library.with_playlists(|playlists| {
+                get_playlists(playlists, index, max_count, order, reverse_order)
+            });
+            Ok(vec![m.msg.method_return().append1(playlists)])
+        })
+    };
+
+    let method_activateplaylist = {
+        let spotify = spotify.clone();
+        let queue = queue.clone();
+        let library = library.clone();
+        f.method("ActivatePlaylist", (), move |m| {
+            ensure_ready(&spotify)?;
+            let id: Path = m.msg.read1()?;
+            let uri = trackid_to_uri(&id)
+                .filter(|uri| {
+                    library.with_playlists(|playlists| {
+                        playlists.iter().any(|p| p.playback_context().uri == *uri)
+                    })
+                })
+                .ok_or_else(|| MethodErr::invalid_arg(&id))?;
+            open_playlist(&spotify, &queue, &uri);
+            Ok(vec![m.msg.method_return()])
+        })
+    };
+
+    // https://specifications.freedesktop.org/mpris-spec/latest/Playlists_Interface.html
+    let interface_playlists = f
+        .interface("org.mpris.MediaPlayer2.Playlists", ())
+        .add_p(property_playlistcount)
+        .add_p(property_orderings)
+        .add_p(property_activeplaylist)
+        .add_m(method_getplaylists)
+        .add_m(method_activateplaylist)
+        .add_s(f.signal("PlaylistChanged", ()));
+
+    let tree = f.tree(()).add(
+        f.object_path("/org/mpris/MediaPlayer2", ())
+            .introspectable()
+            .add(interface)
+            .add(interface_player)
+            .add(interface_tracklist)
+            .add(interface_playlists)
+            .add(interface_ncspot),
+    );
+
+    if let Err(e) = tree.set_registered(conn, true) {
+        error!("failed to register dbus tree, MPRIS is unavailable: {}", e);
+        return;
+    }
+
+    conn.add_handler(tree);
+    let object_path = Path::new("/org/mpris/MediaPlayer2".to_string()).unwrap();
+    // the state that was broadcast last, to only send the properties that changed
+    let mut last_state: Option<MprisState> = None;
+    let mut listens = ListenTracker::default();
+    loop {
+        if shutdown.load(Ordering::Relaxed) {
+            debug!("mpris server shutting down");
+            return;
+        }
+        if !conn.is_connected() {
+            warn!("lost connection to the session bus");
+            return;
+        }
+
+        let timeout = poll_interval(last_state.as_ref(), idle_interval);
+        if let Some(m) = conn.incoming(timeout).next() {
+            if let Some(reply) = unhandled_reply(&m) {
+                debug!("mpris: unhandled method call: {:?}", m);
+                if conn.send(reply).is_err() {
+                    warn!("could not send dbus message, connection to the session bus lost");
+                    return;
+                }
+            }
+        }
+
+        let current = queue.get_current();
+        let scrobble = listens.sample(
+            current.as_ref().map(Playable::uri),
+            Duration::from_millis(current.as_ref().map_or(0, |p| p.duration()) as u64),
+            spotify.progress(),
+            matches!(spotify.status(), PlayerEvent::Playing(_)),
+            Instant::now(),
+        );
+        if let Some((uri, listened)) = scrobble {
+            debug!("mpris Scrobble: {} after {:?}", uri, listened);
+            let msg = Message::signal(&object_path, &"org.ncspot".into(), &"Scrobble".into())
+                .append2(uri, listened.as_micros() as i64);
+            if conn.send(msg).is_err() {
+                warn!("could not send dbus message, connection to the session bus lost");
+                return;
+            }
+        }
+
+        // no event is sent once the current item has played long enough to be restarted
+        // by `Previous`, so check for it while playing
+        let threshold_crossed = last_state
+            .as_ref()
+            .filter(|s| s.status == "Playing" && !s.can_go_previous)
+            .map_or(false, |_| can_go_previous(&queue))
+            .then(|| MprisCommand::UpdateState(Box::new(get_state(&spotify, &queue))));
+
+        for command in coalesce(rx.try_iter().chain(threshold_crossed)) {
+            let messages = match command {
+                MprisCommand::UpdateState(state) => {
+                    let mut messages = Vec::new();
+
+                    let changed = state.changed_properties(last_state.as_ref());
+                    if !changed.is_empty() {
+                        debug!(
+                            "mpris PropertiesChanged: {:?}, status {}, track: {:?}",
+                            changed, state.status, state.playable
+                        );
+
+                        let mut changed_properties: HashMap<String, Variant<Box<dyn RefArg>>> =
+                            HashMap::new();
+                        for property in changed {
+                            let value: Box<dyn RefArg> = match property {
+                                "Metadata" => {
+                                    // the previous playable is no longer polled, drop it from the cache
+                                    if let Some(id) = last_state
+                                        .as_ref()
+                                        .and_then(|s| s.playable.as_ref())
+                                        .and_then(Playable::id)
+                                    {
+                                        metadata_cache.lock().unwrap().remove(&id);
+                                    }
+                                    Box::new(get_current_metadata(
+                                        state.playable.clone(),
+                                        state.entry_id,
+                                        &spotify,
+                                        &*library,
+                                        &metadata_cache,
+                                        local_art,
+                                    ))
+                                }
+                                "PlaybackStatus" => Box::new(state.status.clone()),
+                                "Volume" => Box::new(state.volume),
+                                "Rate" => Box::new(state.rate),
+                                "LoopStatus" => Box::new(loop_status(state.repeat).to_string()),
+                                "Shuffle" => Box::new(state.shuffle),
+                                "CanGoNext" => Box::new(state.can_go_next),
+                                "CanGoPrevious" => Box::new(state.can_go_previous),
+                                "CanControl" => Box::new(state.controls.control),
+                                "CanPlay" => Box::new(state.controls.play),
+                                "CanPause" => Box::new(state.controls.pause),
+                                "CanSeek" => Box::new(state.controls.seek),
+                                _ => unreachable!("unknown property {}", property),
+                            };
+                            changed_properties.insert(property.to_string(), Variant(value));
+                        }
+                        messages.push(properties_changed(
+                            &object_path,
+                            "org.mpris.MediaPlayer2.Player",
+                            changed_properties,
+                        ));
+                    }
+
+                    if last_state.as_ref().map_or(true, |s| {
+                        s.queue_length != state.queue_length
+                            || s.upcoming_ms != state.upcoming_ms
+                            || s.playable.as_ref().map(Playable::uri)
+                                != state.playable.as_ref().map(Playable::uri)
+                    }) {
+                        let remaining = queue_remaining_us(
+                            state.playable.as_ref().map(Playable::duration),
+                            spotify.progress(),
+                            state.upcoming_ms,
+                        );
+                        debug!(
+                            "mpris queue changed: {} items, {}us remaining",
+                            state.queue_length, remaining
+                        );
+                        let mut changed_properties: HashMap<String, Variant<Box<dyn RefArg>>> =
+                            HashMap::new();
+                        changed_properties.insert(
+                            "QueueLength".to_string(),
+                            Variant(Box::new(state.queue_length as u32)),
+                        );
+                        changed_properties
+                            .insert("QueueRemainingUs".to_string(), Variant(Box::new(remaining)));
+                        messages.push(properties_changed(
+                            &object_path,
+                            "org.ncspot",
+                            changed_properties,
+                        ));
+                    }
+
+                    if last_state
+                        .as_ref()
+                        .map_or(true, |s| s.crossfade_ms != state.crossfade_ms)
+                    {
+                        let mut changed_properties: HashMap<String, Variant<Box<dyn RefArg>>> =
+                            HashMap::new();
+                        changed_properties.insert(
+                            "CrossfadeMs".to_string(),
+                            Variant(Box::new(state.crossfade_ms)),
+                        );
+                        messages.push(properties_changed(
+                            &object_path,
+                            "org.ncspot",
+                            changed_properties,
+                        ));
+                    }
+
+                    if last_state
+                        .as_ref()
+                        .map_or(true, |s| s.device != state.device)
+                    {
+                        debug!("mpris ActiveDevice changed: {}", state.device);
+                        let mut changed_properties: HashMap<String, Variant<Box<dyn RefArg>>> =
+                            HashMap::new();
+                        changed_properties.insert(
+                            "ActiveDevice".to_string(),
+                            Variant(Box::new((state.device.clone(), true))),
+                        );
+                        messages.push(properties_changed(
+                            &object_path,
+                            "org.ncspot",
+                            changed_properties,
+                        ));
+                    }
+
+                    if last_state
+                        .as_ref()
+                        .map_or(true, |s| s.context != state.context)
+                    {
+                        debug!("mpris CurrentContext changed: {:?}", state.context);
+                        let mut changed_properties: HashMap<String, Variant<Box<dyn RefArg>>> =
+                            HashMap::new();
+                        changed_properties.insert(
+                            "CurrentContext".to_string(),
+                            Variant(Box::new(context_value(state.context.clone()))),
+                        );
+                        messages.push(properties_changed(
+                            &object_path,
+                            "org.ncspot",
+                            changed_properties,
+                        ));
+
+                        let mut changed_properties: HashMap<String, Variant<Box<dyn RefArg>>> =
+                            HashMap::new();
+                        changed_properties.insert(
+                            "ActivePlaylist".to_string(),
+                            Variant(Box::new(library.with_playlists(|playlists| {
+                                active_playlist(playlists, state.context.as_ref())
+                            }))),
+                        );
+                        messages.push(properties_changed(
+                            &object_path,
+                            "org.mpris.MediaPlayer2.Playlists",
+                            changed_properties,
+                        ));
+                    }
+
+                    last_state = Some(*state);
+                    messages
+                }
+                MprisCommand::UpdateTrackList(change) => {
+                    debug!("mpris TrackList change: {:?}", change);
+                    match change {
+                        QueueChange::TrackAdded { track, id, after } => {
+                            let metadata = get_metadata(
+                                Some(*track),
+                                Some(id),
+                                &spotify,
+                                &*library,
+                                &metadata_cache,
+                                local_art,
+                            );
+                            vec![tracklist_signal(&object_path, "TrackAdded")
+                                .append2(metadata, get_trackid(after))]
+                        }
+                        QueueChange::TrackRemoved(id) => {
+                            vec![tracklist_signal(&object_path, "TrackRemoved")
+                                .append1(get_trackid(Some(id)))]
+                        }
+                        QueueChange::Replaced => {
+                            let current = get_trackid(queue.get_current_entry().map(|(id, _)| id));
+                            vec![tracklist_signal(&object_path, "TrackListReplaced")
+                                .append2(get_tracklist(&queue), current)]
+                        }
+                    }
+                }
+                MprisCommand::Seeked(position_ms) => {
+                    debug!("mpris Seeked: {}ms", position_ms);
+                    vec![Message::signal(
+                        &object_path,
+                        &"org.mpris.MediaPlayer2.Player".into(),
+                        &"Seeked".into(),
+                    )
+                    .append1(position_ms as i64 * 1000)]
+                }
+                MprisCommand::RefreshMetadata => {
+                    match last_state.as_ref().and_then(|s| s.playable.clone()) {
+                        Some(playable) => {
+                            debug!("mpris Metadata refreshed: {:?}", playable);
+                            let metadata = get_current_metadata(
+                                Some(playable),
+                                last_state.as_ref().and_then(|s| s.entry_id),
+                                &spotify,
+                                &*library,
+                                &metadata_cache,
+                                local_art,
+                            );
+                            let mut changed_properties: HashMap<String, Variant<Box<dyn RefArg>>> =
+                                HashMap::new();
+                            changed_properties
+                                .insert("Metadata".to_string(), Variant(Box::new(metadata)));
+                            vec![properties_changed(
+                                &object_path,
+                                "org.mpris.MediaPlayer2.Player",
+                                changed_properties,
+                            )]
+                        }
+                        None => Vec::new(),
+                    }
+                }
+                MprisCommand::LibraryChanged(change) => {
+                    debug!("mpris LibraryChanged: {:?}", change);
+                    let (uri, added) = match change {
+                        LibraryChange::Added(uri) => (uri, true),
+                        LibraryChange::Removed(uri) => (uri, false),
+                    };
+                    vec![Message::signal(
+                        &object_path,
+                        &"org.ncspot".into(),
+                        &"LibraryChanged".into(),
+                    )
+                    .append2(uri, added)]
+                }
+                MprisCommand::PlaylistChanged(playlist) => {
+                    debug!("mpris PlaylistChanged: {}", playlist.id);
+                    vec![Message::signal(
+                        &object_path,
+                        &"org.mpris.MediaPlayer2.Playlists".into(),
+                        &"PlaylistChanged".into(),
+                    )
+                    .append1(mpris_playlist(&playlist))]
+                }
+            };
+
+            for msg in messages {
+                if conn.send(msg).is_err() {
+                    warn!("could not send dbus message, connection to the session bus lost");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Merges all state updates among the pending commands into the most recent
+/// one, so that a burst of updates (e.g. while seeking) results in a single
+/// `PropertiesChanged` signal. Other commands are kept in order.
+fn coalesce(commands: impl Iterator<Item = MprisCommand>) -> Vec<MprisCommand> {
+    let mut coalesced: Vec<MprisCommand> = Vec::new();
+    for command in commands {
+        if let MprisCommand::UpdateState(_) = command {
+            coalesced.retain(|c| !matches!(c, MprisCommand::UpdateState(_)));
+        }
+        coalesced.push(command);
+    }
+    coalesced
+}
+
+/// Error reply to a method call that isn't handled by the tree, e.g. one for
+/// another object path, so that the caller doesn't wait for a reply until it
+/// times out. Other messages, like signals, need no reply.
+fn unhandled_reply(msg: &Message) -> Option<Message> {
+    if msg.msg_type() != MessageType::MethodCall || msg.get_no_reply() {
+        return None;
+    }
+    let description = format!(
+        "{} is not supported on {}",
+        msg.member().as_deref().unwrap_or_default(),
+        msg.path().as_deref().unwrap_or_default()
+    );
+    Some(msg.error(
+        &"org.freedesktop.DBus.Error.UnknownMethod".into(),
+        &std::ffi::CString::new(description).ok()?,
+    ))
+}
+
+/// Connects to the session bus again after the connection was lost, waiting
+/// increasingly longer between failed attempts. Gives up with `None` once
+/// `shutdown` is set, the waiting thread is unparked to notice it right away.
+fn reconnect(
+    instance: Option<&str>,
+    shutdown: &AtomicBool,
+) -> Option<(dbus::ffidisp::Connection, String)> {
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        std::thread::park_timeout(backoff);
+        if shutdown.load(Ordering::Relaxed) {
+            return None;
+        }
+        match connect(instance) {
+            Ok(connection) => {
+                info!("reconnected to the session bus");
+                return Some(connection);
+            }
+            Err(e) => {
+                warn!("could not reconnect to the session bus: {}", e);
+                backoff = std::cmp::min(backoff * 2, MAX_RECONNECT_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Name and URI of the playback context, both empty if there is none.
+fn context_value(context: Option<PlaybackContext>) -> (String, String) {
+    context
+        .map(|context| (context.name, context.uri))
+        .unwrap_or_default()
+}
+
+/// A playlist as represented by the MPRIS `Playlists` interface: its id, name
+/// and icon.
+type MprisPlaylist = (Path<'static>, String, String);
+
+/// Orderings supported by `GetPlaylists`, user defined is the library order.
+const PLAYLIST_ORDERINGS: [&str; 2] = ["Alphabetical", "UserDefined"];
+
+fn mpris_playlist(playlist: &Playlist) -> MprisPlaylist {
+    let id = uri_to_trackid(&playlist.playback_context().uri).unwrap_or_else(|| Path::from("/"));
+    (id, playlist.name.clone(), String::new())
+}
+
+/// Returns the page of `playlists` requested by `GetPlaylists`.
+fn get_playlists(
+    playlists: &[Playlist],
+    index: u32,
+    max_count: u32,
+    order: &str,
+    reverse_order: bool,
+) -> Vec<MprisPlaylist> {
+    let mut playlists: Vec<&Playlist> = playlists.iter().collect();
+    if order == "Alphabetical" {
+        playlists.sort_by_cached_key(|p| p.name.to_lowercase());
+    }
+    if reverse_order {
+        playlists.reverse();
+    }
+    playlists
+        .into_iter()
+        .skip(index as usize)
+        .take(max_count as usize)
+        .map(mpris_playlist)
+        .collect()
+}
+
+/// Value of the `ActivePlaylist` property, the playlist the queue is playing
+/// from if it is part of the library.
+fn active_playlist(
+    playlists: &[Playlist],
+    context: Option<&PlaybackContext>,
+) -> (bool, MprisPlaylist) {
+    context
+        .and_then(|context| {
+            playlists
+                .iter()
+                .find(|p| p.playback_context().uri == context.uri)
+        })
+        .map(|playlist| (true, mpris_playlist(playlist)))
+        .unwrap_or_else(|| (false, (Path::from("/"), String::new(), String::new())))
+}
+
+/// Returns the chapters of the currently playing episode, if any.
+fn current_chapters(queue: &Queue) -> Vec<(u32, String)> {
+    match queue.get_current() {
+        Some(Playable::Episode(episode)) => episode.chapters(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the currently playing track, or an error reply if there is none.
+fn current_track(queue: &Queue) -> Result<Track, MethodErr> {
+    match queue.get_current() {
+        Some(Playable::Track(track)) => Ok(track),
+        Some(Playable::Episode(_)) => Err(MethodErr::failed("Current item is not a track")),
+        None => Err(MethodErr::failed("Nothing is playing")),
+    }
+}
+
+fn metadata_changed(path: &Path<'static>, metadata: Metadata) -> Message {
+    let mut changed_properties: HashMap<String, Variant<Box<dyn RefArg>>> = HashMap::new();
+    changed_properties.insert("Metadata".to_string(), Variant(Box::new(metadata)));
+    properties_changed(path, "org.mpris.MediaPlayer2.Player", changed_properties)
+}
+
+fn properties_changed(
+    path: &Path<'static>,
+    interface: &str,
+    changed_properties: HashMap<String, Variant<Box<dyn RefArg>>>,
+) -> Message {
+    PropertiesPropertiesChanged {
+        interface_name: interface.to_string(),
+        changed_properties,
+        invalidated_properties: Vec::new(),
+    }
+    .to_emit_message(path)
+}
+
+fn tracklist_signal(path: &Path<'static>, member: &str) -> Message {
+    Message::signal(
+        path,
+        &"org.mpris.MediaPlayer2.TrackList".into(),
+        &member.into(),
+    )
+}
+
+pub struct MprisManager {
+    /// `None` if the MPRIS server couldn't be started, e.g. without a session bus
+    tx: Option<mpsc::Sender<MprisCommand>>,
+    queue: Arc<Queue>,
+    spotify: Spotify,
+    library: Arc<Library>,
+    cfg: Arc<Config>,
+    /// Shared with the server thread, so dumps report what the `Metadata` property does
+    metadata_cache: Arc<PlayableCache>,
+    /// Whether the cover screen is shown
+    fullscreen: Arc<AtomicBool>,
+    /// Set to stop the server thread when the manager is dropped
+    shutdown: Arc<AtomicBool>,
+    /// Unique bus name of the server's current connection, to wake it up on shutdown
+    unique_name: Arc<Mutex<Option<String>>>,
+    /// Disconnected once the server thread has ended
+    stopped: mpsc::Receiver<()>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl MprisManager {
+    pub fn new(
+        ev: EventManager,
+        spotify: Spotify,
+        queue: Arc<Queue>,
+        library: Arc<Library>,
+        cfg: Arc<Config>,
+        instance: Option<String>,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel::<MprisCommand>();
+        let instance = instance.or_else(|| cfg.values().mpris_name.clone());
+        let (ready_tx, ready_rx) = mpsc::channel::<bool>();
+        let fullscreen = Arc::new(AtomicBool::new(false));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let unique_name = Arc::new(Mutex::new(None));
+        let (stopped_tx, stopped_rx) = mpsc::channel::<()>();
+        // kept across reconnects, as are the pending commands in `rx`
+        let metadata_cache: Arc<PlayableCache> = Arc::new(Mutex::new(MetadataCache::new(
+            cfg.values()
+                .mpris_cache_size
+                .unwrap_or(DEFAULT_METADATA_CACHE_SIZE),
+        )));
+
+        let thread = {
+            let spotify = spotify.clone();
+            let queue = queue.clone();
+            let library = library.clone();
+            let cfg = cfg.clone();
+            let metadata_cache = metadata_cache.clone();
+            let fullscreen = fullscreen.clone();
+            let shutdown = shutdown.clone();
+            let unique_name = unique_name.clone();
+            std::thread::spawn(move || {
+                // disconnects `stopped_rx` once the thread ends, even if it panics
+                let _stopped = stopped_tx;
+                match connect(instance.as_deref()) {
+                    Ok((mut conn, mut name)) => {
+                        ready_tx.send(true).ok();
+                        loop {
+                            *unique_name.lock().unwrap() = Some(conn.unique_name());
+                            run_dbus_server(
+                                &conn,
+                                ev.clone(),
+                                spotify.clone(),
+                                queue.clone(),
+                                library.clone(),
+                                cfg.clone(),
+                                &rx,
+                                metadata_cache.clone(),
+                                fullscreen.clone(),
+                                &shutdown,
+                            );
+                            if shutdown.load(Ordering::Relaxed) {
+                                if let Err(e) = conn.release_name(&name) {
+                                    debug!("could not release {}: {}", name, e);
+                                }
+                                break;
+                            }
+                            match reconnect(instance.as_deref(), &shutdown) {
+                                Some(connection) => (conn, name) = connection,
+                                None => break,
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        warn!(
+                            "could not connect to the session bus, MPRIS is unavailable: {}",
+                            e
+                        );
+                        ready_tx.send(false).ok();
+                    }
+                }
+            })
+        };
+
+        let tx = ready_rx.recv().unwrap_or(false).then_some(tx);
+        MprisManager {
+            tx,
+            queue,
+            spotify,
+            library,
+            cfg,
+            metadata_cache,
+            fullscreen,
+            shutdown,
+            unique_name,
+            stopped: stopped_rx,
+            thread: Some(thread),
+        }
+    }
+
+    fn send(&self, command: MprisCommand) {
+        if let Some(tx) = &self.tx {
+            if tx.send(command).is_err() {
+                debug!("mpris server is not running anymore");
+            }
+        }
+    }
+
+    pub fn update(&self) {
+        let state = get_state(&self.spotify, &self.queue);
+        self.send(MprisCommand::UpdateState(Box::new(state)));
+    }
+
+    pub fn seeked(&self, position_ms: u32) {
+        self.send(MprisCommand::Seeked(position_ms));
+        // seeking may cross the threshold for restarting the current item
+        self.update();
+    }
+
+    /// Returns the metadata of the current item as JSON, as it is reported by
+    /// the `Metadata` property.
+    pub fn dump_metadata(&self) -> serde_json::Value {
+        let current = self.queue.get_current_entry();
+        let metadata = get_current_metadata(
+            current.as_ref().map(|(_, playable)| playable.clone()),
+            current.map(|(id, _)| id),
+            &self.spotify,
+            &*self.library,
+            &self.metadata_cache,
+            self.cfg.values().mpris_local_art.unwrap_or(false),
+        );
+        metadata_to_json(&metadata)
+    }
+
+    pub fn screen_changed(&self, screen: &str) {
+        self.fullscreen.store(screen == "cover", Ordering::Relaxed);
+    }
+
+    /// Sends the metadata of the current item again, e.g. once its rating is known.
+    pub fn refresh_metadata(&self) {
+        self.send(MprisCommand::RefreshMetadata);
+    }
+
+    pub fn library_changed(&self, change: LibraryChange) {
+        let uri = match &change {
+            LibraryChange::Added(uri) | LibraryChange::Removed(uri) => uri.clone(),
+        };
+        self.send(MprisCommand::LibraryChanged(change));
+        // the rating of the current track depends on whether it is saved
+        if self.queue.get_current().map(|p| p.uri()) == Some(uri) {
+            self.refresh_metadata();
+        }
+    }
+
+    pub fn playlist_changed(&self, playlist: Playlist) {
+        self.send(MprisCommand::PlaylistChanged(playlist));
+    }
+
+    pub fn update_tracklist(&self, change: QueueChange) {
+        self.send(MprisCommand::UpdateTrackList(change));
+        // the position in the queue determines whether there is a next or previous item
+        self.update();
+    }
+}
+
+/// Interrupts the server's wait for dbus messages by pinging its connection.
+fn wake_server(unique_name: &str) {
+    let ping = dbus::blocking::Connection::new_session().and_then(|conn| {
+        let mut msg =
+            Message::new_method_call(unique_name, "/", "org.freedesktop.DBus.Peer", "Ping")
+                .map_err(|e| dbus::Error::new_failed(&e))?;
+        msg.set_no_reply(true);
+        conn.channel()
+            .send(msg)
+            .map_err(|_| dbus::Error::new_failed("could not send ping"))?;
+        conn.channel().flush();
+        Ok(())
+    });
+    if let Err(e) = ping {
+        debug!("could not wake up the mpris server: {}", e);
+    }
+}
+
+impl Drop for MprisManager {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            // it only checks for the shutdown between waiting to reconnect, which
+            // is interrupted by unparking it, and waiting for dbus messages
+            thread.thread().unpark();
+            if let Some(name) = self.unique_name.lock().unwrap().take() {
+                wake_server(&name);
+            }
+            // a method call may still be handled, e.g. one waiting for the Web API
+            match self.stopped.recv_timeout(SHUTDOWN_TIMEOUT) {
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    debug!("mpris server thread is still busy, not waiting for it");
+                }
+                _ => {
+                    if thread.join().is_err() {
+                        error!("the mpris server thread panicked");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant, SystemTime};
+
+    use super::{
+        active_playlist, bus_name, coalesce, ensure_ready, get_controls, get_current_metadata,
+        get_metadata, get_playlists, get_state, get_trackid, is_shuffled, iso_date, loop_status,
+        metadata_to_json, mpris_volume, normalize_uri, parse_open_spotify_url, playback_status,
+        player_volume, poll_interval, queue_controls, queue_remaining_us, refarg_to_json,
+        repeat_setting, seek_position, set_loop_status, set_shuffle, shuffle_setting, step_volume,
+        trackid_to_entry, trackid_to_uri, unhandled_reply, uri_to_trackid, Controls, LibraryState,
+        ListenTracker, LruCache, Metadata, MetadataCache, MetadataSource, MprisCommand,
+        MprisLibrary, MprisPlaylist, MprisState, PlaybackControl, Player, TrackFeatures,
+        TRACKLIST_NO_TRACK,
+    };
+    use crate::model::artist::Artist;
+    use crate::model::episode::Episode;
+    use crate::model::history::HistoryEntry;
+    use crate::model::playable::Playable;
+    use crate::model::playlist::Playlist;
+    use crate::model::track::Track;
+    use crate::queue::tests::{track, Fixture};
+    use crate::queue::QueueChange;
+    use crate::queue::{PlaybackContext, RepeatSetting};
+    use crate::spotify::{PlayerEvent, VOLUME_PERCENT};
+    use crate::ui::pagination::ApiResult;
+    use chrono::{DateTime, Utc};
+    use dbus::arg::Variant;
+    use dbus::message::MessageType;
+    use dbus::strings::Path;
+    use dbus::Message;
+
+    /// Stands in for the player and Web API, counting the lookups of full tracks
+    struct FakePlayer {
+        status: PlayerEvent,
+        lookups: Cell<usize>,
+        rate_limited: bool,
+    }
+
+    impl PlaybackControl for FakePlayer {
+        fn status(&self) -> PlayerEvent {
+            self.status.clone()
+        }
+
+        fn bitrate(&self) -> u32 {
+            320
+        }
+
+        fn can_control(&self) -> bool {
+            true
+        }
+    }
+
+    impl MetadataSource for FakePlayer {
+        fn full_playable(&self, playable: &Playable, _id: &str) -> Option<Playable> {
+            self.lookups.set(self.lookups.get() + 1);
+            Some(match playable.clone() {
+                Playable::Track(mut track) => {
+                    track.cover_url = Some("https://i.scdn.co/image/cover".to_string());
+                    track.popularity = Some(77);
+                    Playable::Track(track)
+                }
+                Playable::Episode(mut episode) => {
+                    episode.show_name = Some("Show".to_string());
+                    episode.duration = 3_600_000;
+                    Playable::Episode(episode)
+                }
+            })
+        }
+
+        fn audio_features(&self, id: &str) -> Option<TrackFeatures> {
+            self.lookups.set(self.lookups.get() + 1);
+            // tracks that weren't analyzed
+            (id != "0000000000000000000000").then_some(TrackFeatures {
+                energy: 0.5,
+                tempo: 113.0,
+            })
+        }
+
+        fn rate_limited(&self) -> bool {
+            self.rate_limited
+        }
+
+        fn lyrics(&self, track: &Track) -> Option<String> {
+            (!track.artists.is_empty()).then(|| "We're no strangers to love".to_string())
+        }
+    }
+
+    /// A player whose session is being restarted
+    struct Restarting;
+
+    impl PlaybackControl for Restarting {
+        fn status(&self) -> PlayerEvent {
+            PlayerEvent::Paused(Duration::from_secs(10))
+        }
+
+        fn bitrate(&self) -> u32 {
+            0
+        }
+
+        fn can_control(&self) -> bool {
+            false
+        }
+    }
+
+    struct FakeLibrary(bool);
+
+    impl LibraryState for FakeLibrary {
+        fn is_saved(&self, _playable: &Playable) -> bool {
+            self.0
+        }
+
+        fn last_played(&self, _playable: &Playable) -> Option<DateTime<Utc>> {
+            None
+        }
+    }
+
+    /// Stands in for `Spotify` when serving MPRIS, keeping track of the
+    /// volume and recording the playback commands it receives.
+    #[derive(Clone)]
+    pub(super) struct StubPlayer {
+        pub status: Arc<Mutex<PlayerEvent>>,
+        pub volume: Arc<Mutex<u16>>,
+        pub crossfade_ms: Arc<Mutex<u32>>,
+        pub commands: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl StubPlayer {
+        pub fn new(status: PlayerEvent) -> StubPlayer {
+            StubPlayer {
+                status: Arc::new(Mutex::new(status)),
+                volume: Arc::new(Mutex::new(u16::MAX / 2)),
+                crossfade_ms: Arc::new(Mutex::new(0)),
+                commands: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+
+        fn record(&self, command: &'static str) {
+            self.commands.lock().unwrap().push(command);
+        }
+    }
+
+    impl PlaybackControl for StubPlayer {
+        fn status(&self) -> PlayerEvent {
+            self.status.lock().unwrap().clone()
+        }
+
+        fn bitrate(&self) -> u32 {
+            320
+        }
+
+        fn can_control(&self) -> bool {
+            true
+        }
+    }
+
+    impl MetadataSource for StubPlayer {
+        fn full_playable(&self, _playable: &Playable, _id: &str) -> Option<Playable> {
+            None
+        }
+
+        fn audio_features(&self, _id: &str) -> Option<TrackFeatures> {
+            None
+        }
+
+        fn rate_limited(&self) -> bool {
+            false
+        }
+    }
+
+    impl Player for StubPlayer {
+        fn volume(&self) -> u16 {
+            *self.volume.lock().unwrap()
+        }
+
+        fn set_volume(&self, volume: u16) {
+            *self.volume.lock().unwrap() = volume;
+        }
+
+        fn progress(&self) -> Duration {
+            Duration::ZERO
+        }
+
+        fn play(&self) {
+            self.record("play");
+        }
+
+        fn pause(&self) {
+            self.record("pause");
+        }
+
+        fn stop(&self) {
+            self.record("stop");
+        }
+
+        fn seek(&self, _position_ms: u32) {
+            self.record("seek");
+        }
+
+        fn playback_rate(&self) -> f64 {
+            1.0
+        }
+
+        fn normalisation(&self) -> bool {
+            false
+        }
+
+        fn crossfade_ms(&self) -> u32 {
+            *self.crossfade_ms.lock().unwrap()
+        }
+
+        fn set_crossfade_ms(&self, crossfade_ms: u32) {
+            *self.crossfade_ms.lock().unwrap() = crossfade_ms;
+        }
+
+        fn device_name(&self) -> String {
+            "stub".to_string()
+        }
+
+        fn sleep_timer_remaining(&self) -> Option<Duration> {
+            None
+        }
+
+        fn set_sleep_timer(&self, _duration: Option<Duration>) {}
+
+        fn playables_for_uri(&self, uri: &str) -> Vec<Playable> {
+            uri.strip_prefix("spotify:track:")
+                .map(|id| vec![Playable::Track(Track::with_id(id))])
+                .unwrap_or_default()
+        }
+
+        fn playlist(&self, _id: &str) -> Option<(PlaybackContext, ApiResult<Playable>)> {
+            None
+        }
+
+        fn album_tracks(&self, _album_id: &str) -> Option<Vec<Track>> {
+            None
+        }
+
+        fn artist(&self, _id: &str) -> Option<Artist> {
+            None
+        }
+
+        fn recommendations(&self, _artist: &Artist) -> Option<Vec<Track>> {
+            None
+        }
+    }
+
+    /// Stands in for `Library` when serving MPRIS, remembering the URIs of
+    /// the tracks saved through it.
+    #[derive(Default)]
+    pub(super) struct StubLibrary {
+        pub playlists: Vec<Playlist>,
+        pub saved: Mutex<Vec<String>>,
+    }
+
+    impl LibraryState for StubLibrary {
+        fn is_saved(&self, playable: &Playable) -> bool {
+            self.saved.lock().unwrap().contains(&playable.uri())
+        }
+
+        fn last_played(&self, _playable: &Playable) -> Option<DateTime<Utc>> {
+            None
+        }
+    }
+
+    impl MprisLibrary for StubLibrary {
+        fn with_playlists<T>(&self, f: impl FnOnce(&[Playlist]) -> T) -> T {
+            f(&self.playlists)
+        }
+
+        fn history(&self) -> Vec<HistoryEntry> {
+            Vec::new()
+        }
+
+        fn save_track(&self, track: &Track) {
+            self.saved.lock().unwrap().push(track.uri.clone());
+        }
+
+        fn unsave_track(&self, track: &Track) {
+            self.saved.lock().unwrap().retain(|uri| *uri != track.uri);
+        }
+    }
+
+    fn simplified_track() -> Track {
+        Track {
+            title: "Never Gonna Give You Up".to_string(),
+            artists: vec!["Rick Astley".to_string()],
+            ..Track::with_id("4uLU6hMCjMI75M1A2tKUQC")
+        }
+    }
+
+    pub(super) fn playlist(id: &str, name: &str) -> Playlist {
+        Playlist {
+            id: id.to_string(),
+            name: name.to_string(),
+            owner_id: String::new(),
+            owner_name: None,
+            snapshot_id: String::new(),
+            num_tracks: 0,
+            tracks: None,
+            collaborative: false,
+        }
+    }
+
+    #[test]
+    fn test_get_playlists() {
+        let playlists = [
+            playlist("b", "beta"),
+            playlist("a", "Alpha"),
+            playlist("c", "gamma"),
+        ];
+        let names = |playlists: Vec<MprisPlaylist>| -> Vec<String> {
+            playlists.into_iter().map(|(_, name, _)| name).collect()
+        };
+
+        assert_eq!(
+            names(get_playlists(&playlists, 0, 10, "UserDefined", false)),
+            ["beta", "Alpha", "gamma"]
+        );
+        assert_eq!(
+            names(get_playlists(&playlists, 0, 10, "Alphabetical", false)),
+            ["Alpha", "beta", "gamma"]
+        );
+        assert_eq!(
+            names(get_playlists(&playlists, 1, 1, "Alphabetical", true)),
+            ["beta"]
+        );
+        assert_eq!(
+            get_playlists(&playlists, 0, 1, "UserDefined", false)[0].0,
+            Path::from("/org/ncspot/spotify/playlist/b")
+        );
+    }
+
+    #[test]
+    fn test_active_playlist() {
+        let playlists = [playlist("a", "Alpha")];
+        let context = |uri: &str| PlaybackContext {
+            name: "Alpha".to_string(),
+            uri: uri.to_string(),
+        };
+
+        let (valid, (id, name, _)) =
+            active_playlist(&playlists, Some(&context("spotify:playlist:a")));
+        assert!(valid);
+        assert_eq!(id, Path::from("/org/ncspot/spotify/playlist/a"));
+        assert_eq!(name, "Alpha");
+
+        assert!(!active_playlist(&playlists, Some(&context("spotify:playlist:b"))).0);
+        assert!(!active_playlist(&playlists, Some(&context("spotify:album:a"))).0);
+        assert!(!active_playlist(&playlists, None).0);
+    }
+
+    #[test]
+    fn test_bus_name() {
+        assert_eq!(bus_name(None), "org.mpris.MediaPlayer2.ncspot");
+        assert_eq!(bus_name(Some("")), "org.mpris.MediaPlayer2.ncspot");
+        assert_eq!(bus_name(Some("work")), "org.mpris.MediaPlayer2.ncspot.work");
+        assert_eq!(
+            bus_name(Some("my podcasts")),
+            "org.mpris.MediaPlayer2.ncspot.my_podcasts"
+        );
+        assert_eq!(bus_name(Some("2nd")), "org.mpris.MediaPlayer2.ncspot._2nd");
+    }
+
+    #[test]
+    fn test_loop_status() {
+        for setting in [
+            RepeatSetting::None,
+            RepeatSetting::RepeatTrack,
+            RepeatSetting::RepeatPlaylist,
+        ] {
+            assert_eq!(repeat_setting(loop_status(setting)), Some(setting));
+        }
+
+        assert_eq!(repeat_setting("Track"), Some(RepeatSetting::RepeatTrack));
+        assert_eq!(repeat_setting("track"), None);
+        assert_eq!(repeat_setting(""), None);
+
+        // the setters leave the other setting alone, whichever is set first
+        let fixture = Fixture::new();
+        let queue = &*fixture.queue;
+        queue.append(track("first"));
+        queue.append(track("second"));
+        for repeat in [
+            RepeatSetting::None,
+            RepeatSetting::RepeatTrack,
+            RepeatSetting::RepeatPlaylist,
+        ] {
+            for shuffle in [false, true] {
+                set_loop_status(queue, Some(loop_status(repeat)));
+                set_shuffle(queue, Some(shuffle));
+                assert_eq!(queue.get_repeat(), repeat);
+                assert_eq!(queue.get_shuffle(), shuffle);
+
+                set_shuffle(queue, Some(!shuffle));
+                set_loop_status(queue, Some(loop_status(repeat)));
+                assert_eq!(queue.get_repeat(), repeat);
+                assert_eq!(queue.get_shuffle(), !shuffle);
+                assert_eq!(is_shuffled(queue), !shuffle);
+            }
+        }
+
+        // invalid values are ignored
+        set_loop_status(queue, Some("track"));
+        set_loop_status(queue, None);
+        set_shuffle(queue, None);
+        assert_eq!(queue.get_repeat(), RepeatSetting::RepeatPlaylist);
+        assert!(!queue.get_shuffle());
+    }
+
+    #[test]
+    fn test_seek_position() {
+        assert_eq!(seek_position(10_000, 5_000_000, 60_000), Some(15_000));
+        assert_eq!(seek_position(10_000, -20_000_000, 60_000), Some(0));
+        assert_eq!(seek_position(50_000, 10_000_000, 60_000), None);
+        assert_eq!(seek_position(0, 60_000_000, 60_000), None);
+
+        // offsets that don't fit into 32 bits as milliseconds
+        assert_eq!(seek_position(10_000, i64::MAX, 60_000), None);
+        assert_eq!(seek_position(10_000, i64::MIN, 60_000), Some(0));
+    }
+
+    #[test]
+    fn test_playback_status() {
+        assert_eq!(
+            playback_status(&PlayerEvent::FinishedTrack, true),
+            "Playing"
+        );
+        assert_eq!(
+            playback_status(&PlayerEvent::FinishedTrack, false),
+            "Stopped"
+        );
+        assert_eq!(playback_status(&PlayerEvent::Stopped, true), "Stopped");
+    }
+
+    #[test]
+    fn test_volume_round_trip() {
+        for step in 0..=1000 {
+            let requested = step as f64 / 1000.0;
+            let volume = player_volume(requested);
+            assert!((mpris_volume(volume) - requested).abs() <= 0.5 / u16::MAX as f64);
+            assert_eq!(player_volume(mpris_volume(volume)), volume);
+        }
+        for volume in 0..=u16::MAX {
+            assert_eq!(player_volume(mpris_volume(volume)), volume);
+        }
+        assert_eq!(player_volume(1.0), u16::MAX);
+    }
+
+    #[test]
+    fn test_step_volume() {
+        assert_eq!(step_volume(0, 1), VOLUME_PERCENT);
+        assert_eq!(step_volume(VOLUME_PERCENT * 10, -5), VOLUME_PERCENT * 5);
+        assert_eq!(step_volume(VOLUME_PERCENT, -5), 0);
+        assert_eq!(step_volume(u16::MAX - 10, 5), u16::MAX);
+        assert_eq!(step_volume(1000, i64::MAX), u16::MAX);
+    }
+
+    #[test]
+    fn test_shuffle_setting() {
+        assert_eq!(shuffle_setting(true, 0), None);
+        assert_eq!(shuffle_setting(false, 0), None);
+        assert_eq!(shuffle_setting(true, 3), Some(true));
+        assert_eq!(shuffle_setting(false, 3), Some(false));
+    }
+
+    #[test]
+    fn test_get_metadata() {
+        let player = FakePlayer {
+            status: PlayerEvent::Stopped,
+            lookups: Cell::new(0),
+            rate_limited: false,
+        };
+        let cache = Mutex::new(MetadataCache::new(10));
+        let playable = Some(Playable::Track(simplified_track()));
+
+        // simplified tracks lack a cover, so the full track is fetched once, as
+        // are its audio features
+        for _ in 0..2 {
+            let metadata = get_metadata(
+                playable.clone(),
+                Some(0),
+                &player,
+                &FakeLibrary(true),
+                &cache,
+                false,
+            );
+            assert_eq!(
+                metadata["mpris:artUrl"].0.as_str(),
+                Some("https://i.scdn.co/image/cover")
+            );
+            assert_eq!(metadata["mpris:length"].0.as_i64(), Some(213_000_000));
+            assert_eq!(metadata["ncspot:popularity"].0.as_i64(), Some(77));
+            assert_eq!(metadata["ncspot:energy"].0.as_f64(), Some(0.5));
+            assert_eq!(metadata["ncspot:tempo"].0.as_f64(), Some(113.0));
+            assert_eq!(metadata["ncspot:mediaType"].0.as_str(), Some("track"));
+            assert_eq!(metadata["xesam:userRating"].0.as_f64(), Some(1.0));
+        }
+        assert_eq!(player.lookups.get(), 2);
+
+        // the features are left out if they couldn't be fetched, which isn't retried
+        let unanalyzed = Track {
+            id: Some("0000000000000000000000".to_string()),
+            cover_url: Some("https://i.scdn.co/image/cover".to_string()),
+            popularity: Some(0),
+            ..simplified_track()
+        };
+        for _ in 0..2 {
+            let playable = Some(Playable::Track(unanalyzed.clone()));
+            let metadata = get_metadata(
+                playable,
+                Some(0),
+                &player,
+                &FakeLibrary(true),
+                &cache,
+                false,
+            );
+            assert!(!metadata.contains_key("ncspot:energy"));
+            assert!(!metadata.contains_key("ncspot:tempo"));
+        }
+        assert_eq!(player.lookups.get(), 3);
+    }
+
+    #[test]
+    fn test_get_metadata_no_track() {
+        let player = FakePlayer {
+            status: PlayerEvent::Playing(SystemTime::now()),
+            lookups: Cell::new(0),
+            rate_limited: false,
+        };
+        let cache = Mutex::new(MetadataCache::new(10));
+
+        let metadata = get_current_metadata(None, None, &player, &FakeLibrary(true), &cache, false);
+        assert_eq!(
+            metadata_to_json(&metadata),
+            serde_json::json!({ "mpris:trackid": TRACKLIST_NO_TRACK })
+        );
+        assert_eq!(metadata["mpris:trackid"].0.signature().to_string(), "o");
+        assert_eq!(player.lookups.get(), 0);
+    }
+
+    #[test]
+    fn test_get_metadata_local_track() {
+        let player = FakePlayer {
+            status: PlayerEvent::Stopped,
+            lookups: Cell::new(0),
+            rate_limited: false,
+        };
+        let cache = Mutex::new(MetadataCache::new(10));
+        // as local files in playlists are returned by the API
+        let local = Track {
+            id: None,
+            uri: String::new(),
+            album: Some("Local Album".to_string()),
+            popularity: Some(0),
+            ..simplified_track()
+        };
+
+        let metadata = get_metadata(
+            Some(Playable::Track(local)),
+            Some(0),
+            &player,
+            &FakeLibrary(false),
+            &cache,
+            false,
+        );
+        assert_eq!(
+            metadata["xesam:title"].0.as_str(),
+            Some("Never Gonna Give You Up")
+        );
+        assert_eq!(metadata["xesam:album"].0.as_str(), Some("Local Album"));
+        assert!(metadata.contains_key("xesam:artist"));
+        assert!(!metadata.contains_key("ncspot:energy"));
+        assert_eq!(player.lookups.get(), 0);
+    }
+
+    #[test]
+    fn test_get_metadata_rate_limited() {
+        let player = FakePlayer {
+            status: PlayerEvent::Stopped,
+            lookups: Cell::new(0),
+            rate_limited: true,
+        };
+        let cache = Mutex::new(MetadataCache::new(10));
+        let playable = Some(Playable::Track(simplified_track()));
+
+        // the simplified track is reported without looking anything up
+        let metadata = get_metadata(
+            playable.clone(),
+            Some(0),
+            &player,
+            &FakeLibrary(true),
+            &cache,
+            false,
+        );
+        assert_eq!(metadata["mpris:artUrl"].0.as_str(), Some(""));
+        assert_eq!(metadata["mpris:length"].0.as_i64(), Some(213_000_000));
+        assert!(!metadata.contains_key("ncspot:energy"));
+        assert_eq!(player.lookups.get(), 0);
+
+        // nothing was cached, so the lookups happen once the cooldown elapsed
+        let player = FakePlayer {
+            rate_limited: false,
+            ..player
+        };
+        let metadata = get_metadata(
+            playable,
+            Some(0),
+            &player,
+            &FakeLibrary(true),
+            &cache,
+            false,
+        );
+        assert_eq!(
+            metadata["mpris:artUrl"].0.as_str(),
+            Some("https://i.scdn.co/image/cover")
+        );
+        assert_eq!(metadata["ncspot:energy"].0.as_f64(), Some(0.5));
+        assert_eq!(player.lookups.get(), 2);
+    }
+
+    #[test]
+    fn test_get_metadata_last_used() {
+        struct PlayedLibrary(DateTime<Utc>);
+
+        impl LibraryState for PlayedLibrary {
+            fn is_saved(&self, _playable: &Playable) -> bool {
+                false
+            }
+
+            fn last_played(&self, _playable: &Playable) -> Option<DateTime<Utc>> {
+                Some(self.0)
+            }
+        }
+
+        let player = FakePlayer {
+            status: PlayerEvent::Stopped,
+            lookups: Cell::new(0),
+            rate_limited: true,
+        };
+        let cache = Mutex::new(MetadataCache::new(10));
+        let playable = Some(Playable::Track(simplified_track()));
+
+        let played_at: DateTime<Utc> = "2026-10-15T08:30:00Z".parse().unwrap();
+        let metadata = get_metadata(
+            playable.clone(),
+            Some(0),
+            &player,
+            &PlayedLibrary(played_at),
+            &cache,
+            false,
+        );
+        assert_eq!(
+            metadata["xesam:lastUsed"].0.as_str(),
+            Some("2026-10-15T08:30:00Z")
+        );
+
+        // items that were never played leave it out
+        let metadata = get_metadata(
+            playable,
+            Some(0),
+            &player,
+            &FakeLibrary(false),
+            &cache,
+            false,
+        );
+        assert!(!metadata.contains_key("xesam:lastUsed"));
+    }
+
+    #[test]
+    fn test_get_metadata_episode_length() {
+        let player = FakePlayer {
+            status: PlayerEvent::Stopped,
+            lookups: Cell::new(0),
+            rate_limited: false,
+        };
+        let cache = Mutex::new(MetadataCache::new(10));
+        let episode = Episode {
+            id: "512ojhOuo1ktJprKbVcKyQ".to_string(),
+            uri: "spotify:episode:512ojhOuo1ktJprKbVcKyQ".to_string(),
+            duration: 0,
+            name: "Episode".to_string(),
+            description: String::new(),
+            release_date: String::new(),
+            show_name: Some("Show".to_string()),
+            show_publisher: None,
+            show_id: None,
+            cover_url: None,
+            added_at: None,
+            list_index: 0,
+        };
+
+        // the full episode is fetched for its duration, even though the show is known
+        for _ in 0..2 {
+            let metadata = get_metadata(
+                Some(Playable::Episode(episode.clone())),
+                Some(0),
+                &player,
+                &FakeLibrary(false),
+                &cache,
+                false,
+            );
+            assert_eq!(metadata["mpris:length"].0.as_i64(), Some(3_600_000_000));
+            assert_eq!(metadata["ncspot:mediaType"].0.as_str(), Some("episode"));
+        }
+        assert_eq!(player.lookups.get(), 1);
+    }
+
+    #[test]
+    fn test_get_metadata_unknown_length() {
+        let player = FakePlayer {
+            status: PlayerEvent::Stopped,
+            lookups: Cell::new(0),
+            rate_limited: false,
+        };
+        let cache = Mutex::new(MetadataCache::new(10));
+        let track = Track {
+            duration: 0,
+            cover_url: Some("https://i.scdn.co/image/cover".to_string()),
+            ..simplified_track()
+        };
+
+        let metadata = get_metadata(
+            Some(Playable::Track(track)),
+            Some(0),
+            &player,
+            &FakeLibrary(false),
+            &cache,
+            false,
+        );
+        assert!(!metadata.contains_key("mpris:length"));
+        assert!(metadata.contains_key("mpris:trackid"));
+    }
+
+    #[test]
+    fn test_get_metadata_output() {
+        let player = FakePlayer {
+            status: PlayerEvent::Stopped,
+            lookups: Cell::new(0),
+            rate_limited: false,
+        };
+        let cache = Mutex::new(MetadataCache::new(10));
+        let track = Track {
+            album: Some("Whenever You Need Somebody".to_string()),
+            album_artists: vec!["Rick Astley".to_string()],
+            album_release_date: Some("1987-11-12".to_string()),
+            cover_url: Some("https://i.scdn.co/image/cover".to_string()),
+            url: "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC".to_string(),
+            popularity: Some(77),
+            ..simplified_track()
+        };
+        let episode = Episode {
+            id: "512ojhOuo1ktJprKbVcKyQ".to_string(),
+            uri: "spotify:episode:512ojhOuo1ktJprKbVcKyQ".to_string(),
+            duration: 3_600_000,
+            name: "Episode".to_string(),
+            description: "About the show".to_string(),
+            release_date: "2022-10-01".to_string(),
+            show_name: Some("Show".to_string()),
+            show_publisher: Some("Publisher".to_string()),
+            show_id: None,
+            cover_url: Some("https://i.scdn.co/image/show".to_string()),
+            added_at: None,
+            list_index: 0,
+        };
+
+        let expected = [
+            serde_json::json!({
+                "mpris:artUrl": "https://i.scdn.co/image/cover",
+                "mpris:length": 213_000_000,
+                "mpris:trackid": "/org/ncspot/queue/0",
+                "ncspot:energy": 0.5,
+                "ncspot:mediaType": "track",
+                "ncspot:popularity": 77,
+                "ncspot:tempo": 113.0,
+                "xesam:album": "Whenever You Need Somebody",
+                "xesam:albumArtist": ["Rick Astley"],
+                "xesam:artist": ["Rick Astley"],
+                "xesam:asText": "We're no strangers to love",
+                "xesam:contentCreated": "1987-11-12",
+                "xesam:discNumber": 1,
+                "xesam:title": "Never Gonna Give You Up",
+                "xesam:trackNumber": 1,
+                "xesam:url": "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+                "xesam:userRating": 1.0,
+            }),
+            serde_json::json!({
+                "mpris:artUrl": "https://i.scdn.co/image/show",
+                "mpris:length": 3_600_000_000i64,
+                "mpris:trackid": "/org/ncspot/queue/1",
+                "ncspot:mediaType": "episode",
+                "ncspot:show": "Show",
+                "xesam:album": "Show",
+                "xesam:artist": ["Publisher"],
+                "xesam:comment": ["About the show"],
+                "xesam:contentCreated": "2022-10-01",
+                "xesam:discNumber": 0,
+                "xesam:genre": ["Podcast"],
+                "xesam:title": "Episode",
+                "xesam:trackNumber": 0,
+                "xesam:url": "https://open.spotify.com/episode/512ojhOuo1ktJprKbVcKyQ",
+                "xesam:userRating": 0.0,
+            }),
+        ];
+        let signature = |metadata: &Metadata, key: &str| metadata[key].0.signature().to_string();
+
+        for (entry_id, (playable, expected)) in [Playable::Track(track), Playable::Episode(episode)]
+            .into_iter()
+            .zip(expected)
+            .enumerate()
+        {
+            let metadata = get_metadata(
+                Some(playable),
+                Some(entry_id as u64),
+                &player,
+                &FakeLibrary(true),
+                &cache,
+                false,
+            );
+            assert_eq!(metadata_to_json(&metadata), expected);
+            assert_eq!(signature(&metadata, "mpris:trackid"), "o");
+            assert_eq!(signature(&metadata, "mpris:length"), "x");
+            assert_eq!(signature(&metadata, "xesam:discNumber"), "i");
+            assert_eq!(signature(&metadata, "xesam:trackNumber"), "i");
+            assert_eq!(signature(&metadata, "xesam:userRating"), "d");
+        }
+    }
+
+    #[test]
+    fn test_metadata_to_json() {
+        let player = FakePlayer {
+            status: PlayerEvent::Stopped,
+            lookups: Cell::new(0),
+            rate_limited: false,
+        };
+        let cache = Mutex::new(MetadataCache::new(10));
+        let metadata = get_metadata(
+            Some(Playable::Track(simplified_track())),
+            Some(0),
+            &player,
+            &FakeLibrary(true),
+            &cache,
+            false,
+        );
+
+        let json = metadata_to_json(&metadata);
+        assert_eq!(json["mpris:trackid"], "/org/ncspot/queue/0");
+        assert_eq!(json["xesam:title"], "Never Gonna Give You Up");
+        assert_eq!(json["mpris:length"], 213_000_000);
+        assert_eq!(json["xesam:trackNumber"], 1);
+        assert_eq!(json["xesam:userRating"], 1.0);
+        assert_eq!(json["xesam:artist"], serde_json::json!(["Rick Astley"]));
+        assert_eq!(refarg_to_json(&Variant(Box::new(true))), true);
+    }
+
+    #[test]
+    fn test_get_metadata_artists() {
+        let player = FakePlayer {
+            status: PlayerEvent::Stopped,
+            lookups: Cell::new(0),
+            rate_limited: false,
+        };
+        let cache = Mutex::new(MetadataCache::new(10));
+
+        let metadata = get_metadata(
+            Some(Playable::Track(simplified_track())),
+            Some(0),
+            &player,
+            &FakeLibrary(false),
+            &cache,
+            false,
+        );
+        assert!(metadata.contains_key("xesam:artist"));
+        assert!(!metadata.contains_key("xesam:albumArtist"));
+        assert_eq!(
+            metadata["xesam:asText"].0.as_str(),
+            Some("We're no strangers to love")
+        );
+
+        let track = Track {
+            artists: Vec::new(),
+            album_artists: vec!["Rick Astley".to_string()],
+            cover_url: Some("https://i.scdn.co/image/cover".to_string()),
+            popularity: Some(77),
+            ..simplified_track()
+        };
+        let metadata = get_metadata(
+            Some(Playable::Track(track)),
+            Some(0),
+            &player,
+            &FakeLibrary(false),
+            &cache,
+            false,
+        );
+        assert!(!metadata.contains_key("xesam:artist"));
+        assert!(metadata.contains_key("xesam:albumArtist"));
+        assert!(!metadata.contains_key("xesam:asText"));
+
+        let metadata = get_metadata(None, None, &player, &FakeLibrary(false), &cache, false);
+        assert!(!metadata.contains_key("xesam:artist"));
+        assert!(!metadata.contains_key("xesam:albumArtist"));
+    }
+
+    #[test]
+    fn test_get_current_metadata_bitrate() {
+        let cache = Mutex::new(MetadataCache::new(10));
+        let playable = Some(Playable::Track(simplified_track()));
+        for (status, bitrate) in [
+            (PlayerEvent::Paused(Default::default()), Some(320)),
+            (PlayerEvent::Stopped, None),
+        ] {
+            let player = FakePlayer {
+                status,
+                lookups: Cell::new(0),
+                rate_limited: false,
+            };
+            let metadata = get_current_metadata(
+                playable.clone(),
+                None,
+                &player,
+                &FakeLibrary(false),
+                &cache,
+                false,
+            );
+            assert_eq!(
+                metadata.get("ncspot:bitrate").and_then(|v| v.0.as_i64()),
+                bitrate
+            );
+        }
+    }
+
+    #[test]
+    fn test_iso_date() {
+        assert_eq!(iso_date("1987-11-12").as_deref(), Some("1987-11-12"));
+        assert_eq!(iso_date("1987-11").as_deref(), Some("1987-11"));
+        assert_eq!(iso_date("1987").as_deref(), Some("1987"));
+        assert_eq!(iso_date(" 1987 ").as_deref(), Some("1987"));
+        for unknown in [
+            "",
+            "0000",
+            "87",
+            "1987-13",
+            "1987-02-30",
+            "1987-1-5",
+            "Nov 1987",
+        ] {
+            assert_eq!(iso_date(unknown), None, "{}", unknown);
+        }
+    }
+
+    #[test]
+    fn test_unhandled_reply() {
+        let mut call = Message::new_method_call(
+            "org.mpris.MediaPlayer2.ncspot",
+            "/org/ncspot/Unknown",
+            "org.ncspot",
+            "Frobnicate",
+        )
+        .unwrap();
+        call.set_serial(1);
+        let reply = unhandled_reply(&call).unwrap();
+        assert_eq!(reply.msg_type(), MessageType::Error);
+        assert_eq!(
+            reply.get1::<&str>(),
+            Some("Frobnicate is not supported on /org/ncspot/Unknown")
+        );
+
+        // signals and calls that don't expect a reply aren't answered
+        call.set_no_reply(true);
+        assert!(unhandled_reply(&call).is_none());
+        let signal = Message::signal(
+            &"/org/freedesktop/DBus".into(),
+            &"org.freedesktop.DBus".into(),
+            &"NameAcquired".into(),
+        );
+        assert!(unhandled_reply(&signal).is_none());
+    }
+
+    #[test]
+    fn test_lru_cache() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+        assert_eq!(cache.get("a"), Some(1));
+
+        // "b" is the least recently used entry now
+        cache.insert("c".to_string(), 3);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
+
+        cache.remove("a");
+        assert_eq!(cache.get("a"), None);
+
+        let mut disabled = LruCache::new(0);
+        disabled.insert("a".to_string(), 1);
+        assert_eq!(disabled.get("a"), None);
+    }
+
+    #[test]
+    fn test_open_spotify_url() {
+        let cases = [
+            (
+                "https://open.spotify.com/track/6fRJg3R90w0juYoCJXxj2d",
+                ("track", "6fRJg3R90w0juYoCJXxj2d"),
+            ),
+            (
+                "https://open.spotify.com/album/29Xikj6r9kQDSbnZWCCW2s?si=5Bk0GSGKQmWUr7W-pKmC8w",
+                ("album", "29Xikj6r9kQDSbnZWCCW2s"),
+            ),
+            (
+                "http://open.spotify.com/user/spotify/playlist/37i9dQZF1DXcBWIGoYBM5M",
+                ("playlist", "37i9dQZF1DXcBWIGoYBM5M"),
+            ),
+            (
+                "https://open.spotify.com/user/spotify/playlist/37i9dQZF1DXcBWIGoYBM5M?si=a1b2c3",
+                ("playlist", "37i9dQZF1DXcBWIGoYBM5M"),
+            ),
+        ];
+
+        for (url, expected) in cases {
+            assert_eq!(parse_open_spotify_url(url), Some(expected), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_open_spotify_url_variants() {
+        let cases = [
+            (
+                "https://open.spotify.com/intl-de/track/6fRJg3R90w0juYoCJXxj2d?si=1234",
+                ("track", "6fRJg3R90w0juYoCJXxj2d"),
+            ),
+            (
+                "https://open.spotify.com/intl-pt-BR/album/29Xikj6r9kQDSbnZWCCW2s",
+                ("album", "29Xikj6r9kQDSbnZWCCW2s"),
+            ),
+            (
+                "https://open.spotify.com/embed/episode/0Q86acNRm6V9GYx55SXKwf",
+                ("episode", "0Q86acNRm6V9GYx55SXKwf"),
+            ),
+            (
+                "https://open.spotify.com/show/5CfCWKI5pZ28U0uOzXkDHe/episodes",
+                ("show", "5CfCWKI5pZ28U0uOzXkDHe"),
+            ),
+        ];
+
+        for (url, expected) in cases {
+            assert_eq!(parse_open_spotify_url(url), Some(expected), "{}", url);
+        }
+
+        for url in [
+            "https://open.spotify.com/track/",
+            "https://open.spotify.com/collection/tracks",
+            "https://open.spotify.com/",
+            "open.spotify.com",
+        ] {
+            assert_eq!(parse_open_spotify_url(url), None, "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_normalize_uri() {
+        assert_eq!(
+            normalize_uri("spotify:user:spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"),
+            "spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"
+        );
+        assert_eq!(
+            normalize_uri("spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"),
+            "spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"
+        );
+        assert_eq!(
+            normalize_uri("spotify:track:6fRJg3R90w0juYoCJXxj2d"),
+            "spotify:track:6fRJg3R90w0juYoCJXxj2d"
+        );
+        assert_eq!(
+            normalize_uri("spotify:user:spotify:collection"),
+            "spotify:user:spotify:collection"
+        );
+    }
+
+    fn state(status: &str) -> MprisState {
+        MprisState {
+            status: status.to_string(),
+            playable: None,
+            entry_id: None,
+            volume: 1.0,
+            rate: 1.0,
+            repeat: RepeatSetting::None,
+            shuffle: false,
+            can_go_next: false,
+            can_go_previous: false,
+            controls: Controls {
+                control: true,
+                play: true,
+                pause: true,
+                seek: true,
+            },
+            context: None,
+            queue_length: 0,
+            upcoming_ms: 0,
+            crossfade_ms: 0,
+            device: "stub".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_queue_remaining_us() {
+        let secs = Duration::from_secs;
+        assert_eq!(
+            queue_remaining_us(Some(200_000), secs(50), 300_000),
+            450_000_000
+        );
+        // nothing is playing, only the queued items count
+        assert_eq!(queue_remaining_us(None, secs(0), 300_000), 300_000_000);
+        // the progress may briefly exceed the duration when a track ends
+        assert_eq!(queue_remaining_us(Some(200_000), secs(201), 0), 0);
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let states =
+            (0..10).map(|i| MprisCommand::UpdateState(Box::new(state(&format!("state {}", i)))));
+        let coalesced = coalesce(states);
+        assert_eq!(coalesced.len(), 1);
+        assert!(matches!(&coalesced[0], MprisCommand::UpdateState(s) if s.status == "state 9"));
+
+        let commands = vec![
+            MprisCommand::UpdateState(Box::new(state("first"))),
+            MprisCommand::Seeked(1000),
+            MprisCommand::UpdateState(Box::new(state("second"))),
+            MprisCommand::UpdateTrackList(QueueChange::Replaced),
+        ];
+        let coalesced = coalesce(commands.into_iter());
+        assert_eq!(coalesced.len(), 3);
+        assert!(matches!(coalesced[0], MprisCommand::Seeked(1000)));
+        assert!(matches!(&coalesced[1], MprisCommand::UpdateState(s) if s.status == "second"));
+        assert!(matches!(
+            coalesced[2],
+            MprisCommand::UpdateTrackList(QueueChange::Replaced)
+        ));
+    }
+
+    #[test]
+    fn test_changed_properties() {
+        let playing = state("Playing");
+        assert_eq!(
+            playing.changed_properties(None),
+            vec![
+                "Metadata",
+                "PlaybackStatus",
+                "Volume",
+                "Rate",
+                "LoopStatus",
+                "Shuffle",
+                "CanGoNext",
+                "CanGoPrevious",
+                "CanControl",
+                "CanPlay",
+                "CanPause",
+                "CanSeek"
+            ]
+        );
+        assert!(playing.changed_properties(Some(&playing)).is_empty());
+        assert_eq!(
+            state("Paused").changed_properties(Some(&playing)),
+            vec!["PlaybackStatus"]
+        );
+
+        let muted = MprisState {
+            volume: 0.0,
+            ..state("Playing")
+        };
+        assert_eq!(muted.changed_properties(Some(&playing)), vec!["Volume"]);
+
+        let faster = MprisState {
+            rate: 1.5,
+            ..state("Playing")
+        };
+        assert_eq!(faster.changed_properties(Some(&playing)), vec!["Rate"]);
+
+        let shuffled = MprisState {
+            repeat: RepeatSetting::RepeatTrack,
+            shuffle: true,
+            ..state("Playing")
+        };
+        assert_eq!(
+            shuffled.changed_properties(Some(&playing)),
+            vec!["LoopStatus", "Shuffle"]
+        );
+
+        let stopped = MprisState {
+            controls: Controls {
+                pause: false,
+                seek: false,
+                ..playing.controls
+            },
+            ..state("Stopped")
+        };
+        assert_eq!(
+            stopped.changed_properties(Some(&playing)),
+            vec!["PlaybackStatus", "CanPause", "CanSeek"]
+        );
+    }
+
+    #[test]
+    fn test_poll_interval() {
+        assert_eq!(poll_interval(Some(&state("Playing")), 5000), 200);
+        assert_eq!(poll_interval(Some(&state("Paused")), 5000), 5000);
+        assert_eq!(poll_interval(None, 5000), 5000);
+        // never poll more often while idle than while playing
+        assert_eq!(poll_interval(Some(&state("Stopped")), 0), 200);
+    }
+
+    #[test]
+    fn test_listen_tracker() {
+        let secs = Duration::from_secs;
+        let start = Instant::now();
+        let uri = || Some("spotify:track:4uLU6hMCjMI75M1A2tKUQC".to_string());
+        let mut listens = ListenTracker::default();
+        let mut sample = |progress: u64, playing: bool, at: u64| {
+            listens.sample(uri(), secs(200), secs(progress), playing, start + secs(at))
+        };
+
+        assert_eq!(sample(0, true, 0), None);
+        assert_eq!(sample(40, true, 40), None);
+        // paused for a while, which doesn't count
+        assert_eq!(sample(40, false, 100), None);
+        assert_eq!(sample(40, true, 200), None);
+        // seeking ahead only counts the time that passed
+        assert_eq!(sample(150, true, 210), None);
+        assert_eq!(sample(180, true, 240), None);
+        // 100 seconds have been listened to, half of the duration
+        assert_eq!(sample(200, true, 260), Some((uri().unwrap(), secs(100))));
+        assert_eq!(sample(200, true, 270), None);
+
+        // replaying the track, e.g. on repeat, scrobbles it again
+        assert_eq!(sample(0, true, 280), None);
+        assert_eq!(sample(100, true, 380), Some((uri().unwrap(), secs(100))));
+
+        // long items are scrobbled after four minutes, short ones never
+        let mut listens = ListenTracker::default();
+        assert_eq!(
+            listens.sample(uri(), secs(3600), secs(0), true, start),
+            None
+        );
+        assert!(listens
+            .sample(uri(), secs(3600), secs(240), true, start + secs(240))
+            .is_some());
+        let mut listens = ListenTracker::default();
+        assert_eq!(listens.sample(uri(), secs(20), secs(0), true, start), None);
+        assert_eq!(
+            listens.sample(uri(), secs(20), secs(20), true, start + secs(20)),
+            None
+        );
+    }
 
-fn get_metadata(playable: Option<Playable>, spotify: Spotify, library: Arc<Library>) -> Metadata {
-    let mut hm: Metadata = HashMap::new();
+    #[test]
+    fn test_ensure_ready() {
+        let player = FakePlayer {
+            status: PlayerEvent::Stopped,
+            lookups: Cell::new(0),
+            rate_limited: false,
+        };
+        assert!(ensure_ready(&player).is_ok());
+        assert!(ensure_ready(&Restarting).is_err());
+    }
 
-    // Fetch full track details in case this playable is based on a SimplifiedTrack
-    // This is necessary because SimplifiedTrack objects don't contain a cover_url
-    let playable_full = playable.and_then(|p| match p {
-        Playable::Track(track) => {
-            if track.cover_url.is_some() {
-                // We already have `cover_url`, no need to fetch the full track
-                Some(Playable::Track(track))
-            } else {
-                spotify
-                    .api
-                    .track(&track.id.unwrap_or_default())
-                    .as_ref()
-                    .map(|t| Playable::Track(t.into()))
-            }
-        }
-        Playable::Episode(episode) => Some(Playable::Episode(episode)),
-    });
-    let playable = playable_full.as_ref();
+    #[test]
+    fn test_get_controls() {
+        let player = |status| FakePlayer {
+            status,
+            lookups: Cell::new(0),
+            rate_limited: false,
+        };
 
-    hm.insert(
-        "mpris:trackid".to_string(),
-        Variant(Box::new(Path::from(format!(
-            "/org/ncspot/{}",
-            playable
-                .filter(|t| t.id().is_some())
-                .map(|t| t.uri().replace(':', "/"))
-                .unwrap_or_else(|| String::from("0"))
-        )))),
-    );
-    hm.insert(
-        "mpris:length".to_string(),
-        Variant(Box::new(
-            playable.map(|t| t.duration() as i64 * 1_000).unwrap_or(0),
-        )),
-    );
-    hm.insert(
-        "mpris:artUrl".to_string(),
-        Variant(Box::new(
-            playable
-                .map(|t| t.cover_url().unwrap_or_default())
-                .unwrap_or_default(),
-        )),
-    );
+        let playing = player(PlayerEvent::Playing(SystemTime::now()));
+        let controls = get_controls(&playing, 2, Some(213_000));
+        assert!(controls.control && controls.play && controls.pause && controls.seek);
 
-    hm.insert(
-        "xesam:album".to_string(),
-        Variant(Box::new(
-            playable
-                .and_then(|p| p.track())
-                .map(|t| t.album.unwrap_or_default())
-                .unwrap_or_default(),
-        )),
-    );
-    hm.insert(
-        "xesam:albumArtist".to_string(),
-        Variant(Box::new(
-            playable
-                .and_then(|p| p.track())
-                .map(|t| t.album_artists)
-                .unwrap_or_default(),
-        )),
-    );
-    hm.insert(
-        "xesam:artist".to_string(),
-        Variant(Box::new(
-            playable
-                .and_then(|p| p.track())
-                .map(|t| t.artists)
-                .unwrap_or_default(),
-        )),
-    );
-    hm.insert(
-        "xesam:discNumber".to_string(),
-        Variant(Box::new(
-            playable
-                .and_then(|p| p.track())
-                .map(|t| t.disc_number)
-                .unwrap_or(0),
-        )),
-    );
-    hm.insert(
-        "xesam:title".to_string(),
-        Variant(Box::new(
-            playable
-                .map(|t| match t {
-                    Playable::Track(t) => t.title.clone(),
-                    Playable::Episode(ep) => ep.name.clone(),
-                })
-                .unwrap_or_default(),
-        )),
-    );
-    hm.insert(
-        "xesam:trackNumber".to_string(),
-        Variant(Box::new(
-            playable
-                .and_then(|p| p.track())
-                .map(|t| t.track_number)
-                .unwrap_or(0) as i32,
-        )),
-    );
-    hm.insert(
-        "xesam:url".to_string(),
-        Variant(Box::new(
-            playable
-                .map(|t| t.share_url().unwrap_or_default())
-                .unwrap_or_default(),
-        )),
-    );
-    hm.insert(
-        "xesam:userRating".to_string(),
-        Variant(Box::new(
-            playable
-                .and_then(|p| p.track())
-                .map(|t| match library.is_saved_track(&Playable::Track(t)) {
-                    true => 1.0,
-                    false => 0.0,
-                })
-                .unwrap_or(0.0) as f64,
-        )),
-    );
+        // live content can't be seeked in
+        let live = get_controls(&playing, 2, None);
+        assert!(live.pause && !live.seek);
 
-    hm
-}
+        let stopped = get_controls(&player(PlayerEvent::Stopped), 2, Some(213_000));
+        assert!(stopped.control && stopped.play && !stopped.pause && !stopped.seek);
 
-fn run_dbus_server(
-    ev: EventManager,
-    spotify: Spotify,
-    queue: Arc<Queue>,
-    library: Arc<Library>,
-    rx: mpsc::Receiver<MprisState>,
-) {
-    let conn = Rc::new(
-        dbus::ffidisp::Connection::get_private(dbus::ffidisp::BusType::Session)
-            .expect("Failed to connect to dbus"),
-    );
-    conn.register_name(
-        "org.mpris.MediaPlayer2.ncspot",
-        dbus::ffidisp::NameFlag::ReplaceExisting as u32,
-    )
-    .expect("Failed to register dbus player name");
+        let empty = get_controls(&player(PlayerEvent::Stopped), 0, None);
+        assert!(!empty.play);
 
-    let f = Factory::new_fn::<()>();
+        let restarting = get_controls(&Restarting, 2, Some(213_000));
+        assert_eq!(
+            restarting,
+            Controls {
+                control: false,
+                play: false,
+                pause: false,
+                seek: false,
+            }
+        );
+    }
 
-    let property_canquit = f
-        .property::<bool, _>("CanQuit", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(false); // TODO
-            Ok(())
-        });
+    #[test]
+    fn test_get_state() {
+        let player = StubPlayer::new(PlayerEvent::Playing(SystemTime::now()));
+        let fixture = Fixture::new();
+        let queue = &*fixture.queue;
+        queue.append(track("first"));
+        queue.append(track("second"));
 
-    let property_canraise = f
-        .property::<bool, _>("CanRaise", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(false);
-            Ok(())
-        });
+        // the player may still report the last item after the queue was cleared
+        let state = get_state(&player, queue);
+        assert_eq!(state.status, "Stopped");
+        assert_eq!(state.entry_id, None);
+        assert!(!state.can_go_next && !state.can_go_previous);
+        assert_eq!(state.upcoming_ms, 2 * 213_000);
 
-    let property_cansetfullscreen = f
-        .property::<bool, _>("CanSetFullscreen", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(false);
-            Ok(())
-        });
+        queue.play(0, false, false);
+        player.set_crossfade_ms(5000);
+        let state = get_state(&player, queue);
+        assert_eq!(state.status, "Playing");
+        assert_eq!(state.entry_id, Some(0));
+        assert_eq!(
+            state.playable.and_then(|p| p.id()).as_deref(),
+            Some("first")
+        );
+        assert_eq!(state.volume, mpris_volume(u16::MAX / 2));
+        assert!(state.can_go_next && !state.can_go_previous);
+        assert!(!state.shuffle);
+        assert_eq!(state.queue_length, 2);
+        assert_eq!(state.upcoming_ms, 213_000);
+        assert_eq!(state.crossfade_ms, 5000);
+        assert_eq!(state.device, "stub");
 
-    let property_hastracklist = f
-        .property::<bool, _>("HasTrackList", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(false); // TODO
-            Ok(())
-        });
+        // the last item only continues if it is repeated
+        queue.play(1, false, false);
+        let state = get_state(&player, queue);
+        assert!(!state.can_go_next && state.can_go_previous);
+        *player.status.lock().unwrap() = PlayerEvent::FinishedTrack;
+        assert_eq!(get_state(&player, queue).status, "Stopped");
+        queue.set_repeat(RepeatSetting::RepeatTrack);
+        assert_eq!(get_state(&player, queue).status, "Playing");
 
-    let property_identity = f
-        .property::<String, _>("Identity", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append("ncspot".to_string());
-            Ok(())
-        });
+        queue.set_shuffle(true);
+        assert!(get_state(&player, queue).shuffle);
+    }
 
-    let property_urischemes = f
-        .property::<Vec<String>, _>("SupportedUriSchemes", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(vec!["spotify".to_string()]);
-            Ok(())
-        });
+    #[test]
+    fn test_queue_controls() {
+        let player = StubPlayer::new(PlayerEvent::Stopped);
+        let fixture = Fixture::new();
+        let queue = &*fixture.queue;
+        assert!(!queue_controls(&player, queue).play);
 
-    let property_mimetypes = f
-        .property::<Vec<String>, _>("SupportedMimeTypes", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(Vec::new() as Vec<String>);
-            Ok(())
-        });
+        let live = Track {
+            duration: 0,
+            ..Track::with_id("live")
+        };
+        queue.append(Playable::Track(live));
+        queue.append(Playable::Track(Track::with_id("track")));
+        let stopped = queue_controls(&player, queue);
+        assert!(stopped.play && !stopped.pause && !stopped.seek);
 
-    // https://specifications.freedesktop.org/mpris-spec/latest/Media_Player.html
-    let interface = f
-        .interface("org.mpris.MediaPlayer2", ())
-        .add_p(property_canquit)
-        .add_p(property_canraise)
-        .add_p(property_cansetfullscreen)
-        .add_p(property_hastracklist)
-        .add_p(property_identity)
-        .add_p(property_urischemes)
-        .add_p(property_mimetypes);
+        *player.status.lock().unwrap() = PlayerEvent::Playing(SystemTime::now());
+        queue.play(0, false, false);
+        let live = queue_controls(&player, queue);
+        assert!(live.pause && !live.seek);
+        queue.next(true);
+        let track = queue_controls(&player, queue);
+        assert!(track.pause && track.seek);
+    }
 
-    let property_playbackstatus = {
-        let spotify = spotify.clone();
-        f.property::<String, _>("PlaybackStatus", ())
-            .access(Access::Read)
-            .on_get(move |iter, _| {
-                let status = get_playbackstatus(spotify.clone());
-                iter.append(status);
-                Ok(())
-            })
-    };
+    #[test]
+    fn test_trackid() {
+        for uri in [
+            "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+            "spotify:episode:512ojhOuo1ktJprKbVcKyQ",
+        ] {
+            let trackid = uri_to_trackid(uri).unwrap();
+            assert_eq!(trackid_to_uri(&trackid).as_deref(), Some(uri));
+        }
 
-    let property_loopstatus = {
-        let queue1 = queue.clone();
-        let queue2 = queue.clone();
-        f.property::<String, _>("LoopStatus", ())
-            .access(Access::ReadWrite)
-            .on_get(move |iter, _| {
-                iter.append(
-                    match queue1.get_repeat() {
-                        RepeatSetting::None => "None",
-                        RepeatSetting::RepeatTrack => "Track",
-                        RepeatSetting::RepeatPlaylist => "Playlist",
-                    }
-                    .to_string(),
-                );
-                Ok(())
-            })
-            .on_set(move |iter, _| {
-                let setting = match iter.get::<&str>().unwrap_or_default() {
-                    "Track" => RepeatSetting::RepeatTrack,
-                    "Playlist" => RepeatSetting::RepeatPlaylist,
-                    _ => RepeatSetting::None,
-                };
-                queue2.set_repeat(setting);
+        assert_eq!(
+            &*uri_to_trackid("spotify:track:4uLU6hMCjMI75M1A2tKUQC").unwrap(),
+            "/org/ncspot/spotify/track/4uLU6hMCjMI75M1A2tKUQC"
+        );
+        assert_eq!(uri_to_trackid("spotify:local:a+b:c:d%20e:123"), None);
+        assert_eq!(uri_to_trackid("https://open.spotify.com/track/x"), None);
 
-                Ok(())
-            })
+        assert_eq!(trackid_to_uri(&Path::from("/org/ncspot/NoTrack")), None);
+        assert_eq!(trackid_to_uri(&Path::from("/org/ncspot/queue/3")), None);
+        assert_eq!(
+            trackid_to_uri(&Path::from("/org/mpris/MediaPlayer2/TrackList/NoTrack")),
+            None
+        );
+
+        for id in [0, 3, u64::MAX] {
+            assert_eq!(trackid_to_entry(&get_trackid(Some(id))), Some(id));
+        }
+        assert_eq!(&*get_trackid(None), TRACKLIST_NO_TRACK);
+        assert_eq!(trackid_to_entry(&get_trackid(None)), None);
+        assert_eq!(
+            trackid_to_entry(&uri_to_trackid("spotify:track:4uLU6hMCjMI75M1A2tKUQC").unwrap()),
+            None
+        );
+        assert_eq!(trackid_to_entry(&Path::from("/org/ncspot/queue/x")), None);
+    }
+}
+
+/// Tests talking to the MPRIS interface over the session bus, run them with
+/// `dbus-run-session -- cargo test --features mpris_bus_tests`.
+#[cfg(all(test, feature = "mpris_bus_tests"))]
+mod bus_tests {
+    use std::ops::{Deref, DerefMut};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::time::{Duration, SystemTime};
+
+    use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+    use dbus::blocking::{Connection, Proxy};
+
+    use super::tests::{playlist, StubLibrary, StubPlayer};
+    use super::{
+        bus_name, mpris_volume, player_volume, run_dbus_server, MetadataCache, MprisCommand,
+        MprisLibrary, MprisManager, Player, DEFAULT_METADATA_CACHE_SIZE, TRACKLIST_NO_TRACK,
     };
+    use crate::config::{self, Config};
+    use crate::events::EventManager;
+    use crate::queue::tests::{track, Fixture};
+    use crate::queue::{Queue, RepeatSetting};
+    use crate::spotify::PlayerEvent;
+    use crate::spotify_worker::WorkerCommand;
 
-    let property_metadata = {
-        let spotify = spotify.clone();
-        let queue = queue.clone();
-        let library = library.clone();
-        f.property::<HashMap<String, Variant<Box<dyn RefArg>>>, _>("Metadata", ())
-            .access(Access::Read)
-            .on_get(move |iter, _| {
-                let hm = get_metadata(
-                    queue.clone().get_current(),
-                    spotify.clone(),
-                    library.clone(),
+    const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+
+    /// Gives every server its own bus name, so tests can run in parallel
+    static SERVERS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Runs an MPRIS server for `player`, `queue` and `library` under a bus
+    /// name of its own, which is returned along with the channel the server
+    /// is sent commands on.
+    fn serve<P: Player, L: MprisLibrary>(
+        ev: EventManager,
+        cfg: Arc<Config>,
+        player: P,
+        queue: Arc<Queue>,
+        library: Arc<L>,
+    ) -> (String, mpsc::Sender<MprisCommand>) {
+        let bus_name = format!(
+            "org.mpris.MediaPlayer2.ncspot.test{}",
+            SERVERS.fetch_add(1, Ordering::SeqCst)
+        );
+        let (commands, rx) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        {
+            let bus_name = bus_name.clone();
+            std::thread::spawn(move || {
+                let conn = dbus::ffidisp::Connection::get_private(dbus::ffidisp::BusType::Session)
+                    .expect("no session bus available");
+                conn.register_name(&bus_name, 0).unwrap();
+                ready_tx.send(()).unwrap();
+                let metadata_cache =
+                    Arc::new(Mutex::new(MetadataCache::new(DEFAULT_METADATA_CACHE_SIZE)));
+                run_dbus_server(
+                    &conn,
+                    ev,
+                    player,
+                    queue,
+                    library,
+                    cfg,
+                    &rx,
+                    metadata_cache,
+                    Arc::new(AtomicBool::new(false)),
+                    &AtomicBool::new(false),
                 );
+            });
+        }
+        ready_rx.recv().expect("could not start the MPRIS server");
+        (bus_name, commands)
+    }
 
-                iter.append(hm);
-                Ok(())
-            })
-    };
+    /// An MPRIS server serving the queue of a test fixture.
+    struct Harness {
+        fixture: Fixture,
+        client: Connection,
+        bus_name: String,
+        _commands: mpsc::Sender<MprisCommand>,
+    }
 
-    let property_position = {
-        let spotify = spotify.clone();
-        f.property::<i64, _>("Position", ())
-            .access(Access::Read)
-            .on_get(move |iter, _| {
-                let progress = spotify.get_current_progress();
-                iter.append(progress.as_micros() as i64);
-                Ok(())
-            })
-    };
+    impl Deref for Harness {
+        type Target = Fixture;
 
-    let property_volume = {
-        let spotify1 = spotify.clone();
-        let spotify2 = spotify.clone();
-        let event = ev.clone();
-        f.property::<f64, _>("Volume", ())
-            .access(Access::ReadWrite)
-            .on_get(move |i, _| {
-                i.append(spotify1.volume() as f64 / 65535_f64);
-                Ok(())
-            })
-            .on_set(move |i, _| {
-                let cur = spotify2.volume() as f64 / 65535_f64;
-                let req = i.get::<f64>().unwrap_or(cur);
-                if (0.0..=1.0).contains(&req) {
-                    let vol = (VOLUME_PERCENT as f64) * req * 100.0;
-                    spotify2.set_volume(vol as u16);
-                }
-                event.trigger();
-                Ok(())
-            })
-    };
+        fn deref(&self) -> &Fixture {
+            &self.fixture
+        }
+    }
 
-    let property_rate = f
-        .property::<f64, _>("Rate", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(1.0);
-            Ok(())
-        });
+    impl DerefMut for Harness {
+        fn deref_mut(&mut self) -> &mut Fixture {
+            &mut self.fixture
+        }
+    }
 
-    let property_minrate = f
-        .property::<f64, _>("MinimumRate", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(1.0);
-            Ok(())
-        });
+    impl Harness {
+        fn new() -> Harness {
+            let fixture = Fixture::new();
+            let (player, library) = (fixture.spotify.clone(), fixture.library.clone());
+            Harness::serve(fixture, player, library)
+        }
 
-    let property_maxrate = f
-        .property::<f64, _>("MaximumRate", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(1.0);
-            Ok(())
-        });
+        /// Serves the queue of a fixture, with `player` standing in for
+        /// Spotify and `library` for the library.
+        fn with_stubs(player: StubPlayer, library: Arc<StubLibrary>) -> Harness {
+            Harness::serve(Fixture::new(), player, library)
+        }
+
+        fn serve<P: Player, L: MprisLibrary>(
+            fixture: Fixture,
+            player: P,
+            library: Arc<L>,
+        ) -> Harness {
+            let (bus_name, commands) = serve(
+                fixture.ev.clone(),
+                fixture.cfg.clone(),
+                player,
+                fixture.queue.clone(),
+                library,
+            );
+
+            Harness {
+                fixture,
+                client: Connection::new_session().unwrap(),
+                bus_name,
+                _commands: commands,
+            }
+        }
+
+        fn proxy(&self) -> Proxy<'_, &Connection> {
+            self.client.with_proxy(
+                self.bus_name.as_str(),
+                "/org/mpris/MediaPlayer2",
+                Duration::from_secs(5),
+            )
+        }
+
+        fn call(&self, method: &str, args: impl dbus::arg::AppendAll) {
+            self.proxy()
+                .method_call::<(), _, _, _>(PLAYER, method, args)
+                .unwrap();
+        }
+    }
 
-    let property_canplay = f
-        .property::<bool, _>("CanPlay", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(true);
-            Ok(())
+    #[test]
+    fn test_stub_server() {
+        let player = StubPlayer::new(PlayerEvent::Paused(Duration::ZERO));
+        let hits = playlist("37i9dQZF1DXcBWIGoYBM5M", "Hits");
+        let library = Arc::new(StubLibrary {
+            playlists: vec![playlist("other", "Other"), hits.clone()],
+            ..Default::default()
         });
+        let harness = Harness::with_stubs(player.clone(), library.clone());
+        harness.queue.append(track("first"));
+        harness.queue.set_context(Some(hits.playback_context()));
+        let proxy = harness.proxy();
 
-    let property_canpause = f
-        .property::<bool, _>("CanPause", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(true);
-            Ok(())
-        });
+        // playback is controlled through the player
+        proxy
+            .method_call::<(), _, _, _>(PLAYER, "Pause", ())
+            .unwrap();
+        proxy.set(PLAYER, "Volume", 0.3).unwrap();
+        assert_eq!(*player.commands.lock().unwrap(), ["pause"]);
+        assert_eq!(player.volume(), player_volume(0.3));
 
-    let property_canseek = f
-        .property::<bool, _>("CanSeek", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(true);
-            Ok(())
-        });
+        // a rate of 0 pauses instead of slowing playback down
+        proxy.set(PLAYER, "Rate", 0.0).unwrap();
+        assert_eq!(*player.commands.lock().unwrap(), ["pause", "pause"]);
 
-    let property_cancontrol = f
-        .property::<bool, _>("CanControl", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(true);
-            Ok(())
-        });
+        // tracks are looked up through the player as well
+        proxy
+            .method_call::<(), _, _, _>(
+                "org.mpris.MediaPlayer2.TrackList",
+                "AddTrack",
+                (
+                    "spotify:track:second",
+                    dbus::Path::from(TRACKLIST_NO_TRACK),
+                    false,
+                ),
+            )
+            .unwrap();
+        let tracks: Vec<dbus::Path<'static>> = proxy
+            .get("org.mpris.MediaPlayer2.TrackList", "Tracks")
+            .unwrap();
+        assert_eq!(tracks.len(), 2);
 
-    let property_cangonext = f
-        .property::<bool, _>("CanGoNext", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(true);
-            Ok(())
-        });
+        // added at the start of the tracklist
+        proxy
+            .method_call::<(), _, _, _>("org.ncspot", "SetRating", (tracks[0].clone(), 1.0))
+            .unwrap();
+        assert_eq!(*library.saved.lock().unwrap(), ["spotify:track:second"]);
 
-    let property_cangoprevious = f
-        .property::<bool, _>("CanGoPrevious", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(true);
-            Ok(())
-        });
+        let count: u32 = proxy
+            .get("org.mpris.MediaPlayer2.Playlists", "PlaylistCount")
+            .unwrap();
+        assert_eq!(count, 2);
+        let (active, (_, name, _)): (bool, (dbus::Path<'static>, String, String)) = proxy
+            .get("org.mpris.MediaPlayer2.Playlists", "ActivePlaylist")
+            .unwrap();
+        assert!(active);
+        assert_eq!(name, "Hits");
 
-    let property_shuffle = {
-        let queue_get = queue.clone();
-        let queue_set = queue.clone();
-        f.property::<bool, _>("Shuffle", ())
-            .access(Access::ReadWrite)
-            .on_get(move |iter, _| {
-                let current_state = queue_get.get_shuffle();
-                iter.append(current_state);
-                Ok(())
-            })
-            .on_set(move |iter, _| {
-                if let Some(shuffle_state) = iter.get() {
-                    queue_set.set_shuffle(shuffle_state);
-                }
-                ev.trigger();
-                Ok(())
-            })
-    };
+        let device: (String, bool) = proxy.get("org.ncspot", "ActiveDevice").unwrap();
+        assert_eq!(device, ("stub".to_string(), true));
+    }
 
-    let property_cangoforward = f
-        .property::<bool, _>("CanGoForward", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(true);
-            Ok(())
-        });
+    #[test]
+    fn test_playpause_toggles() {
+        let mut harness = Harness::new();
 
-    let property_canrewind = f
-        .property::<bool, _>("CanRewind", ())
-        .access(Access::Read)
-        .on_get(|iter, _| {
-            iter.append(true);
-            Ok(())
-        });
+        harness
+            .spotify
+            .update_status(PlayerEvent::Playing(SystemTime::now()));
+        harness.call("PlayPause", ());
+        assert!(matches!(
+            harness.worker_commands()[..],
+            [WorkerCommand::Pause]
+        ));
 
-    let method_playpause = {
-        let queue = queue.clone();
-        f.method("PlayPause", (), move |m| {
-            queue.toggleplayback();
-            Ok(vec![m.msg.method_return()])
-        })
-    };
+        harness
+            .spotify
+            .update_status(PlayerEvent::Paused(Duration::from_secs(10)));
+        harness.call("PlayPause", ());
+        assert!(matches!(
+            harness.worker_commands()[..],
+            [WorkerCommand::Play]
+        ));
+    }
 
-    let method_play = {
-        let spotify = spotify.clone();
-        f.method("Play", (), move |m| {
-            spotify.play();
-            Ok(vec![m.msg.method_return()])
-        })
-    };
+    #[test]
+    fn test_volume_round_trip() {
+        let mut harness = Harness::new();
+        harness.worker_commands();
 
-    let method_pause = {
-        let spotify = spotify.clone();
-        f.method("Pause", (), move |m| {
-            spotify.pause();
-            Ok(vec![m.msg.method_return()])
-        })
-    };
+        harness.proxy().set(PLAYER, "Volume", 0.3).unwrap();
+        let volume: f64 = harness.proxy().get(PLAYER, "Volume").unwrap();
+        assert_eq!(volume, mpris_volume(player_volume(0.3)));
+        assert!(matches!(
+            harness.worker_commands()[..],
+            [WorkerCommand::SetVolume(v)] if v == player_volume(0.3)
+        ));
 
-    let method_stop = {
-        let spotify = spotify.clone();
-        f.method("Stop", (), move |m| {
-            spotify.stop();
-            Ok(vec![m.msg.method_return()])
-        })
-    };
+        harness.proxy().set(PLAYER, "Volume", volume).unwrap();
+        let again: f64 = harness.proxy().get(PLAYER, "Volume").unwrap();
+        assert_eq!(again, volume);
+    }
 
-    let method_next = {
-        let queue = queue.clone();
-        f.method("Next", (), move |m| {
-            queue.next(true);
-            Ok(vec![m.msg.method_return()])
-        })
-    };
+    #[test]
+    fn test_openuri_plays_track() {
+        let player = StubPlayer::new(PlayerEvent::Stopped);
+        let mut harness = Harness::with_stubs(player, Arc::new(StubLibrary::default()));
+        harness.queue.append(track("first"));
+        harness.worker_commands();
 
-    let method_previous = {
-        let spotify = spotify.clone();
-        let queue = queue.clone();
-        f.method("Previous", (), move |m| {
-            if spotify.get_current_progress() < Duration::from_secs(5) {
-                queue.previous();
-            } else {
-                spotify.seek(0);
-            }
-            Ok(vec![m.msg.method_return()])
-        })
-    };
+        // the queue is replaced by the track, which is loaded and played
+        harness.call("OpenUri", ("spotify:track:second",));
+        assert_eq!(harness.queue.len(), 1);
+        assert_eq!(harness.queue.get_current_index(), Some(0));
+        assert!(matches!(
+            harness.worker_commands().last(),
+            Some(WorkerCommand::Load(playable, true, 0)) if playable.id().as_deref() == Some("second")
+        ));
+    }
 
-    let method_forward = {
-        let spotify = spotify.clone();
-        f.method("Forward", (), move |m| {
-            spotify.seek_relative(5000);
-            Ok(vec![m.msg.method_return()])
-        })
-    };
+    #[test]
+    fn test_openuri_ignores_unknown_uris() {
+        let harness = Harness::new();
 
-    let method_rewind = {
-        let spotify = spotify.clone();
-        f.method("Rewind", (), move |m| {
-            spotify.seek_relative(-5000);
-            Ok(vec![m.msg.method_return()])
-        })
-    };
+        harness.call("OpenUri", ("https://example.com/not-spotify",));
+        assert_eq!(harness.queue.len(), 0);
+    }
 
-    let method_seek = {
-        let queue = queue.clone();
-        let spotify = spotify.clone();
-        f.method("Seek", (), move |m| {
-            if let Some(current_track) = queue.get_current() {
-                let offset = m.msg.get1::<i64>().unwrap_or(0); // micros
-                let progress = spotify.get_current_progress();
-                let new_position = (progress.as_secs() * 1000) as i32
-                    + progress.subsec_millis() as i32
-                    + (offset / 1000) as i32;
-                let new_position = new_position.max(0) as u32;
-                let duration = current_track.duration();
-
-                if new_position < duration {
-                    spotify.seek(new_position);
-                } else {
-                    queue.next(true);
-                }
-            }
-            Ok(vec![m.msg.method_return()])
-        })
-    };
+    #[test]
+    fn test_openuri_rejects_local_files() {
+        let harness = Harness::new();
 
-    let method_set_position = {
-        let queue = queue.clone();
-        let spotify = spotify.clone();
-        f.method("SetPosition", (), move |m| {
-            if let Some(current_track) = queue.get_current() {
-                let (_, position) = m.msg.get2::<Path, i64>(); // micros
-                let position = (position.unwrap_or(0) / 1000) as u32;
-                let duration = current_track.duration();
+        let reply = harness.proxy().method_call::<(), _, _, _>(
+            PLAYER,
+            "OpenUri",
+            ("spotify:local:Rick+Astley:Whenever+You+Need+Somebody:Never+Gonna+Give+You+Up:213",),
+        );
+        assert!(reply.is_err());
+        assert_eq!(harness.queue.len(), 0);
+    }
 
-                if position < duration {
-                    spotify.seek(position);
-                }
-            }
-            Ok(vec![m.msg.method_return()])
-        })
-    };
+    #[test]
+    fn test_cache_usage() {
+        let harness = Harness::new();
+        let covers_dir = config::cache_path("covers");
+        std::fs::create_dir_all(&covers_dir).unwrap();
+        let path = covers_dir.join(format!("test{}", harness.bus_name));
+        std::fs::write(&path, [0; 100]).unwrap();
+        crate::cache::refresh();
 
-    let method_openuri = {
-        let spotify = spotify.clone();
-        f.method("OpenUri", (), move |m| {
-            let uri_data: Option<&str> = m.msg.get1();
-            let uri = match uri_data {
-                Some(s) => {
-                    let spotify_uri = if s.contains("open.spotify.com") {
-                        let regex = Regex::new(r"https?://open\.spotify\.com(/user/\S+)?/(album|track|playlist|show|episode)/(.+)(\?si=\S+)?").unwrap();
-                        let captures = regex.captures(s).unwrap();
-                        let uri_type = &captures[2];
-                        let id = &captures[3];
-                        format!("spotify:{}:{}", uri_type, id)
-                    }else {
-                        s.to_string()
-                    };
-                    spotify_uri
-                }
-                None => "".to_string(),
-            };
-            let id = &uri[uri.rfind(':').unwrap_or(0) + 1..uri.len()];
-            let uri_type = UriType::from_uri(&uri);
-            match uri_type {
-                Some(UriType::Album) => {
-                    if let Some(a) = spotify.api.album(id) {
-                        if let Some(t) = &Album::from(&a).tracks {
-                            let should_shuffle = queue.get_shuffle();
-                            queue.clear();
-                            let index = queue.append_next(
-                                &t.iter()
-                                    .map(|track| Playable::Track(track.clone()))
-                                    .collect(),
-                            );
-                            queue.play(index, should_shuffle, should_shuffle)
-                        }
-                    }
-                }
-                Some(UriType::Track) => {
-                    if let Some(t) = spotify.api.track(id) {
-                        queue.clear();
-                        queue.append(Playable::Track(Track::from(&t)));
-                        queue.play(0, false, false)
-                    }
-                }
-                Some(UriType::Playlist) => {
-                    if let Some(p) = spotify.api.playlist(id) {
-                        let mut playlist = Playlist::from(&p);
-                        let spotify = spotify.clone();
-                        playlist.load_tracks(spotify);
-                        if let Some(tracks) = &playlist.tracks {
-                            let should_shuffle = queue.get_shuffle();
-                            queue.clear();
-                            let index = queue.append_next(tracks);
-                            queue.play(index, should_shuffle, should_shuffle)
-                        }
-                    }
-                }
-                Some(UriType::Show) => {
-                    if let Some(s) = spotify.api.get_show(id) {
-                        let mut show: Show = (&s).into();
-                        let spotify = spotify.clone();
-                        show.load_all_episodes(spotify);
-                        if let Some(e) = &show.episodes {
-                            let should_shuffle = queue.get_shuffle();
-                            queue.clear();
-                            let mut ep = e.clone();
-                            ep.reverse();
-                            let index = queue.append_next(
-                                &ep.iter()
-                                    .map(|episode| Playable::Episode(episode.clone()))
-                                    .collect(),
-                            );
-                            queue.play(index, should_shuffle, should_shuffle)
-                        }
-                    }
-                }
-                Some(UriType::Episode) => {
-                    if let Some(e) = spotify.api.episode(id) {
-                        queue.clear();
-                        queue.append(Playable::Episode(Episode::from(&e)));
-                        queue.play(0, false, false)
-                    }
-                }
-                Some(UriType::Artist) => {
-                    if let Some(a) = spotify.api.artist_top_tracks(id) {
-                        let should_shuffle = queue.get_shuffle();
-                        queue.clear();
-                        let index = queue.append_next(&a.iter().map(|track| Playable::Track(track.clone())).collect());
-                        queue.play(index, should_shuffle, should_shuffle)
-                    }
-                }
-                None => {}
-            }
-            Ok(vec![m.msg.method_return()])
-        })
-    };
+        let (audio, covers, limit): (u64, u64, u64) =
+            harness.proxy().get("org.ncspot", "CacheUsage").unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(audio, 0);
+        assert!(covers >= 100);
+        assert_eq!(limit, 0);
+    }
 
-    // https://specifications.freedesktop.org/mpris-spec/latest/Player_Interface.html
-    let interface_player = f
-        .interface("org.mpris.MediaPlayer2.Player", ())
-        .add_p(property_playbackstatus)
-        .add_p(property_loopstatus)
-        .add_p(property_metadata)
-        .add_p(property_position)
-        .add_p(property_volume)
-        .add_p(property_rate)
-        .add_p(property_minrate)
-        .add_p(property_maxrate)
-        .add_p(property_canplay)
-        .add_p(property_canpause)
-        .add_p(property_canseek)
-        .add_p(property_cancontrol)
-        .add_p(property_cangonext)
-        .add_p(property_cangoprevious)
-        .add_p(property_shuffle)
-        .add_p(property_cangoforward)
-        .add_p(property_canrewind)
-        .add_m(method_playpause)
-        .add_m(method_play)
-        .add_m(method_pause)
-        .add_m(method_stop)
-        .add_m(method_next)
-        .add_m(method_previous)
-        .add_m(method_forward)
-        .add_m(method_rewind)
-        .add_m(method_seek)
-        .add_m(method_set_position)
-        .add_m(method_openuri);
+    #[test]
+    fn test_history() {
+        let harness = Harness::new();
+        for id in ["a", "b"] {
+            harness.queue.append(track(id));
+        }
+        harness.queue.play(0, false, false);
+        harness.queue.next(true);
 
-    let tree = f.tree(()).add(
-        f.object_path("/org/mpris/MediaPlayer2", ())
-            .introspectable()
-            .add(interface)
-            .add(interface_player),
-    );
+        let history: Vec<(String, i64)> = harness.proxy().get("org.ncspot", "History").unwrap();
+        let uris: Vec<&str> = history.iter().map(|(uri, _)| uri.as_str()).collect();
+        assert_eq!(uris, ["spotify:track:b", "spotify:track:a"]);
+        assert!(history.iter().all(|(_, played_at)| *played_at > 0));
+    }
 
-    tree.set_registered(&conn, true)
-        .expect("failed to register tree");
+    #[test]
+    fn test_toggle_shuffle_and_cycle_repeat() {
+        let harness = Harness::new();
+        let call = |method: &str| {
+            harness
+                .proxy()
+                .method_call::<(bool,), _, _, _>("org.ncspot", method, ())
+                .map(|(shuffled,)| shuffled)
+        };
 
-    conn.add_handler(tree);
-    loop {
-        if let Some(m) = conn.incoming(200).next() {
-            warn!("Unhandled dbus message: {:?}", m);
+        // there's nothing to shuffle yet
+        assert!(!call("ToggleShuffle").unwrap());
+        harness.queue.append(track("first"));
+        harness.queue.append(track("second"));
+        assert!(call("ToggleShuffle").unwrap());
+        assert!(!call("ToggleShuffle").unwrap());
+
+        let repeat: Vec<String> = (0..3)
+            .map(|_| {
+                let (loop_status,): (String,) = harness
+                    .proxy()
+                    .method_call("org.ncspot", "CycleRepeat", ())
+                    .unwrap();
+                loop_status
+            })
+            .collect();
+        assert_eq!(repeat, ["Playlist", "Track", "None"]);
+        assert_eq!(harness.queue.get_repeat(), RepeatSetting::None);
+    }
+
+    #[test]
+    fn test_tracklist_entries() {
+        let harness = Harness::new();
+        let tracklist = |harness: &Harness| -> Vec<dbus::Path<'static>> {
+            harness
+                .proxy()
+                .get("org.mpris.MediaPlayer2.TrackList", "Tracks")
+                .unwrap()
+        };
+        let call = |method: &str, trackid: &dbus::Path<'static>| {
+            harness
+                .proxy()
+                .method_call::<(), _, _, _>(
+                    "org.mpris.MediaPlayer2.TrackList",
+                    method,
+                    (trackid.clone(),),
+                )
+                .unwrap();
+        };
+        harness
+            .spotify
+            .update_status(PlayerEvent::Paused(Duration::ZERO));
+        for id in ["a", "a", "b"] {
+            harness.queue.append(track(id));
         }
 
-        if let Ok(state) = rx.try_recv() {
-            let mut changed: PropertiesPropertiesChanged = Default::default();
-            debug!(
-                "mpris PropertiesChanged: status {}, track: {:?}",
-                state.0, state.1
-            );
+        // copies of the same track are told apart
+        let tracks = tracklist(&harness);
+        assert_eq!(tracks.len(), 3);
+        assert_ne!(tracks[0], tracks[1]);
 
-            changed.interface_name = "org.mpris.MediaPlayer2.Player".to_string();
-            changed.changed_properties.insert(
-                "Metadata".to_string(),
-                Variant(Box::new(get_metadata(
-                    state.1,
-                    spotify.clone(),
-                    library.clone(),
-                ))),
-            );
+        // the ids of the remaining entries stay the same
+        call("RemoveTrack", &tracks[0]);
+        assert_eq!(tracklist(&harness), [tracks[1].clone(), tracks[2].clone()]);
+        call("GoTo", &tracks[1]);
+        assert_eq!(harness.queue.get_current_index(), Some(0));
+        harness.queue.move_to(1, 0);
+        assert_eq!(tracklist(&harness), [tracks[2].clone(), tracks[1].clone()]);
+        call("GoTo", &tracks[1]);
+        assert_eq!(harness.queue.get_current_index(), Some(1));
 
-            changed
-                .changed_properties
-                .insert("PlaybackStatus".to_string(), Variant(Box::new(state.0)));
+        // removed entries can't be found anymore
+        call("GoTo", &tracks[0]);
+        assert_eq!(harness.queue.get_current_index(), Some(1));
+    }
 
-            conn.send(
-                changed.to_emit_message(&Path::new("/org/mpris/MediaPlayer2".to_string()).unwrap()),
-            )
-            .unwrap();
+    #[test]
+    fn test_queue_properties() {
+        let harness = Harness::new();
+        for id in ["first", "second", "third"] {
+            harness.queue.append(track(id));
         }
+        harness.queue.play(1, false, false);
+
+        let length: u32 = harness.proxy().get("org.ncspot", "QueueLength").unwrap();
+        assert_eq!(length, 3);
+        // the current track hasn't started yet, so all of it remains
+        let remaining: i64 = harness
+            .proxy()
+            .get("org.ncspot", "QueueRemainingUs")
+            .unwrap();
+        assert_eq!(remaining, 2 * 213_000_000);
     }
-}
 
-#[derive(Clone)]
-pub struct MprisManager {
-    tx: mpsc::Sender<MprisState>,
-    queue: Arc<Queue>,
-    spotify: Spotify,
-}
+    #[test]
+    fn test_sleep_timer() {
+        let harness = Harness::new();
+        let set_timer = |minutes: u32| {
+            harness
+                .proxy()
+                .method_call::<(), _, _, _>("org.ncspot", "SetSleepTimer", (minutes,))
+                .unwrap();
+        };
+        let remaining = || -> i64 {
+            harness
+                .proxy()
+                .get("org.ncspot", "SleepTimerRemainingUs")
+                .unwrap()
+        };
 
-impl MprisManager {
-    pub fn new(
-        ev: EventManager,
-        spotify: Spotify,
-        queue: Arc<Queue>,
-        library: Arc<Library>,
-    ) -> Self {
-        let (tx, rx) = mpsc::channel::<MprisState>();
+        set_timer(10);
+        let expected = Duration::from_secs(600).as_micros() as i64;
+        assert!((expected - 1_000_000..=expected).contains(&remaining()));
 
-        {
-            let spotify = spotify.clone();
-            let queue = queue.clone();
-            std::thread::spawn(move || {
-                run_dbus_server(ev, spotify.clone(), queue.clone(), library.clone(), rx);
-            });
-        }
+        set_timer(0);
+        assert_eq!(remaining(), 0);
+        assert_eq!(harness.spotify.sleep_timer_remaining(), None);
+    }
+
+    #[test]
+    fn test_normalization_reloads_current() {
+        let mut harness = Harness::new();
+        harness.queue.append(track("first"));
+        harness.queue.play(0, false, false);
+        harness
+            .spotify
+            .update_status(PlayerEvent::Playing(SystemTime::now()));
+        harness.worker_commands();
+
+        harness
+            .proxy()
+            .set("org.ncspot", "VolumeNormalization", true)
+            .unwrap();
+        assert!(matches!(
+            harness.worker_commands()[..],
+            [
+                WorkerCommand::SetNormalisation(true),
+                WorkerCommand::Load(_, true, _)
+            ]
+        ));
+        let enabled: bool = harness
+            .proxy()
+            .get("org.ncspot", "VolumeNormalization")
+            .unwrap();
+        assert!(enabled);
 
-        MprisManager { tx, queue, spotify }
+        // nothing changes if it is already enabled
+        harness
+            .proxy()
+            .set("org.ncspot", "VolumeNormalization", true)
+            .unwrap();
+        assert!(harness.worker_commands().is_empty());
     }
 
-    pub fn update(&self) {
-        let status = get_playbackstatus(self.spotify.clone());
-        let track = self.queue.get_current();
-        self.tx.send(MprisState(status, track)).unwrap();
+    #[test]
+    fn test_crossfade_round_trip() {
+        let harness = Harness::new();
+        let crossfade = |harness: &Harness| -> u32 {
+            harness.proxy().get("org.ncspot", "CrossfadeMs").unwrap()
+        };
+        assert_eq!(crossfade(&harness), 0);
+
+        harness
+            .proxy()
+            .set("org.ncspot", "CrossfadeMs", 5000u32)
+            .unwrap();
+        assert_eq!(crossfade(&harness), 5000);
+        assert_eq!(harness.spotify.crossfade_ms(), 5000);
+    }
+
+    #[test]
+    fn test_unknown_object_path() {
+        let harness = Harness::new();
+        let started = std::time::Instant::now();
+        let reply = harness
+            .client
+            .with_proxy(
+                harness.bus_name.as_str(),
+                "/org/ncspot/Unknown",
+                Duration::from_secs(5),
+            )
+            .method_call::<(), _, _, _>("org.ncspot", "Frobnicate", ());
+        // answered right away instead of timing out
+        assert_eq!(
+            reply.unwrap_err().name(),
+            Some("org.freedesktop.DBus.Error.UnknownMethod")
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_manager_shutdown() {
+        let fixture = Fixture::new();
+
+        let instance = "shutdown";
+        let client = Connection::new_session().unwrap();
+        let has_owner = || {
+            let (owned,): (bool,) = client
+                .with_proxy(
+                    "org.freedesktop.DBus",
+                    "/org/freedesktop/DBus",
+                    Duration::from_secs(5),
+                )
+                .method_call(
+                    "org.freedesktop.DBus",
+                    "NameHasOwner",
+                    (bus_name(Some(instance)),),
+                )
+                .unwrap();
+            owned
+        };
+
+        let manager = MprisManager::new(
+            fixture.ev,
+            fixture.spotify,
+            fixture.queue,
+            fixture.library,
+            fixture.cfg,
+            Some(instance.to_string()),
+        );
+        assert!(has_owner());
+        // the server thread is stopped and has released its name once dropped
+        drop(manager);
+        assert!(!has_owner());
     }
 }
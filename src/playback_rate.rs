@@ -0,0 +1,168 @@
+use std::f64::consts::PI;
+use std::sync::{Arc, RwLock};
+
+use librespot_playback::audio_backend::{Sink, SinkResult};
+use librespot_playback::convert::Converter;
+use librespot_playback::decoder::AudioPacket;
+use librespot_playback::NUM_CHANNELS;
+
+pub const MIN_PLAYBACK_RATE: f64 = 0.5;
+pub const MAX_PLAYBACK_RATE: f64 = 2.0;
+
+/// Length of the grains the stream is cut into, in frames (~46ms)
+const GRAIN_FRAMES: usize = 2048;
+/// Distance between two consecutive grains in the output, in frames
+const SYNTHESIS_HOP: usize = GRAIN_FRAMES / 2;
+
+/// Audio sink wrapper that time-stretches the decoded stream according to the
+/// shared playback rate before handing it to the actual backend.
+///
+/// The stream is cut into overlapping, windowed grains which are read from the
+/// input `rate` times as fast as they are written to the output, which changes
+/// the speed without affecting the pitch.
+pub struct RateSink {
+    sink: Box<dyn Sink>,
+    rate: Arc<RwLock<f64>>,
+    window: Vec<f64>,
+    /// Decoded samples that haven't been passed on in a grain yet
+    input: Vec<f64>,
+    /// Start of the next grain in `input`, in frames
+    position: f64,
+    /// Overlap-add buffer, its first `SYNTHESIS_HOP` frames are complete
+    /// once a grain has been added
+    output: Vec<f64>,
+}
+
+impl RateSink {
+    pub fn new(sink: Box<dyn Sink>, rate: Arc<RwLock<f64>>) -> RateSink {
+        // periodic Hann window, sums up to 1 when overlapping by half
+        let window = (0..GRAIN_FRAMES)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f64 / GRAIN_FRAMES as f64).cos())
+            .collect();
+
+        RateSink {
+            sink,
+            rate,
+            window,
+            input: Vec::new(),
+            position: 0.0,
+            output: vec![0.0; GRAIN_FRAMES * NUM_CHANNELS as usize],
+        }
+    }
+
+    fn stretch(&mut self, samples: &[f64], rate: f64) -> Vec<f64> {
+        let channels = NUM_CHANNELS as usize;
+        self.input.extend_from_slice(samples);
+
+        let mut stretched = Vec::new();
+        loop {
+            let start = self.position.round() as usize;
+            if (start + GRAIN_FRAMES) * channels > self.input.len() {
+                break;
+            }
+
+            let grain = &self.input[start * channels..(start + GRAIN_FRAMES) * channels];
+            for (i, sample) in grain.iter().enumerate() {
+                self.output[i] += sample * self.window[i / channels];
+            }
+
+            stretched.extend(self.output.drain(..SYNTHESIS_HOP * channels));
+            self.output.resize(GRAIN_FRAMES * channels, 0.0);
+            self.position += SYNTHESIS_HOP as f64 * rate;
+        }
+
+        // drop the input that no future grain will start in
+        let consumed = (self.position as usize).min(self.input.len() / channels);
+        self.input.drain(..consumed * channels);
+        self.position -= consumed as f64;
+
+        stretched
+    }
+
+    /// Returns the input that hasn't been played yet and resets the stretching
+    /// state.
+    fn flush(&mut self) -> Vec<f64> {
+        let channels = NUM_CHANNELS as usize;
+        let start = (self.position.round() as usize * channels).min(self.input.len());
+        let pending = self.input.split_off(start);
+        self.reset();
+        pending
+    }
+
+    fn reset(&mut self) {
+        self.input.clear();
+        self.position = 0.0;
+        self.output.iter_mut().for_each(|sample| *sample = 0.0);
+    }
+}
+
+impl Sink for RateSink {
+    fn start(&mut self) -> SinkResult<()> {
+        self.sink.start()
+    }
+
+    fn stop(&mut self) -> SinkResult<()> {
+        self.reset();
+        self.sink.stop()
+    }
+
+    fn write(&mut self, packet: AudioPacket, converter: &mut Converter) -> SinkResult<()> {
+        let rate = *self
+            .rate
+            .read()
+            .expect("could not acquire read lock on rate");
+        match packet {
+            AudioPacket::Samples(samples) if rate != 1.0 => {
+                let stretched = self.stretch(&samples, rate);
+                self.sink.write(AudioPacket::Samples(stretched), converter)
+            }
+            AudioPacket::Samples(samples) if !self.input.is_empty() => {
+                let mut pending = self.flush();
+                pending.extend(samples);
+                self.sink.write(AudioPacket::Samples(pending), converter)
+            }
+            packet => self.sink.write(packet, converter),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+
+    use librespot_playback::audio_backend::{Sink, SinkResult};
+    use librespot_playback::convert::Converter;
+    use librespot_playback::decoder::AudioPacket;
+
+    use super::{RateSink, GRAIN_FRAMES};
+
+    struct NullSink;
+
+    impl Sink for NullSink {
+        fn write(&mut self, _: AudioPacket, _: &mut Converter) -> SinkResult<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_stretch() {
+        for rate in [0.5, 1.5, 2.0] {
+            let mut sink = RateSink::new(Box::new(NullSink), Arc::new(RwLock::new(rate)));
+            let frames = 100 * GRAIN_FRAMES;
+            let mut stretched = Vec::new();
+            for _ in 0..100 {
+                stretched.extend(sink.stretch(&vec![1.0; GRAIN_FRAMES * 2], rate));
+            }
+
+            // duration changes with the rate...
+            let expected = frames as f64 / rate;
+            let actual = (stretched.len() / 2) as f64;
+            assert!((actual - expected).abs() <= 2.0 * GRAIN_FRAMES as f64);
+
+            // ...while the overlapping grains keep the amplitude intact
+            for sample in &stretched[GRAIN_FRAMES..] {
+                assert!((sample - 1.0).abs() < 1e-9);
+            }
+        }
+    }
+}
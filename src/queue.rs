@@ -1,20 +1,32 @@
-use std::cmp::Ordering;
+use std::cmp::{min, Ordering};
+use std::collections::HashSet;
+use std::path::Path;
 #[cfg(feature = "notify")]
 use std::sync::atomic::AtomicU32;
+use std::sync::atomic::{self, AtomicBool, AtomicU64, AtomicUsize};
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 #[cfg(feature = "notify")]
 use notify_rust::{Hint, Notification, Urgency};
 
 use rand::prelude::*;
 use strum_macros::Display;
 
-use crate::config::{Config, NotificationFormat, PlaybackState};
+use crate::config::{
+    Config, NotificationFormat, PlaybackState, QueueEnd, QueueState, ShowPlayOrder,
+};
+use crate::events::{Event, EventManager};
 use crate::library::Library;
+use crate::m3u;
+use crate::model::episode::Episode;
 use crate::model::playable::Playable;
+use crate::model::track::Track;
+use crate::serialization::{Serializer, JSON};
 use crate::spotify::PlayerEvent;
-use crate::spotify::Spotify;
+use crate::spotify::{Spotify, UriType};
+use crate::spotify_url::SpotifyUrl;
 
 #[derive(Display, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum RepeatSetting {
@@ -26,35 +38,127 @@ pub enum RepeatSetting {
     RepeatTrack,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+impl RepeatSetting {
+    /// The setting that follows this one when stepping through them, e.g. with
+    /// the `repeat` command.
+    pub fn next(self) -> RepeatSetting {
+        match self {
+            RepeatSetting::None => RepeatSetting::RepeatPlaylist,
+            RepeatSetting::RepeatPlaylist => RepeatSetting::RepeatTrack,
+            RepeatSetting::RepeatTrack => RepeatSetting::None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum QueueEvent {
     PreloadTrackRequest,
+    /// The track with the given URI could not be loaded
+    TrackUnavailable(String),
+    /// Recommendations fetched to continue playback with
+    AutoplayTracks(Vec<Playable>),
+    /// Items resolved from an entry of an imported file
+    Imported(Vec<Playable>),
+    /// All entries of an imported file were resolved, with the numbers of the
+    /// lines that could not be
+    ImportFinished {
+        imported: usize,
+        skipped: Vec<usize>,
+    },
+}
+
+/// Number of recommended tracks appended at a time when autoplaying.
+const AUTOPLAY_TRACKS: usize = 20;
+
+/// Changes to the contents of the queue, broadcast so that external views of
+/// it (e.g. the MPRIS track list) can be kept in sync. Entries are identified
+/// by their entry ids, see `Queue::entries`.
+#[derive(Clone, Debug)]
+pub enum QueueChange {
+    TrackAdded {
+        track: Box<Playable>,
+        id: u64,
+        after: Option<u64>,
+    },
+    TrackRemoved(u64),
+    Replaced,
+}
+
+/// The collection the queue is playing from, e.g. an album or a playlist.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlaybackContext {
+    pub name: String,
+    pub uri: String,
 }
 
+/// The queue state is kept behind several locks. Whenever more than one of
+/// them is held at a time they are taken in the order `current_track`,
+/// `queue`, `entry_ids`, `random_order`, so that no two threads wait for each
+/// other.
 pub struct Queue {
     pub queue: Arc<RwLock<Vec<Playable>>>,
+    /// Ids of the entries of `queue` by position. An entry keeps its id while
+    /// it is in the queue, even if it moves, and ids are never reused.
+    entry_ids: RwLock<Vec<u64>>,
+    next_entry_id: AtomicU64,
     random_order: RwLock<Option<Vec<usize>>>,
     current_track: RwLock<Option<usize>>,
+    context: RwLock<Option<PlaybackContext>>,
+    /// Index and URI of the first item of the context when it was added
+    context_start: RwLock<Option<(usize, String)>>,
     spotify: Spotify,
     cfg: Arc<Config>,
     #[cfg(feature = "notify")]
     notification_id: Arc<AtomicU32>,
     library: Arc<Library>,
+    ev: EventManager,
+    /// Number of unavailable tracks skipped so far
+    #[cfg(any(feature = "mpris", test))]
+    skipped_unavailable: AtomicUsize,
+    /// Whether recommendations to autoplay are being fetched
+    autoplay_pending: Arc<AtomicBool>,
+    /// Number of `batch` calls currently running, and whether the queue was
+    /// changed during them
+    batch_depth: AtomicUsize,
+    batch_changed: AtomicBool,
+    /// Incremented whenever the items change, so views can tell whether the
+    /// indexes they keep still refer to the same items
+    version: AtomicU64,
 }
 
 impl Queue {
-    pub fn new(spotify: Spotify, cfg: Arc<Config>, library: Arc<Library>) -> Queue {
-        let queue_state = cfg.state().queuestate.clone();
+    pub fn new(
+        ev: &EventManager,
+        spotify: Spotify,
+        cfg: Arc<Config>,
+        library: Arc<Library>,
+    ) -> Queue {
+        let queue_state = match cfg.values().restore_queue.unwrap_or(true) {
+            true => cfg.state().queuestate.clone(),
+            false => QueueState::default(),
+        };
         let playback_state = cfg.state().playback_state.clone();
+        let len = queue_state.queue.len() as u64;
         let queue = Queue {
             queue: Arc::new(RwLock::new(queue_state.queue)),
+            entry_ids: RwLock::new((0..len).collect()),
+            next_entry_id: AtomicU64::new(len),
             spotify: spotify.clone(),
             current_track: RwLock::new(queue_state.current_track),
+            context: RwLock::new(None),
+            context_start: RwLock::new(None),
             random_order: RwLock::new(queue_state.random_order),
             cfg,
             #[cfg(feature = "notify")]
             notification_id: Arc::new(AtomicU32::new(0)),
             library,
+            ev: ev.clone(),
+            #[cfg(any(feature = "mpris", test))]
+            skipped_unavailable: AtomicUsize::new(0),
+            autoplay_pending: Arc::new(AtomicBool::new(false)),
+            batch_depth: AtomicUsize::new(0),
+            batch_changed: AtomicBool::new(false),
+            version: AtomicU64::new(0),
         };
 
         if let Some(playable) = queue.get_current() {
@@ -78,15 +182,17 @@ impl Queue {
     }
 
     pub fn next_index(&self) -> Option<usize> {
-        match *self.current_track.read().unwrap() {
+        let current = *self.current_track.read().unwrap();
+        match current {
             Some(mut index) => {
+                let len = self.queue.read().unwrap().len();
                 let random_order = self.random_order.read().unwrap();
                 if let Some(order) = random_order.as_ref() {
                     index = order.iter().position(|&i| i == index).unwrap();
                 }
 
                 let mut next_index = index + 1;
-                if next_index < self.queue.read().unwrap().len() {
+                if next_index < len {
                     if let Some(order) = random_order.as_ref() {
                         next_index = order[next_index];
                     }
@@ -100,8 +206,23 @@ impl Queue {
         }
     }
 
+    /// Index of the item that follows once the current one finished, taking
+    /// the repeat setting into account like `next(false)` does.
+    pub fn following_index(&self) -> Option<usize> {
+        match self.cfg.state().repeat {
+            RepeatSetting::RepeatTrack => self.get_current_index(),
+            RepeatSetting::None if self.on_queue_end() != QueueEnd::RepeatAll => self.next_index(),
+            _ if !self.queue.read().unwrap().is_empty() => self.next_index().or_else(|| {
+                let random_order = self.random_order.read().unwrap();
+                Some(random_order.as_ref().map(|o| o[0]).unwrap_or(0))
+            }),
+            _ => self.next_index(),
+        }
+    }
+
     pub fn previous_index(&self) -> Option<usize> {
-        match *self.current_track.read().unwrap() {
+        let current = *self.current_track.read().unwrap();
+        match current {
             Some(mut index) => {
                 let random_order = self.random_order.read().unwrap();
                 if let Some(order) = random_order.as_ref() {
@@ -123,6 +244,91 @@ impl Queue {
         }
     }
 
+    /// Whether a manual `next` would play another item instead of stopping.
+    /// When autoplaying or continuing at the end of the queue there's always
+    /// more to play.
+    #[cfg(any(feature = "mpris", test))]
+    pub fn has_next(&self) -> bool {
+        self.next_index().is_some()
+            || ((self.get_repeat() != RepeatSetting::None
+                || self.autoplay()
+                || self.on_queue_end() != QueueEnd::Stop)
+                && self.len() > 0)
+    }
+
+    fn autoplay(&self) -> bool {
+        self.cfg.values().autoplay.unwrap_or(false)
+    }
+
+    fn on_queue_end(&self) -> QueueEnd {
+        self.cfg.values().on_queue_end.unwrap_or(QueueEnd::Stop)
+    }
+
+    /// Appends recommendations seeded by the most recent tracks when the last
+    /// item is playing, so playback keeps going like Spotify's autoplay. They
+    /// are fetched in the background and added by `handle_event`. With
+    /// `on_queue_end = "radio"` only the last track seeds them.
+    ///
+    /// Returns whether recommendations are on their way.
+    fn extend_autoplay(&self) -> bool {
+        let radio = self.on_queue_end() == QueueEnd::Radio;
+        if !(self.autoplay() || radio)
+            || self.get_repeat() != RepeatSetting::None
+            || self.next_index().is_some()
+        {
+            return false;
+        }
+        let (seeds, queued) = {
+            let current = match self.get_current_index() {
+                Some(current) => current,
+                None => return false,
+            };
+            let q = self.queue.read().unwrap();
+            let played = if self.autoplay() {
+                &q[..=current]
+            } else {
+                &q[current..=current]
+            };
+            let queued: HashSet<String> = q.iter().map(Playable::uri).collect();
+            (autoplay_seeds(played), queued)
+        };
+        if seeds.is_empty() {
+            return false;
+        }
+        if self.autoplay_pending.swap(true, atomic::Ordering::Relaxed) {
+            return true;
+        }
+
+        debug!("fetching tracks to autoplay, seeded by {:?}", seeds);
+        let spotify = self.spotify.clone();
+        let ev = self.ev.clone();
+        let pending = self.autoplay_pending.clone();
+        std::thread::spawn(move || {
+            let seeds = seeds.iter().map(String::as_str).collect();
+            let tracks: Vec<Playable> = spotify
+                .api
+                .recommendations(None, None, Some(seeds))
+                .map(|r| r.tracks.iter().map(Track::from).collect::<Vec<_>>())
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|track| !queued.contains(&track.uri))
+                .take(AUTOPLAY_TRACKS)
+                .map(Playable::Track)
+                .collect();
+            pending.store(false, atomic::Ordering::Relaxed);
+            ev.send(Event::Queue(QueueEvent::AutoplayTracks(tracks)));
+        });
+        true
+    }
+
+    /// Whether `previous` would move back to another item instead of
+    /// restarting the current one.
+    #[cfg(feature = "mpris")]
+    pub fn has_previous(&self) -> bool {
+        self.previous_index().is_some()
+            || (self.get_repeat() == RepeatSetting::RepeatPlaylist && self.len() > 0)
+    }
+
     pub fn get_current(&self) -> Option<Playable> {
         self.get_current_index()
             .map(|index| self.queue.read().unwrap()[index].clone())
@@ -132,71 +338,159 @@ impl Queue {
         *self.current_track.read().unwrap()
     }
 
+    /// The current item along with the id of its entry.
+    #[cfg(feature = "mpris")]
+    pub fn get_current_entry(&self) -> Option<(u64, Playable)> {
+        let current = (*self.current_track.read().unwrap())?;
+        let q = self.queue.read().unwrap();
+        let entry_ids = self.entry_ids.read().unwrap();
+        Some((entry_ids[current], q[current].clone()))
+    }
+
+    /// The items in the queue along with the ids of their entries.
+    #[cfg(feature = "mpris")]
+    pub fn entries(&self) -> Vec<(u64, Playable)> {
+        let q = self.queue.read().unwrap();
+        let entry_ids = self.entry_ids.read().unwrap();
+        entry_ids.iter().copied().zip(q.iter().cloned()).collect()
+    }
+
+    /// Position of the entry with the id `id`, if it is still in the queue.
+    #[cfg(feature = "mpris")]
+    pub fn entry_index(&self, id: u64) -> Option<usize> {
+        self.entry_ids.read().unwrap().iter().position(|&i| i == id)
+    }
+
+    fn new_entry_id(&self) -> u64 {
+        self.next_entry_id.fetch_add(1, atomic::Ordering::SeqCst)
+    }
+
     pub fn insert_after_current(&self, track: Playable) {
-        if let Some(index) = self.get_current_index() {
+        match self.get_current_index() {
+            Some(index) => self.insert(index + 1, track),
+            None => self.append(track),
+        }
+    }
+
+    /// Inserts `track` at `index`, or at the end if the queue is shorter. When
+    /// shuffled, it is played right after the item that precedes it.
+    pub fn insert(&self, index: usize, track: Playable) {
+        let index = index.min(self.len());
+        {
             let mut random_order = self.random_order.write().unwrap();
             if let Some(order) = random_order.as_mut() {
-                let next_i = order.iter().position(|&i| i == index).unwrap();
+                let position = match index.checked_sub(1) {
+                    Some(previous) => order
+                        .iter()
+                        .position(|&i| i == previous)
+                        .map_or(order.len(), |position| position + 1),
+                    None => 0,
+                };
                 // shift everything after the insertion in order
                 for item in order.iter_mut() {
-                    if *item > index {
+                    if *item >= index {
                         *item += 1;
                     }
                 }
-                // finally, add the next track index
-                order.insert(next_i + 1, index + 1);
+                order.insert(position, index);
             }
+        }
+        let (id, after) = {
             let mut q = self.queue.write().unwrap();
-            q.insert(index + 1, track);
-        } else {
-            self.append(track);
+            let mut entry_ids = self.entry_ids.write().unwrap();
+            let id = self.new_entry_id();
+            q.insert(index, track.clone());
+            entry_ids.insert(index, id);
+            (id, index.checked_sub(1).map(|i| entry_ids[i]))
+        };
+        {
+            let mut current = self.current_track.write().unwrap();
+            if let Some(current) = current.as_mut().filter(|current| **current >= index) {
+                *current += 1;
+            }
         }
+        self.notify_change(QueueChange::TrackAdded {
+            track: Box::new(track),
+            id,
+            after,
+        });
     }
 
     pub fn append(&self, track: Playable) {
-        let mut random_order = self.random_order.write().unwrap();
-        if let Some(order) = random_order.as_mut() {
-            let index = order.len().saturating_sub(1);
-            order.push(index);
+        {
+            let mut random_order = self.random_order.write().unwrap();
+            if let Some(order) = random_order.as_mut() {
+                order.push(order.len());
+            }
         }
 
-        let mut q = self.queue.write().unwrap();
-        q.push(track);
+        let (id, after) = {
+            let mut q = self.queue.write().unwrap();
+            let mut entry_ids = self.entry_ids.write().unwrap();
+            let id = self.new_entry_id();
+            let after = entry_ids.last().copied();
+            q.push(track.clone());
+            entry_ids.push(id);
+            (id, after)
+        };
+        self.notify_change(QueueChange::TrackAdded {
+            track: Box::new(track),
+            id,
+            after,
+        });
     }
 
-    pub fn append_next(&self, tracks: &Vec<Playable>) -> usize {
-        let mut q = self.queue.write().unwrap();
+    pub fn append_next(&self, tracks: &[Playable]) -> usize {
+        let current = *self.current_track.read().unwrap();
+        let first = {
+            let mut q = self.queue.write().unwrap();
 
-        {
-            let mut random_order = self.random_order.write().unwrap();
-            if let Some(order) = random_order.as_mut() {
-                order.extend((q.len().saturating_sub(1))..(q.len() + tracks.len()));
+            {
+                let mut random_order = self.random_order.write().unwrap();
+                if let Some(order) = random_order.as_mut() {
+                    order.extend((q.len().saturating_sub(1))..(q.len() + tracks.len()));
+                }
+            }
+
+            let first = match current {
+                Some(index) => index + 1,
+                None => q.len(),
+            };
+
+            let mut entry_ids = self.entry_ids.write().unwrap();
+            let mut i = first;
+            for track in tracks {
+                q.insert(i, track.clone());
+                entry_ids.insert(i, self.new_entry_id());
+                i += 1;
             }
-        }
 
-        let first = match *self.current_track.read().unwrap() {
-            Some(index) => index + 1,
-            None => q.len(),
+            first
         };
 
-        let mut i = first;
-        for track in tracks {
-            q.insert(i, track.clone());
-            i += 1;
+        // the items of a context are added right after it is set
+        if self.context.read().unwrap().is_some() {
+            let mut context_start = self.context_start.write().unwrap();
+            if context_start.is_none() {
+                *context_start = tracks.first().map(|track| (first, track.uri()));
+            }
         }
+        self.notify_change(QueueChange::Replaced);
 
         first
     }
 
     pub fn remove(&self, index: usize) {
-        {
+        let removed = {
             let mut q = self.queue.write().unwrap();
             if q.len() == 0 {
                 info!("queue is empty");
                 return;
             }
             q.remove(index);
-        }
+            self.entry_ids.write().unwrap().remove(index)
+        };
+        self.notify_change(QueueChange::TrackRemoved(removed));
 
         // if the queue is empty stop playback
         let len = self.queue.read().unwrap().len();
@@ -237,15 +531,267 @@ impl Queue {
         }
     }
 
+    /// Removes items that occur in the queue again further up, the current item
+    /// is kept instead of its other occurrences. Items without a URI, like local
+    /// files, are left alone. Returns how many items were removed.
+    pub fn dedup(&self) -> usize {
+        let removed = self.retain_items(|q, current| {
+            let uris: Vec<String> = q.iter().map(Playable::uri).collect();
+            dedup_keep(&uris, current)
+        });
+        if removed > 0 {
+            debug!("removed {} duplicates from the queue", removed);
+        }
+        removed
+    }
+
+    /// Removes every item after the current one, so playback stops once it
+    /// ends. Does nothing if no item is current. Returns how many items were
+    /// removed.
+    pub fn clear_after_current(&self) -> usize {
+        self.retain_items(|q, current| match current {
+            Some(current) => (0..q.len()).map(|index| index <= current).collect(),
+            None => vec![true; q.len()],
+        })
+    }
+
+    /// Writes the queue to `path`, as JSON with the full metadata if its
+    /// extension is `json` or as an M3U playlist if it is `m3u` or `m3u8`.
+    /// Returns how many items were written.
+    pub fn export(&self, path: &Path) -> Result<usize, String> {
+        let items = self.queue.read().unwrap().clone();
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("json") => JSON.write(path, &items).map(|_| ())?,
+            Some("m3u") | Some("m3u8") => m3u::write(path, &items)?,
+            _ => {
+                return Err(format!(
+                    "Unknown format of {}, use .m3u or .json",
+                    path.display()
+                ))
+            }
+        }
+        Ok(items.len())
+    }
+
+    /// Appends the items listed in the file at `path`. JSON files written by
+    /// `export` are appended right away. Other files list a Spotify URI or
+    /// `open.spotify.com` link per line, which are resolved in the background
+    /// and appended one by one. Returns the number of entries found.
+    pub fn import(&self, path: &Path) -> Result<usize, String> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        if extension.as_deref() == Some("json") {
+            let items: Vec<Playable> = JSON.load(path)?;
+            let imported = items.len();
+            self.ev.send(Event::Queue(QueueEvent::Imported(items)));
+            self.ev.send(Event::Queue(QueueEvent::ImportFinished {
+                imported,
+                skipped: Vec::new(),
+            }));
+            return Ok(imported);
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        let entries = import_entries(&contents);
+        let count = entries.len();
+        let spotify = self.spotify.clone();
+        let ev = self.ev.clone();
+        std::thread::spawn(move || {
+            let mut imported = 0;
+            let mut skipped = Vec::new();
+            for (line, uri) in entries {
+                let playables = uri
+                    .map(|uri| spotify.playables_for_uri(&uri))
+                    .unwrap_or_default();
+                if playables.is_empty() {
+                    warn!("could not import line {}", line);
+                    skipped.push(line);
+                    continue;
+                }
+                imported += playables.len();
+                ev.send(Event::Queue(QueueEvent::Imported(playables)));
+            }
+            ev.send(Event::Queue(QueueEvent::ImportFinished {
+                imported,
+                skipped,
+            }));
+        });
+        Ok(count)
+    }
+
+    /// Removes tracks restored from the last session that can't be played
+    /// anymore, because they were taken down or aren't available in the
+    /// user's market. Returns how many were removed.
+    pub fn remove_unavailable(&self) -> usize {
+        let ids: Vec<String> = self
+            .queue
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|playable| match playable {
+                Playable::Track(track) => track.id.clone(),
+                Playable::Episode(_) => None,
+            })
+            .collect();
+
+        let mut unavailable = HashSet::new();
+        for chunk in ids.chunks(50) {
+            let chunk: Vec<&str> = chunk.iter().map(String::as_str).collect();
+            // the tracks are returned in the order they were asked for, relinked
+            // ones under a different id
+            match self.spotify.api.tracks(&chunk) {
+                Some(tracks) if tracks.len() == chunk.len() => unavailable.extend(
+                    chunk
+                        .iter()
+                        .zip(tracks)
+                        .filter(|(_, track)| track.is_playable == Some(false))
+                        .map(|(id, _)| id.to_string()),
+                ),
+                _ => warn!("could not check the availability of {} tracks", chunk.len()),
+            }
+        }
+        if unavailable.is_empty() {
+            return 0;
+        }
+
+        let removed = self.retain_items(|q, _| {
+            q.iter()
+                .map(|playable| match playable.id() {
+                    Some(id) => !unavailable.contains(&id),
+                    None => true,
+                })
+                .collect()
+        });
+        info!("removed {} unavailable tracks from the queue", removed);
+        removed
+    }
+
+    /// Removes the items at `indexes` in one go, so the current item is
+    /// replaced at most once if it is among them. Returns how many items were
+    /// removed.
+    pub fn remove_many(&self, indexes: &[usize]) -> usize {
+        let indexes: HashSet<usize> = indexes.iter().copied().collect();
+        self.retain_items(|q, _| {
+            (0..q.len())
+                .map(|index| !indexes.contains(&index))
+                .collect()
+        })
+    }
+
+    /// Keeps the items `keep` returns true for, given the items and the current
+    /// index. If the current item is removed, the next remaining one is loaded
+    /// in its place. Returns how many items were removed.
+    fn retain_items<F>(&self, keep: F) -> usize
+    where
+        F: FnOnce(&[Playable], Option<usize>) -> Vec<bool>,
+    {
+        let (removed, current_removed) = {
+            let mut current = self.current_track.write().unwrap();
+            let mut q = self.queue.write().unwrap();
+            let keep = keep(&q, *current);
+
+            // positions of the kept items once the others are gone, removed items
+            // map to the item following them
+            let new_index: Vec<usize> = keep
+                .iter()
+                .scan(0, |next, &kept| {
+                    let index = *next;
+                    *next += kept as usize;
+                    Some(index)
+                })
+                .collect();
+            let removed = keep.iter().filter(|&&kept| !kept).count();
+            let mut kept = keep.iter();
+            q.retain(|_| *kept.next().unwrap());
+            let mut kept = keep.iter();
+            self.entry_ids
+                .write()
+                .unwrap()
+                .retain(|_| *kept.next().unwrap());
+
+            let current_removed = current.map_or(false, |index| !keep[index]);
+            *current = current
+                .map(|index| new_index[index])
+                .filter(|&index| index < q.len());
+            if let Some(order) = self.random_order.write().unwrap().as_mut() {
+                order.retain(|&index| keep[index]);
+                order
+                    .iter_mut()
+                    .for_each(|index| *index = new_index[*index]);
+            }
+            (removed, current_removed)
+        };
+
+        if current_removed {
+            match self.get_current() {
+                Some(playable) => {
+                    let status = self.spotify.get_current_status();
+                    let playing = matches!(status, PlayerEvent::Playing(_));
+                    self.spotify.load(&playable, playing, 0);
+                    self.spotify.update_track();
+                }
+                None => self.stop(),
+            }
+        }
+        if removed > 0 {
+            self.notify_change(QueueChange::Replaced);
+        }
+        removed
+    }
+
     pub fn clear(&self) {
         self.stop();
 
-        let mut q = self.queue.write().unwrap();
-        q.clear();
+        {
+            let mut q = self.queue.write().unwrap();
+            q.clear();
+            self.entry_ids.write().unwrap().clear();
 
-        let mut random_order = self.random_order.write().unwrap();
-        if let Some(o) = random_order.as_mut() {
-            o.clear()
+            let mut random_order = self.random_order.write().unwrap();
+            if let Some(o) = random_order.as_mut() {
+                o.clear()
+            }
+        }
+        self.set_context(None);
+        self.notify_change(QueueChange::Replaced);
+    }
+
+    pub fn get_context(&self) -> Option<PlaybackContext> {
+        self.context.read().unwrap().clone()
+    }
+
+    /// Sets the collection that is being played, should be called before
+    /// starting playback of its items.
+    pub fn set_context(&self, context: Option<PlaybackContext>) {
+        *self.context.write().unwrap() = context;
+        *self.context_start.write().unwrap() = None;
+    }
+
+    /// Plays the current context again from its first item, or from a random
+    /// item if shuffle is enabled. Returns `false` if there is no context.
+    pub fn restart_context(&self) -> bool {
+        let start = self.context_start.read().unwrap().clone();
+        let index = start.and_then(|(index, uri)| {
+            let q = self.queue.read().unwrap();
+            // the item may have moved since, e.g. if items were removed before it
+            match q.get(index) {
+                Some(playable) if playable.uri() == uri => Some(index),
+                _ => q.iter().position(|playable| playable.uri() == uri),
+            }
+        });
+        match index {
+            Some(index) if self.get_context().is_some() => {
+                self.play(index, true, true);
+                true
+            }
+            _ => false,
         }
     }
 
@@ -253,23 +799,95 @@ impl Queue {
         self.queue.read().unwrap().len()
     }
 
-    pub fn shift(&self, from: usize, to: usize) {
-        let mut queue = self.queue.write().unwrap();
-        let item = queue.remove(from);
-        queue.insert(to, item);
+    /// Total duration in milliseconds of the items that are played after the
+    /// current one, or of all items if none is playing. Follows the shuffled
+    /// order if shuffle is enabled.
+    #[cfg(feature = "mpris")]
+    pub fn upcoming_duration(&self) -> u64 {
+        let current = *self.current_track.read().unwrap();
+        let q = self.queue.read().unwrap();
+        let random_order = self.random_order.read().unwrap();
+        let upcoming: Vec<usize> = match (random_order.as_ref(), current) {
+            (Some(order), Some(current)) => order
+                .iter()
+                .skip_while(|&&index| index != current)
+                .skip(1)
+                .copied()
+                .collect(),
+            (None, Some(current)) => (current + 1..q.len()).collect(),
+            (_, None) => (0..q.len()).collect(),
+        };
+        upcoming
+            .into_iter()
+            .filter_map(|index| q.get(index))
+            .map(|playable| playable.duration() as u64)
+            .sum()
+    }
+
+    /// Moves the item at `from` to `to`, returns false if either is out of
+    /// range. The currently playing item stays the same, and so does the play
+    /// order when shuffled.
+    pub fn move_to(&self, from: usize, to: usize) -> bool {
+        {
+            let mut current = self.current_track.write().unwrap();
+            let mut queue = self.queue.write().unwrap();
+            if from >= queue.len() || to >= queue.len() {
+                return false;
+            }
+            let item = queue.remove(from);
+            queue.insert(to, item);
+            let mut entry_ids = self.entry_ids.write().unwrap();
+            let id = entry_ids.remove(from);
+            entry_ids.insert(to, id);
 
-        // if the currently playing track is affected by the shift, update its
-        // index
-        let mut current = self.current_track.write().unwrap();
-        if let Some(index) = *current {
-            if index == from {
-                current.replace(to);
-            } else if index == to && from > index {
-                current.replace(to + 1);
-            } else if index == to && from < index {
-                current.replace(to - 1);
+            *current = current.map(|index| moved_index(index, from, to));
+            if let Some(order) = self.random_order.write().unwrap().as_mut() {
+                order
+                    .iter_mut()
+                    .for_each(|index| *index = moved_index(*index, from, to));
             }
         }
+        self.notify_change(QueueChange::Replaced);
+        true
+    }
+
+    /// Moves the item at `index` up by `amount`, at most to the top. Returns
+    /// its new index, if it moved.
+    pub fn move_up(&self, index: usize, amount: usize) -> Option<usize> {
+        let to = index.saturating_sub(amount);
+        (to != index && self.move_to(index, to)).then_some(to)
+    }
+
+    /// Moves the item at `index` down by `amount`, at most to the bottom.
+    /// Returns its new index, if it moved.
+    pub fn move_down(&self, index: usize, amount: usize) -> Option<usize> {
+        let to = min(index.saturating_add(amount), self.len().saturating_sub(1));
+        (to > index && self.move_to(index, to)).then_some(to)
+    }
+
+    /// Moves the item at `index` so it plays right after the current one, in
+    /// the shuffled order as well. Returns the new index of the item.
+    pub fn move_to_next(&self, index: usize) -> Option<usize> {
+        let current = self.get_current_index()?;
+        if index == current {
+            return None;
+        }
+        let to = if index > current {
+            current + 1
+        } else {
+            current
+        };
+        if !self.move_to(index, to) {
+            return None;
+        }
+
+        let current = moved_index(current, index, to);
+        if let Some(order) = self.random_order.write().unwrap().as_mut() {
+            order.retain(|&i| i != to);
+            let position = order.iter().position(|&i| i == current);
+            order.insert(position.map_or(order.len(), |position| position + 1), to);
+        }
+        Some(to)
     }
 
     pub fn play(&self, mut index: usize, reshuffle: bool, shuffle_index: bool) {
@@ -280,11 +898,12 @@ impl Queue {
             index = rng.gen_range(0..queue_length);
         }
 
-        if let Some(track) = &self.queue.read().unwrap().get(index) {
-            self.spotify.load(track, true, 0);
-            let mut current = self.current_track.write().unwrap();
-            current.replace(index);
+        let track = self.queue.read().unwrap().get(index).cloned();
+        if let Some(track) = &track {
+            self.spotify.load(track, true, self.start_position(track));
+            self.current_track.write().unwrap().replace(index);
             self.spotify.update_track();
+            self.library.add_to_history(track);
 
             #[cfg(feature = "notify")]
             if self.cfg.values().notify.unwrap_or(false) {
@@ -305,11 +924,7 @@ impl Queue {
 
                     let summary_txt = Playable::format(track, &title, self.library.clone());
                     let body_txt = Playable::format(track, &body, self.library.clone());
-                    let cover_url = if cfg!(feature = "cover") {
-                        track.cover_url()
-                    } else {
-                        None
-                    };
+                    let cover_url = track.cover_url();
                     move || send_notification(&summary_txt, &body_txt, cover_url, notification_id)
                 });
             }
@@ -339,9 +954,12 @@ impl Queue {
         self.spotify.stop();
     }
 
+    /// Advances to the next item of the queue. `manual` is set when the user
+    /// skips the current item, as opposed to playback reaching its end: a manual
+    /// skip always moves on, even if the current track is on repeat.
     pub fn next(&self, manual: bool) {
-        let q = self.queue.read().unwrap();
         let current = *self.current_track.read().unwrap();
+        let len = self.len();
         let repeat = self.cfg.state().repeat;
 
         if repeat == RepeatSetting::RepeatTrack && !manual {
@@ -350,50 +968,201 @@ impl Queue {
             }
         } else if let Some(index) = self.next_index() {
             self.play(index, false, false);
-            if repeat == RepeatSetting::RepeatTrack && manual {
+            if repeat == RepeatSetting::RepeatTrack {
                 self.set_repeat(RepeatSetting::RepeatPlaylist);
             }
-        } else if repeat == RepeatSetting::RepeatPlaylist && q.len() > 0 {
-            let random_order = self.random_order.read().unwrap();
-            self.play(
-                random_order.as_ref().map(|o| o[0]).unwrap_or(0),
-                false,
-                false,
-            );
+        } else if (repeat != RepeatSetting::None || self.on_queue_end() == QueueEnd::RepeatAll)
+            && len > 0
+        {
+            // skipping the last item wraps around, which restarts the only item
+            // of a single track queue
+            let first = self
+                .random_order
+                .read()
+                .unwrap()
+                .as_ref()
+                .map(|o| o[0])
+                .unwrap_or(0);
+            self.play(first, false, false);
+            if repeat == RepeatSetting::RepeatTrack && len > 1 {
+                self.set_repeat(RepeatSetting::RepeatPlaylist);
+            }
+        } else if self.on_queue_end() == QueueEnd::Radio && self.extend_autoplay() {
+            // the recommendations are played by `handle_event` once they arrive,
+            // a skipped item stops until then
+            if manual {
+                self.spotify.stop();
+            }
         } else {
             self.spotify.stop();
         }
     }
 
     pub fn previous(&self) {
-        let q = self.queue.read().unwrap();
         let current = *self.current_track.read().unwrap();
+        let len = self.len();
         let repeat = self.cfg.state().repeat;
 
         if let Some(index) = self.previous_index() {
             self.play(index, false, false);
-        } else if repeat == RepeatSetting::RepeatPlaylist && q.len() > 0 {
+        } else if repeat == RepeatSetting::RepeatPlaylist && len > 0 {
             if self.get_shuffle() {
-                let random_order = self.random_order.read().unwrap();
-                self.play(
-                    random_order.as_ref().map(|o| o[q.len() - 1]).unwrap_or(0),
-                    false,
-                    false,
-                );
+                let last = self
+                    .random_order
+                    .read()
+                    .unwrap()
+                    .as_ref()
+                    .map(|o| o[len - 1])
+                    .unwrap_or(0);
+                self.play(last, false, false);
             } else {
-                self.play(q.len() - 1, false, false);
+                self.play(len - 1, false, false);
             }
         } else if let Some(index) = current {
             self.play(index, false, false);
         }
     }
 
+    /// Whether going back restarts the current item rather than playing the
+    /// previous one, depending on `previous_track_threshold_secs`.
+    pub fn restarts_current(&self) -> bool {
+        let threshold = self.cfg.values().previous_track_threshold_secs.unwrap_or(5);
+        self.get_current_index().is_some()
+            && restart_threshold_reached(self.spotify.get_current_progress(), threshold)
+    }
+
+    /// Restarts the current item or goes back to the previous one, shared by
+    /// the `previous` command and MPRIS.
+    pub fn previous_or_restart(&self) {
+        if self.restarts_current() {
+            self.spotify.seek(0);
+        } else {
+            self.previous();
+        }
+    }
+
+    /// Seeks relative to the current position, skipping to the next item when
+    /// seeking past the end of the current one.
+    pub fn seek_relative(&self, delta_ms: i64) {
+        if let Some(current) = self.get_current() {
+            let progress = self.spotify.get_current_progress().as_millis() as i64;
+            match seek_target(progress, delta_ms, current.duration()) {
+                Some(position) => self.spotify.seek(position),
+                None => self.next(true),
+            }
+        }
+    }
+
+    /// Seeks to `position_ms` in the current item, skipping to the next one if
+    /// it is past the end like `seek_relative`.
+    pub fn seek_to(&self, position_ms: u32) {
+        if let Some(current) = self.get_current() {
+            match seek_target(0, position_ms as i64, current.duration()) {
+                Some(position) => self.spotify.seek(position),
+                None => self.next(true),
+            }
+        }
+    }
+
+    /// Sets the number of seconds skipped at the start of the episodes of a
+    /// show, `None` clears it. The playing episode skips ahead right away.
+    pub fn set_show_start_offset(&self, show_id: &str, seconds: Option<u32>) {
+        self.cfg.with_state_mut(|mut s| match seconds {
+            Some(seconds) => {
+                s.show_start_offsets.insert(show_id.to_string(), seconds);
+            }
+            None => {
+                s.show_start_offsets.remove(show_id);
+            }
+        });
+
+        if let Some(current) = self.get_current() {
+            let start = self.start_position(&current);
+            if start as u128 > self.spotify.get_current_progress().as_millis() {
+                self.spotify.seek(start);
+            }
+        }
+    }
+
+    /// Position to start playing `playable` at, which is past the intro for
+    /// episodes of shows with a start offset, unless that's past their end.
+    fn start_position(&self, playable: &Playable) -> u32 {
+        let offset_ms = match playable {
+            Playable::Episode(Episode {
+                show_id: Some(show_id),
+                ..
+            }) => self
+                .cfg
+                .state()
+                .show_start_offsets
+                .get(show_id)
+                .map_or(0, |seconds| seconds.saturating_mul(1000)),
+            _ => 0,
+        };
+        if offset_ms < playable.duration() {
+            offset_ms
+        } else {
+            0
+        }
+    }
+
+    /// Order to play the episodes of shows in, `default` if it isn't configured.
+    pub fn show_play_order(&self, default: ShowPlayOrder) -> ShowPlayOrder {
+        self.cfg.values().show_play_order.unwrap_or(default)
+    }
+
+    /// Seeks ahead by `seek_forward_secs`.
+    pub fn seek_forward(&self) {
+        let step = self.cfg.values().seek_forward_secs.unwrap_or(5);
+        self.seek_relative(step as i64 * 1000);
+    }
+
+    /// Seeks back by `seek_backward_secs`.
+    pub fn seek_backward(&self) {
+        let step = self.cfg.values().seek_backward_secs.unwrap_or(5);
+        self.seek_relative(-(step as i64) * 1000);
+    }
+
+    /// Changes the playback rate. Only episodes may be played at a rate other
+    /// than 1.0, as changing the speed of music isn't desired.
+    pub fn set_playback_rate(&self, rate: f64) {
+        let rate = match self.get_current() {
+            Some(Playable::Track(_)) if rate != 1.0 => {
+                warn!("playback rate {} is only supported for episodes", rate);
+                1.0
+            }
+            _ => rate,
+        };
+        self.spotify.set_playback_rate(rate);
+        self.ev.send(Event::PlaybackModeChanged);
+    }
+
+    /// Turns volume normalisation on or off, the current item continues where
+    /// it was in the new player.
+    pub fn set_normalisation(&self, enabled: bool) {
+        if self.spotify.normalisation() == enabled {
+            return;
+        }
+        self.spotify.set_normalisation(enabled);
+
+        let status = self.spotify.get_current_status();
+        if let (Some(current), PlayerEvent::Playing(_) | PlayerEvent::Paused(_)) =
+            (self.get_current(), &status)
+        {
+            let position_ms = self.spotify.get_current_progress().as_millis() as u32;
+            let playing = matches!(status, PlayerEvent::Playing(_));
+            self.spotify.load(&current, playing, position_ms);
+        }
+        self.ev.send(Event::PlaybackModeChanged);
+    }
+
     pub fn get_repeat(&self) -> RepeatSetting {
         self.cfg.state().repeat
     }
 
     pub fn set_repeat(&self, new: RepeatSetting) {
         self.cfg.with_state_mut(|mut s| s.repeat = new);
+        self.ev.send(Event::PlaybackModeChanged);
     }
 
     pub fn get_shuffle(&self) -> bool {
@@ -405,11 +1174,12 @@ impl Queue {
     }
 
     fn generate_random_order(&self) {
+        let current = *self.current_track.read().unwrap();
         let q = self.queue.read().unwrap();
         let mut order: Vec<usize> = Vec::with_capacity(q.len());
         let mut random: Vec<usize> = (0..q.len()).collect();
 
-        if let Some(current) = *self.current_track.read().unwrap() {
+        if let Some(current) = current {
             order.push(current);
             random.remove(current);
         }
@@ -430,23 +1200,190 @@ impl Queue {
             let mut random_order = self.random_order.write().unwrap();
             *random_order = None;
         }
+        self.ev.send(Event::PlaybackModeChanged);
     }
 
     pub fn handle_event(&self, event: QueueEvent) {
         match event {
             QueueEvent::PreloadTrackRequest => {
-                if let Some(next_index) = self.next_index() {
+                // the track that follows has to be preloaded for gapless playback,
+                // also when repeating it or wrapping around
+                if let Some(next_index) = self.following_index() {
                     let track = self.queue.read().unwrap()[next_index].clone();
                     debug!("Preloading track {} as requested by librespot", track);
                     self.spotify.preload(&track);
                 }
+                self.extend_autoplay();
+            }
+            QueueEvent::TrackUnavailable(uri) => self.skip_unavailable(&uri),
+            QueueEvent::Imported(playables) => self.batch(|| {
+                for playable in playables {
+                    self.append(playable);
+                }
+            }),
+            QueueEvent::ImportFinished { .. } => {}
+            QueueEvent::AutoplayTracks(tracks) => {
+                // playback may have reached the end of the queue while waiting
+                let waiting = self.get_current_index().is_some()
+                    && matches!(
+                        self.spotify.get_current_status(),
+                        PlayerEvent::Stopped | PlayerEvent::FinishedTrack
+                    );
+                if tracks.is_empty() {
+                    warn!("could not find any tracks to autoplay");
+                    if waiting {
+                        self.spotify.stop();
+                    }
+                    return;
+                }
+                info!("autoplay adds {} tracks to the queue", tracks.len());
+                for track in tracks {
+                    self.append(track);
+                }
+                if let Some(next_index) = self.next_index() {
+                    if waiting {
+                        self.play(next_index, false, false);
+                    } else {
+                        // librespot already asked for the next track to preload
+                        let track = self.queue.read().unwrap()[next_index].clone();
+                        self.spotify.preload(&track);
+                    }
+                }
             }
         }
     }
 
+    /// Moves on from the current track if it is the one that couldn't be
+    /// loaded. Neither repeating nor wrapping around, so playback can't get
+    /// stuck on unavailable tracks.
+    fn skip_unavailable(&self, uri: &str) {
+        if self.get_current().map(|playable| playable.uri()).as_deref() != Some(uri) {
+            return;
+        }
+        warn!("skipping unavailable track {}", uri);
+        #[cfg(any(feature = "mpris", test))]
+        self.skipped_unavailable
+            .fetch_add(1, atomic::Ordering::Relaxed);
+        match self.next_index() {
+            Some(index) => self.play(index, false, false),
+            None => self.spotify.stop(),
+        }
+    }
+
+    #[cfg(any(feature = "mpris", test))]
+    pub fn skipped_unavailable(&self) -> usize {
+        self.skipped_unavailable.load(atomic::Ordering::Relaxed)
+    }
+
     pub fn get_spotify(&self) -> Spotify {
         self.spotify.clone()
     }
+
+    /// Runs `f`, which may change the queue many times, e.g. adding several
+    /// items, and notifies about the changes once afterwards. Batches may be
+    /// nested, the changes are notified when the outermost one ends.
+    pub fn batch<F: FnOnce()>(&self, f: F) {
+        self.batch_depth.fetch_add(1, atomic::Ordering::SeqCst);
+        f();
+        let ended = self.batch_depth.fetch_sub(1, atomic::Ordering::SeqCst) == 1;
+        if ended && self.batch_changed.swap(false, atomic::Ordering::SeqCst) {
+            self.notify_change(QueueChange::Replaced);
+        }
+    }
+
+    /// Changes whenever items are added, removed or moved.
+    pub fn version(&self) -> u64 {
+        self.version.load(atomic::Ordering::SeqCst)
+    }
+
+    fn notify_change(&self, change: QueueChange) {
+        self.version.fetch_add(1, atomic::Ordering::SeqCst);
+        if self.batch_depth.load(atomic::Ordering::SeqCst) > 0 {
+            self.batch_changed.store(true, atomic::Ordering::SeqCst);
+            return;
+        }
+        self.ev.send(Event::QueueChanged(change));
+    }
+}
+
+/// The entries of an imported file by line number, with the URI they refer to
+/// or `None` if they aren't a Spotify URI or link. Blank lines and comments,
+/// including M3U directives, are left out.
+fn import_entries(contents: &str) -> Vec<(usize, Option<String>)> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, entry)| {
+            let uri = match UriType::from_uri(entry) {
+                Some(_) => Some(entry.to_string()),
+                None => SpotifyUrl::from_url(entry).map(|url| url.uri()),
+            };
+            (line, uri)
+        })
+        .collect()
+}
+
+/// IDs of the last tracks in `played` to seed recommendations with, the API
+/// takes at most 5.
+fn autoplay_seeds(played: &[Playable]) -> Vec<String> {
+    let mut seeds: Vec<String> = played
+        .iter()
+        .rev()
+        .filter_map(|playable| match playable {
+            Playable::Track(track) => track.id.clone(),
+            Playable::Episode(_) => None,
+        })
+        .take(5)
+        .collect();
+    seeds.reverse();
+    seeds
+}
+
+/// New index of the item at `index` after moving the one at `from` to `to`.
+fn moved_index(index: usize, from: usize, to: usize) -> usize {
+    if index == from {
+        to
+    } else if from < index && index <= to {
+        index - 1
+    } else if to <= index && index < from {
+        index + 1
+    } else {
+        index
+    }
+}
+
+/// Which of the items with the given URIs remain when removing duplicates: the
+/// first occurrence of each URI, or the item at `current` for its URI.
+fn dedup_keep(uris: &[String], current: Option<usize>) -> Vec<bool> {
+    let current_uri = current.map(|index| uris[index].as_str());
+    let mut seen = HashSet::new();
+    uris.iter()
+        .enumerate()
+        .map(|(index, uri)| {
+            if uri.is_empty() || Some(index) == current {
+                true
+            } else if Some(uri.as_str()) == current_uri {
+                false
+            } else {
+                seen.insert(uri.as_str())
+            }
+        })
+        .collect()
+}
+
+/// Position to seek to when moving `delta_ms` from `progress_ms`, clamped at the
+/// start of the item, or `None` if it is past the end.
+pub fn seek_target(progress_ms: i64, delta_ms: i64, duration_ms: u32) -> Option<u32> {
+    let position = progress_ms.saturating_add(delta_ms).max(0);
+    (position < duration_ms as i64).then_some(position as u32)
+}
+
+/// Whether an item that has been playing for `progress` is restarted when going
+/// back, a threshold of 0 seconds always goes to the previous item.
+fn restart_threshold_reached(progress: Duration, threshold_secs: u64) -> bool {
+    threshold_secs > 0 && progress >= Duration::from_secs(threshold_secs)
 }
 
 #[cfg(feature = "notify")]
@@ -464,15 +1401,13 @@ pub fn send_notification(
         .summary(summary_txt)
         .body(body_txt);
 
-    // album cover image
-    if let Some(u) = cover_url {
-        let path = crate::utils::cache_path_for_url(u.to_string());
-        if !path.exists() {
-            if let Err(e) = crate::utils::download(u, path.clone()) {
-                error!("Failed to download cover: {}", e);
-            }
-        }
-        n.icon(path.to_str().unwrap());
+    // album cover image, shared with the cover view and MPRIS clients
+    if let Some(path) = cover_url.and_then(crate::utils::cached_cover) {
+        let path = path.to_string_lossy().into_owned();
+        #[cfg(all(unix, not(target_os = "macos")))]
+        n.hint(Hint::ImagePath(path));
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        n.icon(&path);
     }
 
     // XDG desktop entry hints
@@ -498,3 +1433,669 @@ pub fn send_notification(
         Err(e) => error!("Failed to send notification cover: {}", e),
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    use super::{
+        autoplay_seeds, dedup_keep, import_entries, moved_index, restart_threshold_reached,
+        seek_target, PlaybackContext, Queue, QueueEvent, RepeatSetting,
+    };
+    use crate::config::{self, Config};
+    use crate::events::{Event, EventManager};
+    use crate::library::Library;
+    use crate::model::episode::Episode;
+    use crate::model::playable::Playable;
+    use crate::model::track::Track;
+    use crate::spotify::Spotify;
+    use crate::spotify_worker::WorkerCommand;
+
+    /// A queue backed by a detached `Spotify` instance, whose worker commands
+    /// can be inspected.
+    pub(crate) struct Fixture {
+        pub spotify: Spotify,
+        #[cfg_attr(not(feature = "mpris_bus_tests"), allow(dead_code))]
+        pub library: Arc<Library>,
+        pub queue: Arc<Queue>,
+        pub ev: EventManager,
+        #[cfg_attr(not(feature = "mpris_bus_tests"), allow(dead_code))]
+        pub cfg: Arc<Config>,
+        worker: UnboundedReceiver<WorkerCommand>,
+        _callbacks: Box<dyn std::any::Any>,
+    }
+
+    impl Fixture {
+        pub fn new() -> Fixture {
+            let (cb_sink, callbacks) = crossbeam_channel::unbounded();
+            let ev = EventManager::new(cb_sink);
+            let cfg = test_config();
+            let (spotify, worker) = Spotify::new_detached(ev.clone(), cfg.clone());
+            let library = Arc::new(Library::new_detached(&ev, spotify.clone(), cfg.clone()));
+            let queue = Arc::new(Queue::new(
+                &ev,
+                spotify.clone(),
+                cfg.clone(),
+                library.clone(),
+            ));
+
+            Fixture {
+                spotify,
+                library,
+                queue,
+                ev,
+                cfg,
+                worker,
+                _callbacks: Box::new(callbacks),
+            }
+        }
+
+        /// Returns the commands sent to the worker since the last call.
+        pub fn worker_commands(&mut self) -> Vec<WorkerCommand> {
+            std::iter::from_fn(|| self.worker.try_recv().ok()).collect()
+        }
+    }
+
+    /// Default configuration, kept in a temporary directory.
+    fn test_config() -> Arc<Config> {
+        let base_path = std::env::temp_dir().join(format!("ncspot-tests-{}", std::process::id()));
+        *config::BASE_PATH.write().unwrap() = Some(base_path);
+        Arc::new(Config::new("config.toml"))
+    }
+
+    pub(crate) fn track(id: &str) -> Playable {
+        Playable::Track(Track::with_id(id))
+    }
+
+    fn ids(queue: &Queue) -> Vec<String> {
+        let q = queue.queue.read().unwrap();
+        q.iter().filter_map(Playable::id).collect()
+    }
+
+    fn current(queue: &Queue) -> Option<String> {
+        queue.get_current().and_then(|p| p.id())
+    }
+
+    /// Directory that is removed again at the end of a test.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = std::env::temp_dir().join(format!("ncspot-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn test_autoplay_seeds() {
+        let played: Vec<Playable> = ["a", "b", "c", "d", "e", "f"]
+            .iter()
+            .map(|id| track(id))
+            .chain(std::iter::once(Playable::Track(Track {
+                id: None,
+                uri: String::new(),
+                ..Track::with_id("")
+            })))
+            .collect();
+        assert_eq!(autoplay_seeds(&played), ["b", "c", "d", "e", "f"]);
+        assert_eq!(autoplay_seeds(&played[..2]), ["a", "b"]);
+        assert!(autoplay_seeds(&played[6..]).is_empty());
+    }
+
+    #[test]
+    fn test_moved_index() {
+        let moved =
+            |from, to| -> Vec<usize> { (0..5).map(|index| moved_index(index, from, to)).collect() };
+        assert_eq!(moved(1, 3), [0, 3, 1, 2, 4]);
+        assert_eq!(moved(3, 1), [0, 2, 3, 1, 4]);
+        assert_eq!(moved(2, 2), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_import_entries() {
+        let contents = "#EXTM3U\n\
+            #EXTINF:213,Rick Astley - Never Gonna Give You Up\n\
+            spotify:track:4uLU6hMCjMI75M1A2tKUQC\n\
+            \n  https://open.spotify.com/album/2noRn2Aes5aoNVsU6iWThc?si=abc  \n\
+            not a link\n\
+            spotify:local:Artist:Album:Title:180\n";
+        assert_eq!(
+            import_entries(contents),
+            [
+                (3, Some("spotify:track:4uLU6hMCjMI75M1A2tKUQC".into())),
+                (5, Some("spotify:album:2noRn2Aes5aoNVsU6iWThc".into())),
+                (6, None),
+                (7, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dedup_keep() {
+        let uris: Vec<String> = ["a", "b", "a", "", "c", "b", ""]
+            .iter()
+            .map(|uri| uri.to_string())
+            .collect();
+        assert_eq!(
+            dedup_keep(&uris, None),
+            vec![true, true, false, true, true, false, true]
+        );
+        assert_eq!(
+            dedup_keep(&uris, Some(5)),
+            vec![true, false, false, true, true, true, true]
+        );
+    }
+
+    #[test]
+    fn test_seek_target() {
+        assert_eq!(seek_target(10_000, 15_000, 60_000), Some(25_000));
+        assert_eq!(seek_target(10_000, -30_000, 60_000), Some(0));
+        assert_eq!(seek_target(50_000, 10_000, 60_000), None);
+        assert_eq!(seek_target(50_000, 9_999, 60_000), Some(59_999));
+        assert_eq!(seek_target(0, i64::MAX, 60_000), None);
+    }
+
+    #[test]
+    fn test_restart_threshold_reached() {
+        assert!(!restart_threshold_reached(Duration::from_millis(4999), 5));
+        assert!(restart_threshold_reached(Duration::from_secs(5), 5));
+        assert!(restart_threshold_reached(Duration::from_secs(60), 5));
+        assert!(!restart_threshold_reached(Duration::from_secs(60), 0));
+    }
+
+    #[test]
+    fn test_preload_following_track() {
+        let mut fixture = Fixture::new();
+        fixture.queue.append(track("first"));
+        fixture.queue.append(track("second"));
+        fixture.queue.play(1, false, false);
+        let preloaded = |fixture: &mut Fixture| -> Vec<String> {
+            fixture.queue.handle_event(QueueEvent::PreloadTrackRequest);
+            fixture
+                .worker_commands()
+                .into_iter()
+                .filter_map(|command| match command {
+                    WorkerCommand::Preload(playable) => playable.id(),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert!(preloaded(&mut fixture).is_empty());
+        fixture.queue.set_repeat(RepeatSetting::RepeatPlaylist);
+        assert_eq!(preloaded(&mut fixture), ["first"]);
+        fixture.queue.set_repeat(RepeatSetting::RepeatTrack);
+        assert_eq!(preloaded(&mut fixture), ["second"]);
+    }
+
+    #[test]
+    fn test_next_skips_repeated_track() {
+        let mut fixture = Fixture::new();
+        fixture.queue.append(track("first"));
+        fixture.queue.append(track("second"));
+        fixture.queue.play(0, false, false);
+        fixture.queue.set_repeat(RepeatSetting::RepeatTrack);
+        fixture.worker_commands();
+
+        // unlike reaching the end of the track, skipping it doesn't replay it
+        fixture.queue.next(true);
+        assert_eq!(fixture.queue.get_current_index(), Some(1));
+        assert!(matches!(
+            &fixture.worker_commands()[..],
+            [WorkerCommand::Load(playable, true, 0)] if playable.id().as_deref() == Some("second")
+        ));
+        assert_eq!(fixture.queue.get_repeat(), RepeatSetting::RepeatPlaylist);
+    }
+
+    #[test]
+    fn test_show_start_offset() {
+        let mut fixture = Fixture::new();
+        let episode = |id: &str, show_id: Option<&str>| {
+            Playable::Episode(Episode {
+                id: id.to_string(),
+                uri: format!("spotify:episode:{}", id),
+                duration: 600_000,
+                name: id.to_string(),
+                description: String::new(),
+                release_date: String::new(),
+                show_name: None,
+                show_publisher: None,
+                show_id: show_id.map(str::to_string),
+                cover_url: None,
+                added_at: None,
+                list_index: 0,
+            })
+        };
+        fixture.queue.append(episode("intro", Some("show")));
+        fixture.queue.append(episode("other", Some("other")));
+        fixture.queue.append(episode("unknown", None));
+        fixture.queue.set_show_start_offset("show", Some(90));
+        fixture.queue.set_show_start_offset("other", Some(900));
+        fixture.queue.set_show_start_offset("unknown", Some(30));
+
+        let start = |fixture: &mut Fixture, index| {
+            fixture.queue.play(index, false, false);
+            match &fixture.worker_commands()[..] {
+                [WorkerCommand::Load(_, true, position)] => *position,
+                commands => panic!("expected a single load, got {} commands", commands.len()),
+            }
+        };
+        assert_eq!(start(&mut fixture, 0), 90_000);
+        // offsets past the end of the episode are ignored
+        assert_eq!(start(&mut fixture, 1), 0);
+        assert_eq!(start(&mut fixture, 2), 0);
+
+        fixture.queue.set_show_start_offset("show", None);
+        assert_eq!(start(&mut fixture, 0), 0);
+    }
+
+    #[test]
+    fn test_skip_unavailable() {
+        let mut fixture = Fixture::new();
+        for id in ["first", "second"] {
+            fixture.queue.append(track(id));
+        }
+        fixture.queue.play(0, false, false);
+        fixture.queue.set_repeat(RepeatSetting::RepeatTrack);
+        fixture.worker_commands();
+        let unavailable = |id: &str| QueueEvent::TrackUnavailable(format!("spotify:track:{}", id));
+
+        // a late report for a track that isn't current anymore is ignored
+        fixture.queue.handle_event(unavailable("second"));
+        assert!(fixture.worker_commands().is_empty());
+
+        // the repeated track isn't retried
+        fixture.queue.handle_event(unavailable("first"));
+        assert!(matches!(
+            &fixture.worker_commands()[..],
+            [WorkerCommand::Load(playable, true, 0)] if playable.id().as_deref() == Some("second")
+        ));
+
+        // nor does the queue wrap around at its end
+        fixture.queue.handle_event(unavailable("second"));
+        assert!(matches!(
+            &fixture.worker_commands()[..],
+            [WorkerCommand::Stop]
+        ));
+        assert_eq!(fixture.queue.skipped_unavailable(), 2);
+    }
+
+    #[test]
+    fn test_repeat_single_track() {
+        let mut fixture = Fixture::new();
+        fixture.queue.append(track("only"));
+        fixture.queue.play(0, false, false);
+        fixture.queue.set_repeat(RepeatSetting::RepeatTrack);
+        fixture.worker_commands();
+        let reloaded = |fixture: &mut Fixture| {
+            matches!(
+                &fixture.worker_commands()[..],
+                [WorkerCommand::Load(playable, true, 0)] if playable.id().as_deref() == Some("only")
+            )
+        };
+
+        // the end of the track repeats it
+        fixture.queue.next(false);
+        assert!(reloaded(&mut fixture));
+
+        // skipping it restarts it as well instead of stopping
+        assert!(fixture.queue.has_next());
+        fixture.queue.next(true);
+        assert!(reloaded(&mut fixture));
+        assert_eq!(fixture.queue.get_current_index(), Some(0));
+        assert_eq!(fixture.queue.get_repeat(), RepeatSetting::RepeatTrack);
+
+        // without repeat skipping the last track stops
+        fixture.queue.set_repeat(RepeatSetting::None);
+        fixture.queue.next(true);
+        assert!(!reloaded(&mut fixture));
+    }
+
+    #[test]
+    fn test_restart_context() {
+        let mut fixture = Fixture::new();
+
+        fixture.queue.append(track("queued"));
+        assert!(!fixture.queue.restart_context());
+
+        fixture.queue.set_context(Some(PlaybackContext {
+            name: "Album".to_string(),
+            uri: "spotify:album:6XhjNHCyCDyyGJRM5mg40G".to_string(),
+        }));
+        let index = fixture
+            .queue
+            .append_next(&vec![track("first"), track("second")]);
+        fixture.queue.play(index + 1, false, false);
+        // items removed before the context don't break restarting it
+        fixture.queue.remove(0);
+        fixture.worker_commands();
+
+        assert!(fixture.queue.restart_context());
+        assert_eq!(fixture.queue.get_current_index(), Some(0));
+        assert!(matches!(
+            &fixture.worker_commands()[..],
+            [WorkerCommand::Load(playable, true, 0)] if playable.id().as_deref() == Some("first")
+        ));
+    }
+
+    #[test]
+    fn test_queue_insert() {
+        let fixture = Fixture::new();
+        fixture.queue.append(track("first"));
+        fixture.queue.append(track("second"));
+        fixture.queue.play(1, false, false);
+
+        // inserting before the current track keeps it playing
+        fixture.queue.insert(0, track("front"));
+        assert_eq!(fixture.queue.get_current_index(), Some(2));
+        fixture.queue.insert(2, track("middle"));
+        assert_eq!(fixture.queue.get_current_index(), Some(3));
+        fixture.queue.insert(10, track("back"));
+        assert_eq!(
+            ids(&fixture.queue),
+            ["front", "first", "middle", "second", "back"]
+        );
+
+        // the shuffled order still covers every item once
+        fixture.queue.set_shuffle(true);
+        fixture.queue.insert(1, track("shuffled"));
+        let mut order = fixture.queue.get_random_order().unwrap();
+        order.sort_unstable();
+        assert_eq!(order, (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_queue_batch() {
+        let fixture = Fixture::new();
+        let queue_changes = || {
+            fixture
+                .ev
+                .msg_iter()
+                .filter(|event| matches!(event, Event::QueueChanged(_)))
+                .count()
+        };
+
+        fixture.queue.append(track("a"));
+        fixture.queue.append(track("b"));
+        assert_eq!(queue_changes(), 2);
+
+        fixture.queue.batch(|| {
+            for id in ["c", "d", "e"] {
+                fixture.queue.append(track(id));
+            }
+            fixture.queue.remove(0);
+        });
+        assert_eq!(queue_changes(), 1);
+        assert_eq!(fixture.queue.len(), 4);
+
+        // nested batches are reported once the outermost one ends
+        fixture.queue.batch(|| {
+            fixture.queue.batch(|| fixture.queue.append(track("f")));
+            fixture.queue.append(track("g"));
+        });
+        assert_eq!(queue_changes(), 1);
+    }
+
+    #[test]
+    fn test_queue_move() {
+        let fixture = Fixture::new();
+        for id in ["a", "b", "c", "d"] {
+            fixture.queue.append(track(id));
+        }
+        fixture.queue.play(1, false, false);
+
+        // moving the current track
+        assert_eq!(fixture.queue.move_down(1, 5), Some(3));
+        assert_eq!(ids(&fixture.queue), ["a", "c", "d", "b"]);
+        assert_eq!(fixture.queue.get_current_index(), Some(3));
+        assert_eq!(fixture.queue.move_down(3, 1), None);
+
+        // moving across the current track
+        assert!(fixture.queue.move_to(0, 3));
+        assert_eq!(ids(&fixture.queue), ["c", "d", "b", "a"]);
+        assert_eq!(current(&fixture.queue).as_deref(), Some("b"));
+        assert!(!fixture.queue.move_to(0, 4));
+
+        // the shuffled play order follows the items around
+        fixture.queue.set_shuffle(true);
+        let order_ids = |queue: &Queue| -> Vec<String> {
+            let q = queue.queue.read().unwrap();
+            let order = queue.get_random_order().unwrap();
+            order.iter().filter_map(|&i| q[i].id()).collect()
+        };
+        let before = order_ids(&fixture.queue);
+        assert_eq!(fixture.queue.move_up(3, 2), Some(1));
+        assert_eq!(order_ids(&fixture.queue), before);
+        assert_eq!(current(&fixture.queue).as_deref(), Some("b"));
+
+        // moving to next puts the item after the current one in both orders
+        let index = fixture.queue.move_to_next(0).unwrap();
+        let next = fixture.queue.next_index().unwrap();
+        assert_eq!(next, index);
+        assert_eq!(current(&fixture.queue).as_deref(), Some("b"));
+        let current_index = fixture.queue.get_current_index().unwrap();
+        assert_eq!(index, current_index + 1);
+        assert_eq!(ids(&fixture.queue)[index], "c");
+    }
+
+    #[test]
+    fn test_queue_dedup() {
+        let fixture = Fixture::new();
+        for id in ["first", "second", "first", "third", "second", "first"] {
+            fixture.queue.append(track(id));
+        }
+        fixture.queue.set_shuffle(true);
+        // the current track is the second occurrence of "first"
+        fixture.queue.play(2, false, false);
+
+        assert_eq!(fixture.queue.dedup(), 3);
+        assert_eq!(ids(&fixture.queue), ["second", "first", "third"]);
+        assert_eq!(fixture.queue.get_current_index(), Some(1));
+        assert_eq!(current(&fixture.queue).as_deref(), Some("first"));
+        let mut order = fixture.queue.get_random_order().unwrap();
+        order.sort_unstable();
+        assert_eq!(order, [0, 1, 2]);
+        assert_eq!(fixture.queue.len(), 3);
+
+        // nothing left to remove
+        assert_eq!(fixture.queue.dedup(), 0);
+    }
+
+    #[test]
+    fn test_queue_dedup_repeat() {
+        let fixture = Fixture::new();
+        for id in ["first", "first", "second"] {
+            fixture.queue.append(track(id));
+        }
+        fixture.queue.set_repeat(RepeatSetting::RepeatTrack);
+        fixture.queue.play(1, false, false);
+
+        assert_eq!(fixture.queue.dedup(), 1);
+        assert_eq!(fixture.queue.len(), 2);
+        assert_eq!(fixture.queue.get_current_index(), Some(0));
+        assert_eq!(fixture.queue.get_repeat(), RepeatSetting::RepeatTrack);
+        // the repeated track keeps playing on the same item
+        assert_eq!(fixture.queue.following_index(), Some(0));
+    }
+
+    #[test]
+    fn test_queue_export() {
+        let fixture = Fixture::new();
+        for id in ["first", "second"] {
+            fixture.queue.append(track(id));
+        }
+        let dir = TempDir::new("export");
+        let path = |extension: &str| dir.0.join(format!("queue.{}", extension));
+
+        assert_eq!(fixture.queue.export(&path("json")), Ok(2));
+        let exported: Vec<Playable> =
+            serde_json::from_str(&std::fs::read_to_string(path("json")).unwrap()).unwrap();
+        let ids: Vec<String> = exported.iter().filter_map(Playable::id).collect();
+        assert_eq!(ids, ["first", "second"]);
+
+        assert_eq!(fixture.queue.export(&path("m3u")), Ok(2));
+        let playlist = std::fs::read_to_string(path("m3u")).unwrap();
+        assert!(playlist.starts_with("#EXTM3U\n"));
+        assert!(playlist.contains("\nspotify:track:second\n"));
+
+        assert!(fixture.queue.export(&path("txt")).is_err());
+        assert!(!path("txt").exists());
+    }
+
+    #[test]
+    fn test_queue_import() {
+        let fixture = Fixture::new();
+        for id in ["first", "second"] {
+            fixture.queue.append(track(id));
+        }
+        let dir = TempDir::new("import");
+        let path = |extension: &str| dir.0.join(format!("queue.{}", extension));
+        // hands the queue events to the queue like the main loop, until the
+        // import is finished
+        let finish_import = |fixture: &Fixture| -> Vec<usize> {
+            for _ in 0..100 {
+                for event in fixture.ev.msg_iter() {
+                    match event {
+                        Event::Queue(QueueEvent::ImportFinished { skipped, .. }) => return skipped,
+                        Event::Queue(event) => fixture.queue.handle_event(event),
+                        _ => {}
+                    }
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            panic!("import did not finish");
+        };
+
+        fixture.queue.export(&path("json")).unwrap();
+        fixture.queue.clear();
+        assert_eq!(fixture.queue.import(&path("json")), Ok(2));
+        assert!(finish_import(&fixture).is_empty());
+        assert_eq!(ids(&fixture.queue), ["first", "second"]);
+
+        std::fs::write(
+            path("txt"),
+            "# comment\nnot a link\n\nspotify:local:a:b:c:1\n",
+        )
+        .unwrap();
+        assert_eq!(fixture.queue.import(&path("txt")), Ok(2));
+        assert_eq!(finish_import(&fixture), [2, 4]);
+        assert_eq!(fixture.queue.len(), 2);
+
+        assert!(fixture.queue.import(&path("missing")).is_err());
+    }
+
+    #[test]
+    fn test_queue_clear_after_current() {
+        let fixture = Fixture::new();
+        let fill = |queue: &Queue| {
+            queue.clear();
+            for id in ["first", "second", "third", "fourth"] {
+                queue.append(track(id));
+            }
+        };
+
+        // nothing is playing
+        fill(&fixture.queue);
+        assert_eq!(fixture.queue.clear_after_current(), 0);
+        assert_eq!(fixture.queue.len(), 4);
+
+        // the last item
+        fixture.queue.play(3, false, false);
+        assert_eq!(fixture.queue.clear_after_current(), 0);
+        assert_eq!(fixture.queue.len(), 4);
+
+        // the first item
+        fixture.queue.play(0, false, false);
+        assert_eq!(fixture.queue.clear_after_current(), 3);
+        assert_eq!(ids(&fixture.queue), ["first"]);
+        assert_eq!(fixture.queue.get_current_index(), Some(0));
+        assert_eq!(fixture.queue.entry_ids.read().unwrap().len(), 1);
+
+        // a middle item, with shuffle enabled
+        fill(&fixture.queue);
+        fixture.queue.play(1, false, false);
+        fixture.queue.set_shuffle(true);
+        assert_eq!(fixture.queue.clear_after_current(), 2);
+        assert_eq!(ids(&fixture.queue), ["first", "second"]);
+        assert_eq!(fixture.queue.get_current_index(), Some(1));
+        assert_eq!(current(&fixture.queue).as_deref(), Some("second"));
+        let mut order = fixture.queue.get_random_order().unwrap();
+        order.sort_unstable();
+        assert_eq!(order, [0, 1]);
+        assert_eq!(fixture.queue.entry_ids.read().unwrap().len(), 2);
+    }
+    #[test]
+    fn test_invalid_playback_rate() {
+        let fixture = Fixture::new();
+        fixture.queue.set_playback_rate(1.5);
+        assert_eq!(fixture.spotify.playback_rate(), 1.5);
+
+        // non-finite rates are ignored instead of being clamped
+        for rate in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            fixture.queue.set_playback_rate(rate);
+            assert_eq!(fixture.spotify.playback_rate(), 1.5);
+        }
+    }
+    #[test]
+    fn test_queue_remove_many() {
+        let mut fixture = Fixture::new();
+        for id in ["first", "second", "third", "fourth"] {
+            fixture.queue.append(track(id));
+        }
+        fixture.queue.play(1, false, false);
+        fixture.worker_commands();
+        let version = fixture.queue.version();
+
+        // the current item is replaced by the next remaining one only once
+        assert_eq!(fixture.queue.remove_many(&[1, 2, 7]), 2);
+        assert_eq!(ids(&fixture.queue), ["first", "fourth"]);
+        assert_eq!(current(&fixture.queue).as_deref(), Some("fourth"));
+        let loaded: Vec<Option<String>> = fixture
+            .worker_commands()
+            .into_iter()
+            .filter_map(|command| match command {
+                WorkerCommand::Load(playable, _, _) => Some(playable.id()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(loaded, [Some("fourth".to_string())]);
+        assert_ne!(fixture.queue.version(), version);
+
+        // moving items changes the version as well
+        let version = fixture.queue.version();
+        assert!(fixture.queue.move_to(0, 1));
+        assert_ne!(fixture.queue.version(), version);
+    }
+
+    #[test]
+    fn test_history_keeps_added_at() {
+        use crate::traits::ListItem;
+        use chrono::{TimeZone, Utc};
+
+        let fixture = Fixture::new();
+        let added_at = Utc.timestamp_opt(1_577_836_800, 0).unwrap();
+        let mut saved = track("saved");
+        saved.set_added_at(Some(added_at));
+        fixture.queue.append(saved);
+        fixture.queue.play(0, false, false);
+
+        // the time it was played is kept apart from when it was saved
+        let history = fixture.library.history.read().unwrap();
+        assert_eq!(
+            history[0].playable.track().unwrap().added_at,
+            Some(added_at)
+        );
+        assert!(history[0].played_at > added_at);
+    }
+}
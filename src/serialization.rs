@@ -105,5 +105,38 @@ impl Serializer for CborSerializer {
     }
 }
 
+pub struct JsonSerializer {}
+impl Serializer for JsonSerializer {
+    fn load<P: AsRef<Path>, T: serde::Serialize + serde::de::DeserializeOwned>(
+        &self,
+        path: P,
+    ) -> Result<T, String> {
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Unable to read {}: {}", path.as_ref().to_string_lossy(), e))?;
+        serde_json::from_str(&contents).map_err(|e| {
+            format!(
+                "Unable to parse JSON {}: {}",
+                path.as_ref().to_string_lossy(),
+                e
+            )
+        })
+    }
+
+    fn write<P: AsRef<Path>, T: serde::Serialize>(&self, path: P, value: T) -> Result<T, String> {
+        let content = serde_json::to_string_pretty(&value)
+            .map_err(|e| format!("Failed serializing value: {}", e))?;
+        fs::write(path.as_ref(), content)
+            .map(|_| value)
+            .map_err(|e| {
+                format!(
+                    "Failed writing content to {}: {}",
+                    path.as_ref().display(),
+                    e
+                )
+            })
+    }
+}
+
 pub static TOML: TomlSerializer = TomlSerializer {};
 pub static CBOR: CborSerializer = CborSerializer {};
+pub static JSON: JsonSerializer = JsonSerializer {};
@@ -3,15 +3,16 @@ use librespot_core::cache::Cache;
 use librespot_core::config::SessionConfig;
 use librespot_core::session::Session;
 use librespot_core::session::SessionError;
+use librespot_core::spotify_id::FileId;
 use librespot_playback::audio_backend::SinkBuilder;
 use librespot_playback::config::PlayerConfig;
 use librespot_playback::mixer::softmixer::SoftMixer;
-use librespot_playback::mixer::MixerConfig;
-use log::{debug, error, info};
+use librespot_playback::mixer::{Mixer, MixerConfig};
+use log::{debug, error, info, warn};
 
 use librespot_playback::audio_backend;
 use librespot_playback::config::Bitrate;
-use librespot_playback::player::Player;
+use librespot_playback::player::{Player, PlayerEvent as LibrespotPlayerEvent};
 
 use futures::channel::oneshot;
 use tokio::sync::mpsc;
@@ -20,17 +21,36 @@ use url::Url;
 
 use std::env;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
+use crate::command::Command;
 use crate::config;
+use crate::crossfade::{FadeSink, TrackFade};
 use crate::events::{Event, EventManager};
+use crate::model::album::Album;
+use crate::model::episode::Episode;
 use crate::model::playable::Playable;
+use crate::model::playlist::Playlist;
+use crate::model::show::Show;
+use crate::model::track::Track;
+use crate::playback_rate::{RateSink, MAX_PLAYBACK_RATE, MIN_PLAYBACK_RATE};
 use crate::spotify_api::WebApi;
 use crate::spotify_worker::{Worker, WorkerCommand};
 
 pub const VOLUME_PERCENT: u16 = ((u16::max_value() as f64) * 1.0 / 100.0) as u16;
 
+/// Delay before a changed volume is written to disk, so a ramp only causes a
+/// single write
+const VOLUME_SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// Time before the sleep timer runs out over which the volume is lowered
+const SLEEP_FADE: Duration = Duration::from_secs(30);
+
+/// Interval at which the volume is changed while fading playback in or out
+const PLAYBACK_FADE_STEP: Duration = Duration::from_millis(20);
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum PlayerEvent {
     Playing(SystemTime),
@@ -48,7 +68,24 @@ pub struct Spotify {
     pub api: WebApi,
     elapsed: Arc<RwLock<Option<Duration>>>,
     since: Arc<RwLock<Option<SystemTime>>>,
+    playback_rate: Arc<RwLock<f64>>,
     channel: Arc<RwLock<Option<mpsc::UnboundedSender<WorkerCommand>>>>,
+    volume_save_pending: Arc<AtomicBool>,
+    /// When the sleep timer runs out, along with a counter that is increased
+    /// whenever it is changed so that replaced timers don't fire
+    sleep_deadline: Arc<RwLock<Option<Instant>>>,
+    sleep_generation: Arc<AtomicUsize>,
+    /// Factors the volume is scaled by while the sleep timer fades out and
+    /// while playback is faded in or out. They only affect the output, so the
+    /// volume reported to the user stays at its target.
+    sleep_gain: Arc<RwLock<f64>>,
+    playback_gain: Arc<RwLock<f64>>,
+    /// Increased whenever playback is paused, resumed or stopped, so that
+    /// fades of earlier requests stop
+    playback_fade_generation: Arc<AtomicUsize>,
+    normalisation: Arc<AtomicBool>,
+    /// Audio files of the loaded and preloaded items, which the cache keeps
+    audio_files: Arc<RwLock<Vec<FileId>>>,
     user: Option<String>,
 }
 
@@ -66,7 +103,16 @@ impl Spotify {
             api: WebApi::new(),
             elapsed: Arc::new(RwLock::new(None)),
             since: Arc::new(RwLock::new(None)),
+            playback_rate: Arc::new(RwLock::new(1.0)),
             channel: Arc::new(RwLock::new(None)),
+            volume_save_pending: Arc::new(AtomicBool::new(false)),
+            sleep_deadline: Arc::new(RwLock::new(None)),
+            sleep_generation: Arc::new(AtomicUsize::new(0)),
+            sleep_gain: Arc::new(RwLock::new(1.0)),
+            playback_gain: Arc::new(RwLock::new(1.0)),
+            playback_fade_generation: Arc::new(AtomicUsize::new(0)),
+            normalisation: Arc::new(AtomicBool::new(cfg.values().volnorm.unwrap_or(false))),
+            audio_files: Arc::new(RwLock::new(Vec::new())),
             user: None,
         };
 
@@ -84,6 +130,37 @@ impl Spotify {
         spotify
     }
 
+    /// Creates an instance without a session or worker, the commands meant for
+    /// the worker are handed to the caller instead.
+    #[cfg(test)]
+    pub(crate) fn new_detached(
+        events: EventManager,
+        cfg: Arc<config::Config>,
+    ) -> (Spotify, mpsc::UnboundedReceiver<WorkerCommand>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let spotify = Spotify {
+            events,
+            credentials: Credentials::with_password("", ""),
+            cfg: cfg.clone(),
+            status: Arc::new(RwLock::new(PlayerEvent::Stopped)),
+            api: WebApi::new(),
+            elapsed: Arc::new(RwLock::new(None)),
+            since: Arc::new(RwLock::new(None)),
+            playback_rate: Arc::new(RwLock::new(1.0)),
+            channel: Arc::new(RwLock::new(Some(tx))),
+            volume_save_pending: Arc::new(AtomicBool::new(false)),
+            sleep_deadline: Arc::new(RwLock::new(None)),
+            sleep_generation: Arc::new(AtomicUsize::new(0)),
+            sleep_gain: Arc::new(RwLock::new(1.0)),
+            playback_gain: Arc::new(RwLock::new(1.0)),
+            playback_fade_generation: Arc::new(AtomicUsize::new(0)),
+            normalisation: Arc::new(AtomicBool::new(cfg.values().volnorm.unwrap_or(false))),
+            audio_files: Arc::new(RwLock::new(Vec::new())),
+            user: None,
+        };
+        (spotify, rx)
+    }
+
     pub fn start_worker(&self, user_tx: Option<oneshot::Sender<String>>) {
         let (tx, rx) = mpsc::unbounded_channel();
         *self
@@ -95,6 +172,9 @@ impl Spotify {
             let cfg = self.cfg.clone();
             let events = self.events.clone();
             let volume = self.volume();
+            let playback_rate = self.playback_rate.clone();
+            let normalisation = self.normalisation();
+            let audio_files = self.audio_files.clone();
             let credentials = self.credentials.clone();
             let handle = tokio::runtime::Handle::current();
             handle.spawn(async move {
@@ -106,6 +186,9 @@ impl Spotify {
                     credentials,
                     user_tx,
                     volume,
+                    playback_rate,
+                    normalisation,
+                    audio_files,
                 )
                 .await
             });
@@ -177,6 +260,40 @@ impl Spotify {
         Some(backend.1)
     }
 
+    /// Creates the player for the configured backend. Normalisation is passed
+    /// separately, as it can be changed while ncspot is running.
+    pub(crate) fn create_player(
+        cfg: &Arc<config::Config>,
+        session: &Session,
+        mixer: &dyn Mixer,
+        playback_rate: Arc<RwLock<f64>>,
+        fade: Arc<RwLock<TrackFade>>,
+        normalisation: bool,
+    ) -> (Player, mpsc::UnboundedReceiver<LibrespotPlayerEvent>) {
+        let player_config = PlayerConfig {
+            gapless: cfg.values().gapless.unwrap_or(true),
+            bitrate: Self::bitrate_config(cfg),
+            normalisation,
+            normalisation_pregain_db: cfg.values().volnorm_pregain.unwrap_or(0.0),
+            ..Default::default()
+        };
+
+        let backend_name = cfg.values().backend.clone();
+        let backend =
+            Self::init_backend(backend_name).expect("Could not find an audio playback backend");
+        let audio_format: librespot_playback::config::AudioFormat = Default::default();
+        Player::new(player_config, session.clone(), mixer.get_soft_volume(), {
+            let cfg = cfg.clone();
+            move || {
+                let sink = (backend)(cfg.values().backend_device.clone(), audio_format);
+                // fades are applied to the original stream, before it is stretched
+                let sink = Box::new(RateSink::new(sink, playback_rate));
+                Box::new(FadeSink::new(sink, fade))
+            }
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn worker(
         worker_channel: Arc<RwLock<Option<mpsc::UnboundedSender<WorkerCommand>>>>,
         events: EventManager,
@@ -185,21 +302,10 @@ impl Spotify {
         credentials: Credentials,
         user_tx: Option<oneshot::Sender<String>>,
         volume: u16,
+        playback_rate: Arc<RwLock<f64>>,
+        normalisation: bool,
+        audio_files: Arc<RwLock<Vec<FileId>>>,
     ) {
-        let bitrate_str = cfg.values().bitrate.unwrap_or(320).to_string();
-        let bitrate = Bitrate::from_str(&bitrate_str);
-        if bitrate.is_err() {
-            error!("invalid bitrate, will use 320 instead")
-        }
-
-        let player_config = PlayerConfig {
-            gapless: cfg.values().gapless.unwrap_or(true),
-            bitrate: bitrate.unwrap_or(Bitrate::Bitrate320),
-            normalisation: cfg.values().volnorm.unwrap_or(false),
-            normalisation_pregain_db: cfg.values().volnorm_pregain.unwrap_or(0.0),
-            ..Default::default()
-        };
-
         let session = Self::create_session(&cfg, credentials)
             .await
             .expect("Could not create session");
@@ -210,15 +316,14 @@ impl Spotify {
         let mixer = create_mixer(MixerConfig::default());
         mixer.set_volume(volume);
 
-        let backend_name = cfg.values().backend.clone();
-        let backend =
-            Self::init_backend(backend_name).expect("Could not find an audio playback backend");
-        let audio_format: librespot_playback::config::AudioFormat = Default::default();
-        let (player, player_events) = Player::new(
-            player_config,
-            session.clone(),
-            mixer.get_soft_volume(),
-            move || (backend)(cfg.values().backend_device.clone(), audio_format),
+        let fade = Arc::new(RwLock::new(TrackFade::default()));
+        let (player, player_events) = Self::create_player(
+            &cfg,
+            &session,
+            mixer.as_ref(),
+            playback_rate.clone(),
+            fade.clone(),
+            normalisation,
         );
 
         let mut worker = Worker::new(
@@ -228,6 +333,10 @@ impl Spotify {
             session,
             player,
             mixer,
+            playback_rate,
+            cfg,
+            fade,
+            audio_files,
         );
         debug!("worker thread ready.");
         worker.run_loop().await;
@@ -251,7 +360,7 @@ impl Spotify {
         self.get_elapsed().unwrap_or_else(|| Duration::from_secs(0))
             + self
                 .get_since()
-                .map(|t| t.elapsed().unwrap())
+                .map(|t| t.elapsed().unwrap().mul_f64(self.playback_rate()))
                 .unwrap_or_else(|| Duration::from_secs(0))
     }
 
@@ -289,6 +398,11 @@ impl Spotify {
 
     pub fn load(&self, track: &Playable, start_playing: bool, position_ms: u32) {
         info!("loading track: {:?}", track);
+        self.cancel_playback_fade();
+        *self
+            .playback_rate
+            .write()
+            .expect("could not acquire write lock on playback rate") = 1.0;
         self.send_worker(WorkerCommand::Load(
             track.clone(),
             start_playing,
@@ -326,7 +440,15 @@ impl Spotify {
 
     pub fn play(&self) {
         info!("play()");
-        self.send_worker(WorkerCommand::Play);
+        let fade_ms = self.playback_fade_ms();
+        if fade_ms > 0 && matches!(self.get_current_status(), PlayerEvent::Paused(_)) {
+            self.set_playback_gain(0.0);
+            self.send_worker(WorkerCommand::Play);
+            self.fade_playback(1.0, fade_ms, false);
+        } else {
+            self.cancel_playback_fade();
+            self.send_worker(WorkerCommand::Play);
+        }
     }
 
     pub fn toggleplayback(&self) {
@@ -337,6 +459,16 @@ impl Spotify {
         }
     }
 
+    /// Whether the worker is available to accept commands, which isn't the case
+    /// while the session is being restarted.
+    #[cfg(feature = "mpris")]
+    pub fn is_connected(&self) -> bool {
+        self.channel
+            .read()
+            .expect("can't readlock worker channel")
+            .is_some()
+    }
+
     fn send_worker(&self, cmd: WorkerCommand) {
         let channel = self.channel.read().expect("can't readlock worker channel");
         match channel.as_ref() {
@@ -347,22 +479,81 @@ impl Spotify {
 
     pub fn pause(&self) {
         info!("pause()");
-        self.send_worker(WorkerCommand::Pause);
+        let fade_ms = self.playback_fade_ms();
+        if fade_ms > 0 && matches!(self.get_current_status(), PlayerEvent::Playing(_)) {
+            self.fade_playback(0.0, fade_ms, true);
+        } else {
+            self.cancel_playback_fade();
+            self.send_worker(WorkerCommand::Pause);
+        }
     }
 
     pub fn stop(&self) {
         info!("stop()");
+        self.cancel_playback_fade();
         self.send_worker(WorkerCommand::Stop);
     }
 
-    pub fn seek(&self, position_ms: u32) {
-        self.send_worker(WorkerCommand::Seek(position_ms));
+    /// Duration in milliseconds that playback is faded in for when resuming
+    /// and out for when pausing, 0 if it isn't.
+    fn playback_fade_ms(&self) -> u32 {
+        self.cfg.values().fade_ms.unwrap_or(0)
     }
 
-    pub fn seek_relative(&self, delta: i32) {
-        let progress = self.get_current_progress();
-        let new = (progress.as_secs() * 1000) as i32 + progress.subsec_millis() as i32 + delta;
-        self.seek(std::cmp::max(0, new) as u32);
+    /// Ramps the playback gain to `target` over `fade_ms` in the background,
+    /// pausing once it is reached if `pause` is set. Stops early if playback
+    /// is paused, resumed or stopped again in the meantime.
+    fn fade_playback(&self, target: f64, fade_ms: u32, pause: bool) {
+        let generation = self.playback_fade_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let from = *self.playback_gain.read().unwrap();
+        let duration = Duration::from_millis(fade_ms.into());
+        let start = Instant::now();
+        let spotify = self.clone();
+        std::thread::spawn(move || {
+            let current = || spotify.playback_fade_generation.load(Ordering::SeqCst) == generation;
+            loop {
+                if !current() {
+                    return;
+                }
+                let progress = (start.elapsed().as_secs_f64() / duration.as_secs_f64()).min(1.0);
+                spotify.set_playback_gain(from + (target - from) * progress);
+                if progress >= 1.0 {
+                    break;
+                }
+                std::thread::sleep(PLAYBACK_FADE_STEP);
+            }
+            if pause && current() {
+                spotify.send_worker(WorkerCommand::Pause);
+                // the worker pauses before applying the volume, so the track
+                // is at its full volume again once it is resumed without a fade
+                spotify.set_playback_gain(1.0);
+            }
+        });
+    }
+
+    /// Stops a running playback fade and restores the full volume.
+    fn cancel_playback_fade(&self) {
+        self.playback_fade_generation.fetch_add(1, Ordering::SeqCst);
+        if *self.playback_gain.read().unwrap() != 1.0 {
+            self.set_playback_gain(1.0);
+        }
+    }
+
+    fn set_playback_gain(&self, gain: f64) {
+        *self.playback_gain.write().unwrap() = gain;
+        self.apply_volume();
+    }
+
+    /// Sends the volume scaled by the sleep timer and playback fades to the
+    /// worker.
+    fn apply_volume(&self) {
+        let gain = *self.sleep_gain.read().unwrap() * *self.playback_gain.read().unwrap();
+        self.send_worker(WorkerCommand::SetVolume(output_volume(self.volume(), gain)));
+    }
+
+    pub fn seek(&self, position_ms: u32) {
+        self.send_worker(WorkerCommand::Seek(position_ms));
+        self.events.send(Event::Seeked(position_ms));
     }
 
     pub fn volume(&self) -> u16 {
@@ -371,8 +562,219 @@ impl Spotify {
 
     pub fn set_volume(&self, volume: u16) {
         info!("setting volume to {}", volume);
-        self.cfg.with_state_mut(|mut s| s.volume = volume);
-        self.send_worker(WorkerCommand::SetVolume(volume));
+        if self.volume() != volume {
+            self.cfg.with_state_mut(|mut s| s.volume = volume);
+            self.schedule_volume_save();
+        }
+        self.apply_volume();
+        self.events.send(Event::VolumeChanged);
+    }
+
+    /// Duration in milliseconds that consecutive tracks overlap for.
+    #[cfg(feature = "mpris")]
+    pub fn crossfade_ms(&self) -> u32 {
+        self.cfg.state().crossfade_ms
+    }
+
+    #[cfg(feature = "mpris")]
+    pub fn set_crossfade_ms(&self, crossfade_ms: u32) {
+        info!("setting crossfade to {}ms", crossfade_ms);
+        self.cfg
+            .with_state_mut(|mut s| s.crossfade_ms = crossfade_ms);
+    }
+
+    /// Whether the volume of tracks is normalised.
+    pub fn normalisation(&self) -> bool {
+        self.normalisation.load(Ordering::SeqCst)
+    }
+
+    /// Replaces the player with one that does or doesn't normalise the volume,
+    /// which stops playback. The current item has to be loaded again afterwards.
+    pub fn set_normalisation(&self, enabled: bool) {
+        info!("setting volume normalisation to {}", enabled);
+        self.normalisation.store(enabled, Ordering::SeqCst);
+        self.send_worker(WorkerCommand::SetNormalisation(enabled));
+    }
+
+    /// Time left until the sleep timer runs out, if it is set.
+    pub fn sleep_timer_remaining(&self) -> Option<Duration> {
+        self.sleep_deadline
+            .read()
+            .unwrap()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Pauses playback or quits once `duration` has passed, depending on the
+    /// configuration, or cancels the timer if `None`. The timer is independent
+    /// of the items being played and only fires once.
+    pub fn set_sleep_timer(&self, duration: Option<Duration>) {
+        info!("setting sleep timer to {:?}", duration);
+        let generation = self.sleep_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        *self.sleep_deadline.write().unwrap() = duration.map(|d| Instant::now() + d);
+        if duration.is_none() {
+            return;
+        }
+
+        let spotify = self.clone();
+        std::thread::spawn(move || {
+            let fade = spotify.cfg.values().sleep_timer_fade.unwrap_or(true);
+            let mut faded = false;
+            while spotify.sleep_generation.load(Ordering::SeqCst) == generation {
+                let remaining = match spotify.sleep_timer_remaining() {
+                    Some(remaining) if !remaining.is_zero() => remaining,
+                    _ => break,
+                };
+                if fade && remaining < SLEEP_FADE {
+                    faded = true;
+                    *spotify.sleep_gain.write().unwrap() = sleep_fade_gain(remaining);
+                    spotify.apply_volume();
+                    std::thread::sleep(remaining.min(Duration::from_millis(500)));
+                } else {
+                    std::thread::sleep(
+                        remaining
+                            .saturating_sub(SLEEP_FADE)
+                            .clamp(Duration::from_millis(1), Duration::from_secs(1)),
+                    );
+                }
+            }
+
+            let expired = spotify.sleep_generation.load(Ordering::SeqCst) == generation;
+            if expired {
+                info!("sleep timer ran out");
+                *spotify.sleep_deadline.write().unwrap() = None;
+                match spotify.cfg.values().sleep_timer_action {
+                    Some(config::SleepTimerAction::Quit) => {
+                        spotify.events.send(Event::Command(Command::Quit))
+                    }
+                    _ => spotify.pause(),
+                }
+            }
+            // the volume is restored for the next time something is played
+            if faded {
+                *spotify.sleep_gain.write().unwrap() = 1.0;
+                spotify.apply_volume();
+            }
+        });
+    }
+
+    /// Persists the user state after `VOLUME_SAVE_DELAY`, writing any further
+    /// volume changes made in the meantime along with it.
+    fn schedule_volume_save(&self) {
+        if self.volume_save_pending.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let pending = self.volume_save_pending.clone();
+        let cfg = self.cfg.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(VOLUME_SAVE_DELAY);
+            pending.store(false, Ordering::SeqCst);
+            cfg.save_state();
+        });
+    }
+
+    pub fn playback_rate(&self) -> f64 {
+        *self
+            .playback_rate
+            .read()
+            .expect("could not acquire read lock on playback rate")
+    }
+
+    /// Changes the playback rate, which is reset to 1.0 whenever a new track is
+    /// loaded.
+    pub fn set_playback_rate(&self, rate: f64) {
+        if !rate.is_finite() {
+            warn!("ignoring invalid playback rate {}", rate);
+            return;
+        }
+        let rate = rate.clamp(MIN_PLAYBACK_RATE, MAX_PLAYBACK_RATE);
+        info!("setting playback rate to {}", rate);
+        let progress = self.get_current_progress();
+        *self
+            .playback_rate
+            .write()
+            .expect("could not acquire write lock on playback rate") = rate;
+
+        // progress is derived from the wall clock, so it has to be rebased on the new rate
+        if self.get_since().is_some() {
+            self.set_since(Some(SystemTime::now() - progress.div_f64(rate)));
+        }
+    }
+
+    /// Resolves a Spotify URI to the items it refers to, in the order they are
+    /// meant to be played.
+    pub fn playables_for_uri(&self, uri: &str) -> Vec<Playable> {
+        let id = &uri[uri.rfind(':').unwrap_or(0) + 1..uri.len()];
+        let playables = match UriType::from_uri(uri) {
+            Some(UriType::Album) => self
+                .api
+                .album(id)
+                .and_then(|a| Album::from(&a).tracks)
+                .map(|tracks| tracks.into_iter().map(Playable::Track).collect()),
+            Some(UriType::Track) => self
+                .api
+                .track(id)
+                .map(|t| vec![Playable::Track(Track::from(&t))]),
+            Some(UriType::Playlist) => self.api.playlist(id).and_then(|p| {
+                let mut playlist = Playlist::from(&p);
+                playlist.load_tracks(self.clone());
+                playlist.tracks
+            }),
+            Some(UriType::Show) => self.api.get_show(id).and_then(|s| {
+                let mut show: Show = (&s).into();
+                show.load_all_episodes(self.clone());
+                let order = self.cfg.values().show_play_order;
+                show.episodes
+                    .is_some()
+                    .then(|| show.playables(order.unwrap_or(config::ShowPlayOrder::OldestFirst)))
+            }),
+            Some(UriType::Episode) => self
+                .api
+                .episode(id)
+                .map(|e| vec![Playable::Episode(Episode::from(&e))]),
+            Some(UriType::Artist) => self
+                .api
+                .artist_top_tracks(id)
+                .map(|tracks| tracks.into_iter().map(Playable::Track).collect()),
+            None => None,
+        };
+        playables.unwrap_or_default()
+    }
+
+    fn bitrate_config(cfg: &config::Config) -> Bitrate {
+        let bitrate_str = cfg.values().bitrate.unwrap_or(320).to_string();
+        let bitrate = Bitrate::from_str(&bitrate_str);
+        if bitrate.is_err() {
+            error!("invalid bitrate, will use 320 instead")
+        }
+        bitrate.unwrap_or(Bitrate::Bitrate320)
+    }
+
+    /// Bitrate the player streams at, in kbit/s.
+    #[cfg(feature = "mpris")]
+    pub fn bitrate(&self) -> u32 {
+        match Self::bitrate_config(&self.cfg) {
+            Bitrate::Bitrate96 => 96,
+            Bitrate::Bitrate160 => 160,
+            Bitrate::Bitrate320 => 320,
+        }
+    }
+
+    /// Name of the device audio is played on. ncspot doesn't act as a Spotify
+    /// Connect receiver or controller, so this is always the local backend.
+    #[cfg(feature = "mpris")]
+    pub fn device_name(&self) -> String {
+        let values = self.cfg.values();
+        values
+            .backend_device
+            .clone()
+            .or_else(|| values.backend.clone())
+            .or_else(|| audio_backend::BACKENDS.first().map(|b| b.0.to_string()))
+            .unwrap_or_default()
+    }
+
+    /// Audio files of the loaded and preloaded items, in all formats.
+    pub fn audio_files_in_use(&self) -> Vec<FileId> {
+        self.audio_files.read().unwrap().clone()
     }
 
     pub fn preload(&self, track: &Playable) {
@@ -384,6 +786,18 @@ impl Spotify {
     }
 }
 
+/// Factor the volume is scaled by while the sleep timer is fading out with
+/// `remaining` time left, lowered linearly over `SLEEP_FADE`.
+fn sleep_fade_gain(remaining: Duration) -> f64 {
+    remaining.min(SLEEP_FADE).as_secs_f64() / SLEEP_FADE.as_secs_f64()
+}
+
+/// Volume sent to the player for the `volume` set by the user, scaled by
+/// `gain`.
+fn output_volume(volume: u16, gain: f64) -> u16 {
+    (volume as f64 * gain.clamp(0.0, 1.0)).round() as u16
+}
+
 #[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub enum UriType {
     Album,
@@ -412,4 +826,11 @@ impl UriType {
             None
         }
     }
+
+    /// Whether the URI refers to a local file that was added to a playlist in
+    /// the official client. These can't be streamed, nor be looked up in the API.
+    #[cfg(feature = "mpris")]
+    pub fn is_local(s: &str) -> bool {
+        s.starts_with("spotify:local:")
+    }
 }
@@ -12,6 +12,8 @@ use futures::channel::oneshot;
 use log::{debug, error, info};
 
 use rspotify::http::HttpError;
+#[cfg(feature = "mpris")]
+use rspotify::model::AudioFeatures;
 use rspotify::model::{
     AlbumId, AlbumType, ArtistId, CursorBasedPage, EpisodeId, FullAlbum, FullArtist, FullEpisode,
     FullPlaylist, FullShow, FullTrack, ItemPositions, Market, Page, PlayableId, PlaylistId,
@@ -23,7 +25,7 @@ use std::collections::HashSet;
 use std::iter::FromIterator;
 use std::sync::{Arc, RwLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
 #[derive(Clone)]
@@ -32,8 +34,12 @@ pub struct WebApi {
     user: Option<String>,
     worker_channel: Arc<RwLock<Option<mpsc::UnboundedSender<WorkerCommand>>>>,
     token_expiration: Arc<RwLock<DateTime<Utc>>>,
+    rate_limited_until: Arc<RwLock<Option<Instant>>>,
 }
 
+/// Minimum time to hold back optional requests after a rate limit was hit
+const RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(30);
+
 impl WebApi {
     pub fn new() -> WebApi {
         WebApi {
@@ -41,6 +47,7 @@ impl WebApi {
             user: None,
             worker_channel: Arc::new(RwLock::new(None)),
             token_expiration: Arc::new(RwLock::new(Utc::now())),
+            rate_limited_until: Arc::new(RwLock::new(None)),
         }
     }
 
@@ -100,6 +107,30 @@ impl WebApi {
         }
     }
 
+    /// Whether the API returned a 429 recently, so that optional requests should be
+    /// skipped until the cooldown elapsed.
+    #[cfg(feature = "mpris")]
+    pub fn rate_limited(&self) -> bool {
+        self.rate_limited_until
+            .read()
+            .unwrap()
+            .map_or(false, |until| Instant::now() < until)
+    }
+
+    /// The id to refer to `playable` with in playlist edits, local files don't have one.
+    fn playable_id(playable: &Playable) -> Option<Box<dyn PlayableId>> {
+        match playable {
+            Playable::Track(track) => track
+                .id
+                .as_ref()
+                .and_then(|id| TrackId::from_id(id).ok())
+                .map(|id| Box::new(id) as Box<dyn PlayableId>),
+            Playable::Episode(episode) => EpisodeId::from_id(&episode.id)
+                .ok()
+                .map(|id| Box::new(id) as Box<dyn PlayableId>),
+        }
+    }
+
     /// retries once when rate limits are hit
     fn api_with_retry<F, R>(&self, cb: F) -> Option<R>
     where
@@ -117,6 +148,10 @@ impl WebApi {
                                 .header("Retry-After")
                                 .and_then(|v| v.parse::<u64>().ok());
                             debug!("rate limit hit. waiting {:?} seconds", waiting_duration);
+                            let cooldown = RATE_LIMIT_COOLDOWN
+                                .max(Duration::from_secs(waiting_duration.unwrap_or(0)));
+                            *self.rate_limited_until.write().unwrap() =
+                                Some(Instant::now() + cooldown);
                             thread::sleep(
                                 Duration::from_secs(waiting_duration.unwrap_or(0) as u64),
                             );
@@ -150,15 +185,8 @@ impl WebApi {
         position: Option<i32>,
     ) -> bool {
         self.api_with_retry(|api| {
-            let trackids: Vec<Box<dyn PlayableId>> = tracks
-                .iter()
-                .map(|playable| {
-                    Box::new(
-                        TrackId::from_id(playable.id().as_ref().unwrap_or(&"".to_string()))
-                            .unwrap(),
-                    ) as Box<dyn PlayableId>
-                })
-                .collect();
+            let trackids: Vec<Box<dyn PlayableId>> =
+                tracks.iter().filter_map(Self::playable_id).collect();
             api.playlist_add_items(
                 &PlaylistId::from_id(playlist_id).unwrap(),
                 trackids.iter().map(|id| id.as_ref()),
@@ -175,22 +203,13 @@ impl WebApi {
         playables: &[Playable],
     ) -> bool {
         self.api_with_retry(move |api| {
-            let playable_ids: Vec<Box<dyn PlayableId>> = playables
+            // local files can't be referred to, so they are left in the playlist
+            let (playable_ids, positions): (Vec<Box<dyn PlayableId>>, Vec<_>) = playables
                 .iter()
-                .map(|playable| match playable {
-                    Playable::Track(track) => {
-                        Box::new(TrackId::from_id(&track.id.clone().unwrap_or_default()).unwrap())
-                            as Box<dyn PlayableId>
-                    }
-                    Playable::Episode(episode) => {
-                        Box::new(EpisodeId::from_id(&episode.id).unwrap()) as Box<dyn PlayableId>
-                    }
+                .filter_map(|playable| {
+                    Some((Self::playable_id(playable)?, [playable.list_index() as u32]))
                 })
-                .collect();
-            let positions = playables
-                .iter()
-                .map(|playable| [playable.list_index() as u32])
-                .collect::<Vec<_>>();
+                .unzip();
             let item_pos: Vec<ItemPositions> = playable_ids
                 .iter()
                 .zip(positions.iter())
@@ -220,18 +239,8 @@ impl WebApi {
         };
 
         if let Some(()) = self.api_with_retry(|api| {
-            let playable_ids: Vec<Box<dyn PlayableId>> = tracks
-                .iter()
-                .map(|playable| match playable {
-                    Playable::Track(track) => {
-                        Box::new(TrackId::from_id(&track.id.clone().unwrap_or_default()).unwrap())
-                            as Box<dyn PlayableId>
-                    }
-                    Playable::Episode(episode) => {
-                        Box::new(EpisodeId::from_id(&episode.id).unwrap()) as Box<dyn PlayableId>
-                    }
-                })
-                .collect();
+            let playable_ids: Vec<Box<dyn PlayableId>> =
+                tracks.iter().filter_map(Self::playable_id).collect();
             api.playlist_replace_items(
                 &PlaylistId::from_id(id).unwrap(),
                 playable_ids.iter().map(|p| p.as_ref()),
@@ -302,6 +311,22 @@ impl WebApi {
         self.api_with_retry(|api| api.track(&tid))
     }
 
+    /// Looks up several tracks at once, at most 50, in the user's market so
+    /// their `is_playable` is set.
+    pub fn tracks(&self, track_ids: &[&str]) -> Option<Vec<FullTrack>> {
+        let tids = track_ids
+            .iter()
+            .map(|id| TrackId::from_id(id).ok())
+            .collect::<Option<Vec<TrackId>>>()?;
+        self.api_with_retry(|api| api.tracks(&tids, Some(&Market::FromToken)))
+    }
+
+    #[cfg(feature = "mpris")]
+    pub fn track_features(&self, track_id: &str) -> Option<AudioFeatures> {
+        let tid = TrackId::from_id(track_id).ok()?;
+        self.api_with_retry(|api| api.track_features(&tid))
+    }
+
     pub fn get_show(&self, show_id: &str) -> Option<FullShow> {
         let sid = ShowId::from_id(show_id).ok()?;
         self.api_with_retry(|api| api.get_a_show(&sid, Some(&Market::FromToken)))
@@ -494,7 +519,14 @@ impl WebApi {
                     Ok(page) => Ok(ApiPage {
                         offset: page.offset,
                         total: page.total,
-                        items: page.items.iter().map(|se| se.into()).collect(),
+                        items: page
+                            .items
+                            .iter()
+                            .map(|se| Episode {
+                                show_id: Some(show_id.clone()),
+                                ..se.into()
+                            })
+                            .collect(),
                     }),
                     Err(e) => Err(e),
                 }
@@ -32,6 +32,19 @@ impl SpotifyUrl {
         }
     }
 
+    /// The Spotify URI of the media, e.g. `spotify:track:4uLU6hMCjMI75M1A2tKUQC`
+    pub fn uri(&self) -> String {
+        let type_seg = match self.uri_type {
+            UriType::Album => "album",
+            UriType::Artist => "artist",
+            UriType::Episode => "episode",
+            UriType::Playlist => "playlist",
+            UriType::Show => "show",
+            UriType::Track => "track",
+        };
+        format!("spotify:{}:{}", type_seg, self.id)
+    }
+
     /// Get media id and type from open.spotify.com url
     ///
     /// ```
@@ -1,16 +1,19 @@
 use crate::config;
+use crate::crossfade::TrackFade;
 use crate::events::{Event, EventManager};
 use crate::model::playable::Playable;
 use crate::queue::QueueEvent;
-use crate::spotify::PlayerEvent;
+use crate::spotify::{PlayerEvent, Spotify};
 use futures::channel::oneshot;
 use futures::{Future, FutureExt};
 use librespot_core::keymaster::Token;
 use librespot_core::session::Session;
-use librespot_core::spotify_id::{SpotifyAudioType, SpotifyId};
+use librespot_core::spotify_id::{FileId, SpotifyAudioType, SpotifyId};
+use librespot_metadata::AudioItem;
 use librespot_playback::mixer::Mixer;
 use librespot_playback::player::{Player, PlayerEvent as LibrespotPlayerEvent};
 use log::{debug, error, info, warn};
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 use std::{pin::Pin, time::SystemTime};
 use tokio::sync::mpsc;
@@ -28,6 +31,7 @@ pub(crate) enum WorkerCommand {
     SetVolume(u16),
     RequestToken(oneshot::Sender<Option<Token>>),
     Preload(Playable),
+    SetNormalisation(bool),
     Shutdown,
 }
 
@@ -40,9 +44,17 @@ pub struct Worker {
     token_task: Pin<Box<dyn Future<Output = ()> + Send>>,
     active: bool,
     mixer: Box<dyn Mixer>,
+    playback_rate: Arc<RwLock<f64>>,
+    cfg: Arc<config::Config>,
+    fade: Arc<RwLock<TrackFade>>,
+    /// URI of the track librespot is loading, until it starts or pauses
+    loading: Option<String>,
+    /// Audio files of the loaded and preloaded items
+    audio_files: Arc<RwLock<Vec<FileId>>>,
 }
 
 impl Worker {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         events: EventManager,
         player_events: mpsc::UnboundedReceiver<LibrespotPlayerEvent>,
@@ -50,6 +62,10 @@ impl Worker {
         session: Session,
         player: Player,
         mixer: Box<dyn Mixer>,
+        playback_rate: Arc<RwLock<f64>>,
+        cfg: Arc<config::Config>,
+        fade: Arc<RwLock<TrackFade>>,
+        audio_files: Arc<RwLock<Vec<FileId>>>,
     ) -> Worker {
         Worker {
             events,
@@ -60,6 +76,11 @@ impl Worker {
             token_task: Box::pin(futures::future::pending()),
             active: false,
             mixer,
+            playback_rate,
+            cfg,
+            fade,
+            loading: None,
+            audio_files,
         }
     }
 }
@@ -100,6 +121,30 @@ impl Worker {
         )
     }
 
+    /// Looks up the audio files of `id` in the background and adds them to the
+    /// ones in use, replacing the previous ones if `replace` is set.
+    fn use_audio_files(&self, id: SpotifyId, replace: bool) {
+        if replace {
+            self.audio_files.write().unwrap().clear();
+        }
+        let session = self.session.clone();
+        let audio_files = self.audio_files.clone();
+        tokio::spawn(async move {
+            match AudioItem::get_audio_item(&session, id).await {
+                Ok(item) => audio_files
+                    .write()
+                    .unwrap()
+                    .extend(item.files.into_values()),
+                Err(e) => warn!("could not look up the audio files of {:?}: {:?}", id, e),
+            }
+        });
+    }
+
+    fn unavailable(&self, uri: String) {
+        self.events
+            .send(Event::Queue(QueueEvent::TrackUnavailable(uri)));
+    }
+
     pub async fn run_loop(&mut self) {
         let mut ui_refresh = time::interval(Duration::from_millis(400));
 
@@ -118,14 +163,26 @@ impl Worker {
                                 info!("player loading track: {:?}", id);
                                 if id.audio_type == SpotifyAudioType::NonPlayable {
                                     warn!("track is not playable");
-                                    self.events.send(Event::Player(PlayerEvent::FinishedTrack));
+                                    self.unavailable(playable.uri());
                                 } else {
+                                    // episodes are spoken, fading them would cut off words
+                                    let fade_ms = match playable {
+                                        Playable::Track(_) => self.cfg.state().crossfade_ms,
+                                        Playable::Episode(_) => 0,
+                                    };
+                                    self.fade.write().unwrap().load(
+                                        fade_ms,
+                                        position_ms,
+                                        playable.duration(),
+                                    );
                                     self.player.load(id, start_playing, position_ms);
+                                    self.use_audio_files(id, true);
+                                    self.loading = Some(playable.uri());
                                 }
                             }
                             Err(e) => {
                                 error!("error parsing uri: {:?}", e);
-                                self.events.send(Event::Player(PlayerEvent::FinishedTrack));
+                                self.unavailable(playable.uri());
                             }
                         }
                     }
@@ -136,9 +193,12 @@ impl Worker {
                         self.player.pause();
                     }
                     Some(WorkerCommand::Stop) => {
+                        self.fade.write().unwrap().stop();
                         self.player.stop();
+                        self.audio_files.write().unwrap().clear();
                     }
                     Some(WorkerCommand::Seek(pos)) => {
+                        self.fade.write().unwrap().seek(pos);
                         self.player.seek(pos);
                     }
                     Some(WorkerCommand::SetVolume(volume)) => {
@@ -151,8 +211,23 @@ impl Worker {
                         if let Ok(id) = SpotifyId::from_uri(&playable.uri()) {
                             debug!("Preloading {:?}", id);
                             self.player.preload(id);
+                            self.use_audio_files(id, false);
                         }
                     }
+                    Some(WorkerCommand::SetNormalisation(enabled)) => {
+                        // librespot only reads the setting when the player is created
+                        self.player.stop();
+                        let (player, player_events) = Spotify::create_player(
+                            &self.cfg,
+                            &self.session,
+                            self.mixer.as_ref(),
+                            self.playback_rate.clone(),
+                            self.fade.clone(),
+                            enabled,
+                        );
+                        self.player = player;
+                        self.player_events = UnboundedReceiverStream::new(player_events);
+                    }
                     Some(WorkerCommand::Shutdown) => {
                         self.player.stop();
                         self.session.shutdown();
@@ -166,11 +241,15 @@ impl Worker {
                         position_ms,
                         duration_ms: _,
                     }) => {
-                        let position = Duration::from_millis(position_ms as u64);
+                        // the start is scaled by the playback rate, so that
+                        // `Spotify::get_current_progress` yields the position in the track
+                        let rate = *self.playback_rate.read().unwrap();
+                        let position = Duration::from_millis(position_ms as u64).div_f64(rate);
                         let playback_start = SystemTime::now() - position;
                         self.events
                             .send(Event::Player(PlayerEvent::Playing(playback_start)));
                         self.active = true;
+                        self.loading = None;
                     }
                     Some(LibrespotPlayerEvent::Paused {
                         play_request_id: _,
@@ -182,13 +261,21 @@ impl Worker {
                         self.events
                             .send(Event::Player(PlayerEvent::Paused(position)));
                         self.active = false;
+                        self.loading = None;
                     }
                     Some(LibrespotPlayerEvent::Stopped { .. }) => {
                         self.events.send(Event::Player(PlayerEvent::Stopped));
                         self.active = false;
                     }
                     Some(LibrespotPlayerEvent::EndOfTrack { .. }) => {
-                        self.events.send(Event::Player(PlayerEvent::FinishedTrack));
+                        // librespot also ends tracks that failed to load
+                        match self.loading.take() {
+                            Some(uri) => self.unavailable(uri),
+                            None => {
+                                self.fade.write().unwrap().ended = true;
+                                self.events.send(Event::Player(PlayerEvent::FinishedTrack));
+                            }
+                        }
                     }
                     Some(LibrespotPlayerEvent::TimeToPreloadNextTrack { .. }) => {
                         self.events
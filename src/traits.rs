@@ -9,6 +9,7 @@ use crate::commands::CommandResult;
 use crate::library::Library;
 use crate::model::album::Album;
 use crate::model::artist::Artist;
+use crate::model::show::Show;
 use crate::model::track::Track;
 use crate::queue::Queue;
 
@@ -19,6 +20,10 @@ pub trait ListItem: Sync + Send + 'static {
         "".to_string()
     }
     fn display_right(&self, library: Arc<Library>) -> String;
+    /// Text a search in the list matches against besides the left column.
+    fn search_text(&self, library: Arc<Library>) -> String {
+        self.display_left(library)
+    }
     fn play(&mut self, queue: Arc<Queue>);
     fn play_next(&mut self, queue: Arc<Queue>);
     fn queue(&mut self, queue: Arc<Queue>);
@@ -43,6 +48,10 @@ pub trait ListItem: Sync + Send + 'static {
         None
     }
 
+    fn show(&self, _queue: Arc<Queue>) -> Option<Show> {
+        None
+    }
+
     fn track(&self) -> Option<Track> {
         None
     }
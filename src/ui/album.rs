@@ -45,7 +45,8 @@ impl AlbumView {
                     queue.clone(),
                     library.clone(),
                 )
-                .with_title("Tracks"),
+                .with_title("Tracks")
+                .with_context(album.playback_context()),
             )
             .tab(
                 "artists",
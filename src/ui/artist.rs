@@ -18,6 +18,7 @@ use crate::ui::tabview::TabView;
 
 pub struct ArtistView {
     artist: Artist,
+    queue: Arc<Queue>,
     tabs: TabView,
 }
 
@@ -79,7 +80,9 @@ impl ArtistView {
 
         tabs.add_tab(
             "top_tracks",
-            ListView::new(top_tracks, queue.clone(), library.clone()).with_title("Top 10"),
+            ListView::new(top_tracks, queue.clone(), library.clone())
+                .with_title("Top 10")
+                .with_context(artist.playback_context()),
         );
 
         tabs.add_tab("albums", albums_view.with_title("Albums"));
@@ -87,11 +90,12 @@ impl ArtistView {
 
         tabs.add_tab(
             "related",
-            ListView::new(related, queue, library).with_title("Related Artists"),
+            ListView::new(related, queue.clone(), library).with_title("Related Artists"),
         );
 
         Self {
             artist: artist.clone(),
+            queue,
             tabs,
         }
     }
@@ -125,6 +129,11 @@ impl ViewExt for ArtistView {
     }
 
     fn on_command(&mut self, s: &mut Cursive, cmd: &Command) -> Result<CommandResult, String> {
+        if let (Command::Discography(group, None), Some(artist_id)) = (cmd, &self.artist.id) {
+            Artist::queue_discography(artist_id, *group, self.queue.clone());
+            return Ok(CommandResult::Consumed(None));
+        }
+
         self.tabs.on_command(s, cmd)
     }
 }
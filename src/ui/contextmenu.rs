@@ -43,21 +43,25 @@ enum ContextMenuAction {
     SelectArtistAction(Artist),
     #[cfg(feature = "share_clipboard")]
     ShareUrl(String),
-    AddToPlaylist(Box<Track>),
+    AddToPlaylist(Vec<Track>),
     ShowRecommendations(Box<Track>),
     ToggleSavedStatus(Box<dyn ListItem>),
     Play(Box<dyn ListItem>),
     PlayNext(Box<dyn ListItem>),
     TogglePlayback,
     Queue(Box<dyn ListItem>),
+    QueueAll(Vec<Box<dyn ListItem>>),
+    PlayNextAll(Vec<Box<dyn ListItem>>),
+    SetSavedAll(Vec<Box<dyn ListItem>>, bool),
 }
 
 impl ContextMenu {
     pub fn add_track_dialog(
         library: Arc<Library>,
         spotify: Spotify,
-        track: Track,
+        tracks: Vec<Track>,
     ) -> NamedView<AddToPlaylistMenu> {
+        let several = tracks.len() > 1;
         let mut list_select: SelectView<Playlist> = SelectView::new();
         let current_user_id = library.user_id.as_ref().unwrap();
 
@@ -69,20 +73,23 @@ impl ContextMenu {
 
         list_select.set_autojump(true);
         list_select.set_on_submit(move |s, selected| {
-            let track = track.clone();
+            let playables: Vec<Playable> = tracks.iter().cloned().map(Playable::Track).collect();
             let mut playlist = selected.clone();
             let spotify = spotify.clone();
             let library = library.clone();
 
-            if playlist.has_track(track.id.as_ref().unwrap_or(&String::new())) {
-                let mut already_added_dialog = Self::track_already_added();
+            if tracks
+                .iter()
+                .any(|track| playlist.has_track(track.id.as_ref().unwrap_or(&String::new())))
+            {
+                let mut already_added_dialog = Self::track_already_added(several);
 
                 already_added_dialog.add_button("Add anyway", move |c| {
                     let mut playlist = playlist.clone();
                     let spotify = spotify.clone();
                     let library = library.clone();
 
-                    playlist.append_tracks(&[Playable::Track(track.clone())], spotify, library);
+                    playlist.append_tracks(&playables, spotify, library);
                     c.pop_layer();
 
                     // Close add_track_dialog too
@@ -92,13 +99,17 @@ impl ContextMenu {
                 let modal = Modal::new(already_added_dialog);
                 s.add_layer(modal);
             } else {
-                playlist.append_tracks(&[Playable::Track(track)], spotify, library);
+                playlist.append_tracks(&playables, spotify, library);
                 s.pop_layer();
             }
         });
 
         let dialog = Dialog::new()
-            .title("Add track to playlist")
+            .title(if several {
+                "Add tracks to playlist"
+            } else {
+                "Add track to playlist"
+            })
             .dismiss_button("Close")
             .padding(Margins::lrtb(1, 1, 1, 0))
             .content(ScrollView::new(list_select.with_name("addplaylist_select")));
@@ -194,11 +205,15 @@ impl ContextMenu {
         .with_name("selectartistaction")
     }
 
-    fn track_already_added() -> Dialog {
-        Dialog::text("This track is already in your playlist")
-            .title("Track already exists")
-            .padding(Margins::lrtb(1, 1, 1, 0))
-            .dismiss_button("Close")
+    fn track_already_added(several: bool) -> Dialog {
+        Dialog::text(if several {
+            "Some of these tracks are already in your playlist"
+        } else {
+            "This track is already in your playlist"
+        })
+        .title("Track already exists")
+        .padding(Margins::lrtb(1, 1, 1, 0))
+        .dismiss_button("Close")
     }
 
     pub fn new(item: &dyn ListItem, queue: Arc<Queue>, library: Arc<Library>) -> NamedView<Self> {
@@ -258,7 +273,7 @@ impl ContextMenu {
         if let Some(t) = item.track() {
             content.add_item(
                 "Add to playlist",
-                ContextMenuAction::AddToPlaylist(Box::new(t.clone())),
+                ContextMenuAction::AddToPlaylist(vec![t.clone()]),
             );
             content.add_item(
                 "Similar tracks",
@@ -292,47 +307,7 @@ impl ContextMenu {
         {
             let library = library.clone();
             content.set_on_submit(move |s: &mut Cursive, action: &ContextMenuAction| {
-                let queue = queue.clone();
-                let library = library.clone();
-                s.pop_layer();
-
-                match action {
-                    ContextMenuAction::ShowItem(item) => {
-                        if let Some(view) = item.open(queue, library) {
-                            s.call_on_name("main", move |v: &mut Layout| v.push_view(view));
-                        }
-                    }
-                    #[cfg(feature = "share_clipboard")]
-                    ContextMenuAction::ShareUrl(url) => {
-                        write_share(url.to_string());
-                    }
-                    ContextMenuAction::AddToPlaylist(track) => {
-                        let dialog =
-                            Self::add_track_dialog(library, queue.get_spotify(), *track.clone());
-                        s.add_layer(dialog);
-                    }
-                    ContextMenuAction::ShowRecommendations(item) => {
-                        if let Some(view) = item.to_owned().open_recommendations(queue, library) {
-                            s.call_on_name("main", move |v: &mut Layout| v.push_view(view));
-                        }
-                    }
-                    ContextMenuAction::SelectArtist(artists) => {
-                        let dialog = Self::select_artist_dialog(library, queue, artists.clone());
-                        s.add_layer(dialog);
-                    }
-                    ContextMenuAction::SelectArtistAction(artist) => {
-                        let dialog =
-                            Self::select_artist_action_dialog(library, queue, artist.clone());
-                        s.add_layer(dialog);
-                    }
-                    ContextMenuAction::ToggleSavedStatus(item) => {
-                        item.as_listitem().toggle_saved(library)
-                    }
-                    ContextMenuAction::Play(item) => item.as_listitem().play(queue),
-                    ContextMenuAction::PlayNext(item) => item.as_listitem().play_next(queue),
-                    ContextMenuAction::TogglePlayback => queue.toggleplayback(),
-                    ContextMenuAction::Queue(item) => item.as_listitem().queue(queue),
-                }
+                Self::handle_action(s, action, queue.clone(), library.clone())
             });
         }
 
@@ -346,6 +321,146 @@ impl ContextMenu {
         }
         .with_name("contextmenu")
     }
+
+    /// Context menu for several marked items at once.
+    pub fn new_marked(
+        items: Vec<Box<dyn ListItem>>,
+        queue: Arc<Queue>,
+        library: Arc<Library>,
+    ) -> NamedView<Self> {
+        let mut content: SelectView<ContextMenuAction> = SelectView::new();
+        let title = format!("{} marked items", items.len());
+
+        if items.iter().any(|item| item.is_playable()) {
+            content.add_item(
+                "Play next",
+                ContextMenuAction::PlayNextAll(clone_items(&items)),
+            );
+            content.add_item("Queue", ContextMenuAction::QueueAll(clone_items(&items)));
+        }
+
+        let tracks: Vec<Track> = items.iter().filter_map(|item| item.track()).collect();
+        if !tracks.is_empty() {
+            content.add_item("Add to playlist", ContextMenuAction::AddToPlaylist(tracks));
+        }
+
+        if items
+            .iter()
+            .any(|item| item.is_saved(library.clone()).is_some())
+        {
+            content.add_item(
+                "Save",
+                ContextMenuAction::SetSavedAll(clone_items(&items), true),
+            );
+            content.add_item("Unsave", ContextMenuAction::SetSavedAll(items, false));
+        }
+
+        content.set_on_submit(move |s: &mut Cursive, action: &ContextMenuAction| {
+            Self::handle_action(s, action, queue.clone(), library.clone())
+        });
+
+        let dialog = Dialog::new()
+            .title(title)
+            .dismiss_button("Close")
+            .padding(Margins::lrtb(1, 1, 1, 0))
+            .content(content.with_name("contextmenu_select"));
+        Self {
+            dialog: Modal::new_ext(dialog),
+        }
+        .with_name("contextmenu")
+    }
+
+    fn handle_action(
+        s: &mut Cursive,
+        action: &ContextMenuAction,
+        queue: Arc<Queue>,
+        library: Arc<Library>,
+    ) {
+        s.pop_layer();
+
+        match action {
+            ContextMenuAction::ShowItem(item) => {
+                if let Some(view) = item.open(queue, library) {
+                    s.call_on_name("main", move |v: &mut Layout| v.push_view(view));
+                }
+            }
+            #[cfg(feature = "share_clipboard")]
+            ContextMenuAction::ShareUrl(url) => {
+                write_share(url.to_string());
+            }
+            ContextMenuAction::AddToPlaylist(tracks) => {
+                let dialog = Self::add_track_dialog(library, queue.get_spotify(), tracks.clone());
+                s.add_layer(dialog);
+            }
+            ContextMenuAction::ShowRecommendations(item) => {
+                if let Some(view) = item.to_owned().open_recommendations(queue, library) {
+                    s.call_on_name("main", move |v: &mut Layout| v.push_view(view));
+                }
+            }
+            ContextMenuAction::SelectArtist(artists) => {
+                let dialog = Self::select_artist_dialog(library, queue, artists.clone());
+                s.add_layer(dialog);
+            }
+            ContextMenuAction::SelectArtistAction(artist) => {
+                let dialog = Self::select_artist_action_dialog(library, queue, artist.clone());
+                s.add_layer(dialog);
+            }
+            ContextMenuAction::ToggleSavedStatus(item) => item.as_listitem().toggle_saved(library),
+            ContextMenuAction::Play(item) => item.as_listitem().play(queue),
+            ContextMenuAction::PlayNext(item) => item.as_listitem().play_next(queue),
+            ContextMenuAction::TogglePlayback => queue.toggleplayback(),
+            ContextMenuAction::Queue(item) => item.as_listitem().queue(queue),
+            ContextMenuAction::QueueAll(items) => queue_items(clone_items(items), queue),
+            ContextMenuAction::PlayNextAll(items) => play_next_items(clone_items(items), queue),
+            ContextMenuAction::SetSavedAll(items, saved) => {
+                set_items_saved(clone_items(items), library, *saved)
+            }
+        }
+    }
+}
+
+fn clone_items(items: &[Box<dyn ListItem>]) -> Vec<Box<dyn ListItem>> {
+    items.iter().map(|item| item.as_listitem()).collect()
+}
+
+/// Appends `items` to the queue in order, notifying about the change once.
+pub fn queue_items(items: Vec<Box<dyn ListItem>>, queue: Arc<Queue>) {
+    queue.batch(|| {
+        for mut item in items {
+            item.queue(queue.clone());
+        }
+    });
+}
+
+/// Inserts `items` after the current item in order, notifying about the
+/// change once.
+pub fn play_next_items(items: Vec<Box<dyn ListItem>>, queue: Arc<Queue>) {
+    queue.batch(|| {
+        for mut item in items.into_iter().rev() {
+            item.play_next(queue.clone());
+        }
+    });
+}
+
+/// Saves `items` to the library or removes them, tracks with a single request.
+pub fn set_items_saved(items: Vec<Box<dyn ListItem>>, library: Arc<Library>, saved: bool) {
+    let (tracks, others): (Vec<_>, Vec<_>) =
+        items.into_iter().partition(|item| item.track().is_some());
+    let tracks: Vec<Track> = tracks.iter().filter_map(|item| item.track()).collect();
+    if !tracks.is_empty() {
+        if saved {
+            library.save_tracks(tracks.iter().collect(), true);
+        } else {
+            library.unsave_tracks(tracks.iter().collect(), true);
+        }
+    }
+    for mut item in others {
+        if saved {
+            item.save(library.clone());
+        } else {
+            item.unsave(library.clone());
+        }
+    }
 }
 
 impl ViewExt for AddToPlaylistMenu {
@@ -180,8 +180,9 @@ impl CoverView {
 
         let loading_thread = self.loading.clone();
         std::thread::spawn(move || {
-            if let Err(e) = crate::utils::download(url.clone(), path.clone()) {
-                error!("Failed to download cover: {}", e);
+            match crate::utils::download(url.clone(), path.clone()) {
+                Ok(()) => crate::cache::cover_added(&path),
+                Err(e) => error!("Failed to download cover: {}", e),
             }
             let mut loading = loading_thread.write().unwrap();
             loading.remove(&url.clone());
@@ -252,7 +253,7 @@ impl ViewExt for CoverView {
 
                 return Ok(CommandResult::Consumed(None));
             }
-            Command::Goto(mode) => {
+            Command::Goto(mode, _) => {
                 if let Some(track) = self.queue.get_current() {
                     let queue = self.queue.clone();
                     let library = self.library.clone();
@@ -279,6 +280,9 @@ impl ViewExt for CoverView {
                                 };
                             }
                         }
+                        // opened from the global handler, as there is no
+                        // cover specific way to show it
+                        GotoMode::Show => {}
                     }
                 }
             }
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
 use cursive::align::HAlign;
@@ -15,6 +16,7 @@ use unicode_width::UnicodeWidthStr;
 
 use crate::command::Command;
 use crate::commands::CommandResult;
+use crate::config::Config;
 use crate::events;
 use crate::traits::{IntoBoxedViewExt, ViewExt};
 
@@ -23,6 +25,9 @@ pub struct Layout {
     stack: HashMap<String, Vec<Box<dyn ViewExt>>>,
     statusbar: Box<dyn View>,
     focus: Option<String>,
+    /// Screen to return to when leaving fullscreen
+    #[cfg(feature = "mpris")]
+    previous_focus: Option<String>,
     pub cmdline: EditView,
     cmdline_focus: bool,
     result: Result<Option<String>, String>,
@@ -31,10 +36,16 @@ pub struct Layout {
     last_size: Vec2,
     ev: events::EventManager,
     theme: Theme,
+    cfg: Arc<Config>,
 }
 
 impl Layout {
-    pub fn new<T: IntoBoxedView>(status: T, ev: &events::EventManager, theme: Theme) -> Layout {
+    pub fn new<T: IntoBoxedView>(
+        status: T,
+        ev: &events::EventManager,
+        theme: Theme,
+        cfg: Arc<Config>,
+    ) -> Layout {
         let style = ColorStyle::new(
             ColorType::Color(*theme.palette.custom("cmdline_bg").unwrap()),
             ColorType::Color(*theme.palette.custom("cmdline").unwrap()),
@@ -45,6 +56,8 @@ impl Layout {
             stack: HashMap::new(),
             statusbar: status.into_boxed_view(),
             focus: None,
+            #[cfg(feature = "mpris")]
+            previous_focus: None,
             cmdline: EditView::new().filler(" ").style(style),
             cmdline_focus: false,
             result: Ok(None),
@@ -53,6 +66,7 @@ impl Layout {
             last_size: Vec2::new(0, 0),
             ev: ev.clone(),
             theme,
+            cfg,
         }
     }
 
@@ -96,12 +110,32 @@ impl Layout {
         }
 
         let s = id.into();
+        self.ev.send(events::Event::ScreenChanged(s.clone()));
         self.focus = Some(s);
         self.cmdline_focus = false;
         self.screenchange = true;
+    }
+
+    /// Switches to the cover screen, or back to the screen that was focussed
+    /// before.
+    #[cfg(feature = "mpris")]
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        let cover = "cover";
+        if !self.has_screen(cover) {
+            return;
+        }
 
-        // trigger a redraw
-        self.ev.trigger();
+        let is_fullscreen = self.focus.as_deref() == Some(cover);
+        if fullscreen && !is_fullscreen {
+            self.previous_focus = self.focus.clone();
+            self.set_screen(cover);
+        } else if !fullscreen && is_fullscreen {
+            let previous = self
+                .previous_focus
+                .take()
+                .unwrap_or_else(|| "library".to_string());
+            self.set_screen(previous);
+        }
     }
 
     pub fn set_result(&mut self, result: Result<Option<String>, String>) {
@@ -292,6 +326,10 @@ impl View for Layout {
     }
 
     fn on_event(&mut self, event: Event) -> EventResult {
+        if matches!(event, Event::Mouse { .. }) && !self.cfg.values().mouse.unwrap_or(true) {
+            return EventResult::Ignored;
+        }
+
         // handle mouse events in cmdline/statusbar area
         if let Event::Mouse {
             position,
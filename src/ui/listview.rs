@@ -1,6 +1,7 @@
 use cursive::view::scroll::Scroller;
 use log::info;
 use std::cmp::{max, min, Ordering};
+use std::collections::HashSet;
 use std::sync::{Arc, RwLock};
 
 use cursive::align::HAlign;
@@ -14,28 +15,33 @@ use unicode_width::UnicodeWidthStr;
 use crate::command::{Command, GotoMode, InsertSource, JumpMode, MoveAmount, MoveMode, TargetMode};
 use crate::commands::CommandResult;
 use crate::ext_traits::CursiveExt;
+use crate::fuzzy::fuzzy_match;
 use crate::library::Library;
 use crate::model::album::Album;
 use crate::model::artist::Artist;
 use crate::model::episode::Episode;
+use crate::model::history::HistoryEntry;
 use crate::model::playable::Playable;
 use crate::model::playlist::Playlist;
 use crate::model::show::Show;
 use crate::model::track::Track;
-use crate::queue::Queue;
+use crate::queue::{PlaybackContext, Queue};
 #[cfg(feature = "share_clipboard")]
 use crate::sharing::{read_share, write_share};
 use crate::spotify::UriType;
 use crate::traits::{IntoBoxedViewExt, ListItem, ViewExt};
 use crate::ui::album::AlbumView;
 use crate::ui::artist::ArtistView;
-use crate::ui::contextmenu::ContextMenu;
+use crate::ui::contextmenu::{play_next_items, queue_items, set_items_saved, ContextMenu};
 use crate::ui::pagination::Pagination;
+use crate::ui::show::ShowView;
 
 pub struct ListView<I: ListItem> {
     content: Arc<RwLock<Vec<I>>>,
     last_content_len: usize,
     selected: usize,
+    /// Indexes of the items marked for a batch operation
+    marked: HashSet<usize>,
     search_query: String,
     search_indexes: Vec<usize>,
     search_selected_index: usize,
@@ -45,6 +51,8 @@ pub struct ListView<I: ListItem> {
     library: Arc<Library>,
     pagination: Pagination<I>,
     title: String,
+    /// Collection the listed tracks are played from
+    context: Option<PlaybackContext>,
 }
 
 impl<I: ListItem> Scroller for ListView<I> {
@@ -63,6 +71,7 @@ impl<I: ListItem + Clone> ListView<I> {
             content,
             last_content_len: 0,
             selected: 0,
+            marked: HashSet::new(),
             search_query: String::new(),
             search_indexes: Vec::new(),
             search_selected_index: 0,
@@ -72,6 +81,7 @@ impl<I: ListItem + Clone> ListView<I> {
             library,
             pagination: Pagination::default(),
             title: "".to_string(),
+            context: None,
         };
         result.try_paginate();
         result
@@ -82,6 +92,11 @@ impl<I: ListItem + Clone> ListView<I> {
         self
     }
 
+    pub fn with_context(mut self, context: Option<PlaybackContext>) -> Self {
+        self.context = context;
+        self
+    }
+
     pub fn get_pagination(&self) -> &Pagination<I> {
         &self.pagination
     }
@@ -129,15 +144,16 @@ impl<I: ListItem + Clone> ListView<I> {
         self.selected
     }
 
+    /// Indexes of the items fuzzily matching `query` in their left column or
+    /// search text, e.g. the artists and title of tracks.
     pub fn get_indexes_of(&self, query: &str) -> Vec<usize> {
         let content = self.content.read().unwrap();
         content
             .iter()
             .enumerate()
             .filter(|(_, i)| {
-                i.display_left(self.library.clone())
-                    .to_lowercase()
-                    .contains(&query[..].to_lowercase())
+                fuzzy_match(&i.display_left(self.library.clone()), query).is_some()
+                    || fuzzy_match(&i.search_text(self.library.clone()), query).is_some()
             })
             .map(|(i, _)| i)
             .collect()
@@ -154,6 +170,48 @@ impl<I: ListItem + Clone> ListView<I> {
         self.move_focus_to(max(new, 0) as usize);
     }
 
+    /// Removes all marks, e.g. when the items they refer to moved.
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+    }
+
+    /// Indexes of the marked items in ascending order, clearing the marks.
+    pub fn take_marked_indexes(&mut self) -> Vec<usize> {
+        let mut indexes: Vec<usize> = self.marked.drain().collect();
+        indexes.sort_unstable();
+        indexes
+    }
+
+    /// The marked items in the order they are listed, clearing the marks.
+    /// `None` if no items are marked.
+    fn take_marked(&mut self) -> Option<Vec<Box<dyn ListItem>>> {
+        if self.marked.is_empty() {
+            return None;
+        }
+        let indexes = self.take_marked_indexes();
+        let content = self.content.read().unwrap();
+        Some(
+            indexes
+                .into_iter()
+                .filter_map(|index| content.get(index).map(|item| item.as_listitem()))
+                .collect(),
+        )
+    }
+
+    /// The context menu of the marked items if there are any, otherwise of
+    /// the selected one.
+    fn context_menu(&mut self) -> Option<cursive::views::NamedView<ContextMenu>> {
+        let queue = self.queue.clone();
+        let library = self.library.clone();
+        if let Some(items) = self.take_marked() {
+            return Some(ContextMenu::new_marked(items, queue, library));
+        }
+        let content = self.content.read().unwrap();
+        content
+            .get(self.selected)
+            .map(|item| ContextMenu::new(item, queue, library))
+    }
+
     fn attempt_play_all_tracks(&self) -> bool {
         let content = self.content.read().unwrap();
         let any = &(*content) as &dyn std::any::Any;
@@ -163,7 +221,13 @@ impl<I: ListItem + Clone> ListView<I> {
                 .map(|t| Playable::Track(t.clone()))
                 .collect::<Vec<Playable>>()
         });
-        if let Some(tracks) = playables.or(tracks.as_ref()) {
+        let history = any.downcast_ref::<Vec<HistoryEntry>>().map(|h| {
+            h.iter()
+                .map(|entry| entry.playable.clone())
+                .collect::<Vec<Playable>>()
+        });
+        if let Some(tracks) = playables.or(tracks.as_ref()).or(history.as_ref()) {
+            self.queue.set_context(self.context.clone());
             let index = self.queue.append_next(tracks);
             self.queue.play(index + self.selected, true, false);
             true
@@ -180,6 +244,12 @@ impl<I: ListItem + Clone> ListView<I> {
 
 impl<I: ListItem + Clone> View for ListView<I> {
     fn draw(&self, printer: &Printer<'_, '_>) {
+        // resolved before locking the content, which is the queue itself for
+        // the queue view
+        let playing_index = self.queue.get_current_index().filter(|&index| {
+            let item = self.content.read().unwrap().get(index).cloned();
+            item.map_or(false, |item| item.is_playing(self.queue.clone()))
+        });
         let content = self.content.read().unwrap();
 
         scroll::draw_lines(self, printer, |_, printer, i| {
@@ -194,8 +264,7 @@ impl<I: ListItem + Clone> View for ListView<I> {
                 });
             } else if i < content.len() {
                 let item = &content[i];
-                let currently_playing = item.is_playing(self.queue.clone())
-                    && self.queue.get_current_index() == Some(i);
+                let currently_playing = playing_index == Some(i);
 
                 let style = if self.selected == i {
                     if currently_playing {
@@ -206,6 +275,8 @@ impl<I: ListItem + Clone> View for ListView<I> {
                     } else {
                         ColorStyle::highlight()
                     }
+                } else if self.marked.contains(&i) {
+                    ColorStyle::highlight_inactive()
                 } else if currently_playing {
                     ColorStyle::new(
                         ColorType::Color(*printer.theme.palette.custom("playing").unwrap()),
@@ -232,12 +303,7 @@ impl<I: ListItem + Clone> View for ListView<I> {
                     let fg = *printer.theme.palette.custom("search_match").unwrap();
                     let matched_style = ColorStyle::new(fg, style.back);
 
-                    let matches: Vec<(usize, usize)> = left
-                        .to_lowercase()
-                        .match_indices(&self.search_query)
-                        .map(|i| (i.0, i.0 + i.1.len()))
-                        .collect();
-
+                    let matches = fuzzy_match(&left, &self.search_query).unwrap_or_default();
                     for m in matches {
                         printer.with_color(matched_style, |printer| {
                             printer.print((left[0..m.0].width(), 0), &left[m.0..m.1]);
@@ -291,6 +357,11 @@ impl<I: ListItem + Clone> View for ListView<I> {
         self.last_size = size;
 
         let relayout_scroller = self.content_len(false) != self.last_content_len;
+        // marks are indexes, which no longer match once items were added or
+        // removed
+        if self.content_len(true) != self.last_content_len {
+            self.marked.clear();
+        }
         self.last_content_len = self.content_len(true);
 
         scroll::layout(
@@ -368,13 +439,7 @@ impl<I: ListItem + Clone> View for ListView<I> {
                 if let Some(y) = selected_row.filter(|row| row < &self.content_len(false)) {
                     self.move_focus_to(y);
 
-                    let queue = self.queue.clone();
-                    let library = self.library.clone();
-                    if let Some(target) = {
-                        let content = self.content.read().unwrap();
-                        content.get(self.selected).map(|t| t.as_listitem())
-                    } {
-                        let contextmenu = ContextMenu::new(&*target, queue, library);
+                    if let Some(contextmenu) = self.context_menu() {
                         return EventResult::Consumed(Some(Callback::from_fn_once(move |s| {
                             s.add_layer(contextmenu)
                         })));
@@ -433,8 +498,20 @@ impl<I: ListItem + Clone> ViewExt for ListView<I> {
 
                 return Ok(CommandResult::Consumed(None));
             }
+            Command::Mark => {
+                if self.selected < self.content_len(false) && !self.marked.remove(&self.selected) {
+                    self.marked.insert(self.selected);
+                }
+                self.move_focus(1);
+
+                return Ok(CommandResult::Consumed(None));
+            }
             Command::PlayNext => {
                 info!("played next");
+                if let Some(items) = self.take_marked() {
+                    play_next_items(items, self.queue.clone());
+                    return Ok(CommandResult::Consumed(None));
+                }
                 let mut content = self.content.write().unwrap();
                 if let Some(item) = content.get_mut(self.selected) {
                     item.play_next(self.queue.clone());
@@ -443,6 +520,10 @@ impl<I: ListItem + Clone> ViewExt for ListView<I> {
                 return Ok(CommandResult::Consumed(None));
             }
             Command::Queue => {
+                if let Some(items) = self.take_marked() {
+                    queue_items(items, self.queue.clone());
+                    return Ok(CommandResult::Consumed(None));
+                }
                 let mut content = self.content.write().unwrap();
                 if let Some(item) = content.get_mut(self.selected) {
                     item.queue(self.queue.clone());
@@ -451,6 +532,10 @@ impl<I: ListItem + Clone> ViewExt for ListView<I> {
                 return Ok(CommandResult::Consumed(None));
             }
             Command::Save => {
+                if let Some(items) = self.take_marked() {
+                    set_items_saved(items, self.library.clone(), true);
+                    return Ok(CommandResult::Consumed(None));
+                }
                 let mut item = {
                     let content = self.content.read().unwrap();
                     content.get(self.selected).cloned()
@@ -463,6 +548,10 @@ impl<I: ListItem + Clone> ViewExt for ListView<I> {
                 return Ok(CommandResult::Consumed(None));
             }
             Command::Delete => {
+                if let Some(items) = self.take_marked() {
+                    set_items_saved(items, self.library.clone(), false);
+                    return Ok(CommandResult::Consumed(None));
+                }
                 let mut item = {
                     let content = self.content.read().unwrap();
                     content.get(self.selected).cloned()
@@ -494,7 +583,7 @@ impl<I: ListItem + Clone> ViewExt for ListView<I> {
             }
             Command::Jump(mode) => match mode {
                 JumpMode::Query(query) => {
-                    self.search_query = query.to_lowercase();
+                    self.search_query = query.clone();
                     self.search_indexes = self.get_indexes_of(query);
                     self.search_selected_index = 0;
                     match self.search_indexes.first() {
@@ -557,9 +646,28 @@ impl<I: ListItem + Clone> ViewExt for ListView<I> {
                         self.try_paginate();
                         return Ok(CommandResult::Consumed(None));
                     }
+                    MoveMode::Playing => {
+                        let content = self.content.read().unwrap().clone();
+                        let playing = content
+                            .iter()
+                            .position(|item| item.is_playing(self.queue.clone()));
+                        let message = match playing {
+                            Some(index) => {
+                                self.move_focus_to(index);
+                                None
+                            }
+                            None => Some("The playing item isn't in this list".to_string()),
+                        };
+                        return Ok(CommandResult::Consumed(message));
+                    }
                     _ => return Ok(CommandResult::Consumed(None)),
                 }
             }
+            Command::Open(TargetMode::Selected) if !self.marked.is_empty() => {
+                if let Some(contextmenu) = self.context_menu() {
+                    return Ok(CommandResult::Modal(Box::new(contextmenu)));
+                }
+            }
             Command::Open(mode) => {
                 let queue = self.queue.clone();
                 let library = self.library.clone();
@@ -583,7 +691,7 @@ impl<I: ListItem + Clone> ViewExt for ListView<I> {
                     };
                 }
             }
-            Command::Goto(mode) => {
+            Command::Goto(mode, TargetMode::Selected) => {
                 let mut content = self.content.write().unwrap();
                 if let Some(item) = content.get_mut(self.selected) {
                     let queue = self.queue.clone();
@@ -615,6 +723,13 @@ impl<I: ListItem + Clone> ViewExt for ListView<I> {
                                 };
                             }
                         }
+                        GotoMode::Show => {
+                            if let Some(show) = item.show(queue.clone()) {
+                                let view =
+                                    ShowView::new(queue, library, &show).into_boxed_view_ext();
+                                return Ok(CommandResult::View(view));
+                            }
+                        }
                     }
                 }
             }
@@ -42,7 +42,8 @@ impl PlaylistView {
             Arc::new(RwLock::new(tracks)),
             queue.clone(),
             library.clone(),
-        );
+        )
+        .with_context(Some(playlist.playback_context()));
 
         Self {
             playlist,
@@ -106,7 +107,8 @@ impl ViewExt for PlaylistView {
                 Arc::new(RwLock::new(tracks)),
                 self.queue.clone(),
                 self.library.clone(),
-            );
+            )
+            .with_context(Some(self.playlist.playback_context()));
             return Ok(CommandResult::Consumed(None));
         }
 
@@ -1,9 +1,8 @@
 use cursive::traits::{Nameable, Resizable};
-use cursive::view::{Margins, ViewWrapper};
+use cursive::view::{Margins, View, ViewWrapper};
 use cursive::views::{Dialog, EditView, ScrollView, SelectView};
-use cursive::Cursive;
+use cursive::{Cursive, Vec2};
 
-use std::cmp::min;
 use std::sync::Arc;
 
 use crate::command::{Command, MoveMode, ShiftMode};
@@ -19,6 +18,8 @@ pub struct QueueView {
     list: ListView<Playable>,
     library: Arc<Library>,
     queue: Arc<Queue>,
+    /// Version of the queue the marks in `list` refer to
+    version: u64,
 }
 
 impl QueueView {
@@ -28,10 +29,21 @@ impl QueueView {
         QueueView {
             list,
             library,
+            version: queue.version(),
             queue,
         }
     }
 
+    /// Clears the marks once the queue changed, as their indexes may refer to
+    /// other items by now.
+    fn sync_marks(&mut self) {
+        let version = self.queue.version();
+        if version != self.version {
+            self.list.clear_marks();
+            self.version = version;
+        }
+    }
+
     fn save_dialog_cb(
         s: &mut Cursive,
         queue: Arc<Queue>,
@@ -86,6 +98,11 @@ impl QueueView {
 
 impl ViewWrapper for QueueView {
     wrap_impl!(self.list: ListView<Playable>);
+
+    fn wrap_layout(&mut self, size: Vec2) {
+        self.sync_marks();
+        self.list.layout(size);
+    }
 }
 
 impl ViewExt for QueueView {
@@ -117,18 +134,30 @@ impl ViewExt for QueueView {
     }
 
     fn on_command(&mut self, s: &mut Cursive, cmd: &Command) -> Result<CommandResult, String> {
+        self.sync_marks();
         match cmd {
             Command::Play => {
                 self.queue.play(self.list.get_selected_index(), true, false);
                 return Ok(CommandResult::Consumed(None));
             }
             Command::PlayNext => {
-                return Ok(CommandResult::Ignored);
+                let selected = self.list.get_selected_index();
+                if let Some(index) = self.queue.move_to_next(selected) {
+                    self.list.move_focus_to(index);
+                }
+                return Ok(CommandResult::Consumed(None));
             }
             Command::Queue => {
                 return Ok(CommandResult::Ignored);
             }
             Command::Delete => {
+                let marked = self.list.take_marked_indexes();
+                if !marked.is_empty() {
+                    self.queue.remove_many(&marked);
+                    self.list.move_focus(0);
+                    return Ok(CommandResult::Consumed(None));
+                }
+
                 let selected = self.list.get_selected_index();
                 let len = self.queue.len();
 
@@ -139,28 +168,16 @@ impl ViewExt for QueueView {
                 return Ok(CommandResult::Consumed(None));
             }
             Command::Shift(mode, amount) => {
-                let amount = match amount {
-                    Some(amount) => *amount,
-                    _ => 1,
-                };
-
+                let amount = amount.unwrap_or(1).max(0) as usize;
                 let selected = self.list.get_selected_index();
-                let len = self.queue.len();
 
-                match mode {
-                    ShiftMode::Up if selected > 0 => {
-                        self.queue
-                            .shift(selected, (selected as i32).saturating_sub(amount) as usize);
-                        self.list.move_focus(-(amount as i32));
-                        return Ok(CommandResult::Consumed(None));
-                    }
-                    ShiftMode::Down if selected < len.saturating_sub(1) => {
-                        self.queue
-                            .shift(selected, min(selected + amount as usize, len - 1));
-                        self.list.move_focus(amount as i32);
-                        return Ok(CommandResult::Consumed(None));
-                    }
-                    _ => {}
+                let moved = match mode {
+                    ShiftMode::Up => self.queue.move_up(selected, amount),
+                    ShiftMode::Down => self.queue.move_down(selected, amount),
+                };
+                if let Some(index) = moved {
+                    self.list.move_focus_to(index);
+                    return Ok(CommandResult::Consumed(None));
                 }
             }
             Command::SaveQueue => {
@@ -169,10 +186,14 @@ impl ViewExt for QueueView {
                 return Ok(CommandResult::Consumed(None));
             }
             Command::Move(MoveMode::Playing, _) => {
-                if let Some(playing) = self.queue.get_current_index() {
-                    self.list.move_focus_to(playing);
-                }
-                return Ok(CommandResult::Consumed(None));
+                let message = match self.queue.get_current_index() {
+                    Some(playing) => {
+                        self.list.move_focus_to(playing);
+                        None
+                    }
+                    None => Some("Nothing is playing".to_string()),
+                };
+                return Ok(CommandResult::Consumed(message));
             }
             _ => {}
         }
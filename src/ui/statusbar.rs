@@ -156,6 +156,15 @@ impl View for StatusBar {
             ""
         };
 
+        let sleep = match self.spotify.sleep_timer_remaining() {
+            Some(remaining) => format!(
+                "[S {:02}:{:02}] ",
+                remaining.as_secs() / 60,
+                remaining.as_secs() % 60
+            ),
+            None => String::new(),
+        };
+
         let volume = self.volume_display();
 
         printer.with_color(style_bar_bg, |printer| {
@@ -177,6 +186,7 @@ impl View for StatusBar {
         };
 
         let right = updating.to_string()
+            + &sleep
             + repeat
             + shuffle
             // + saved
@@ -219,20 +229,18 @@ impl View for StatusBar {
             let volume_len = self.volume_display().len();
 
             if position.y == 0 {
-                if event == MouseEvent::WheelUp {
-                    self.spotify.seek_relative(-500);
-                }
-
-                if event == MouseEvent::WheelDown {
-                    self.spotify.seek_relative(500);
-                }
-
-                if event == MouseEvent::Press(MouseButton::Left) {
-                    if let Some(playable) = self.queue.get_current() {
-                        let f: f32 = position.x as f32 / self.last_size.x as f32;
-                        let new = playable.duration() as f32 * f;
-                        self.spotify.seek(new as u32);
+                match event {
+                    MouseEvent::WheelUp => self.queue.seek_backward(),
+                    MouseEvent::WheelDown => self.queue.seek_forward(),
+                    // clicking or dragging along the progress bar
+                    MouseEvent::Press(MouseButton::Left) | MouseEvent::Hold(MouseButton::Left) => {
+                        if let Some(playable) = self.queue.get_current() {
+                            let f = position.x as f32 / self.last_size.x.max(1) as f32;
+                            let new = playable.duration() as f32 * f.min(1.0);
+                            self.spotify.seek(new as u32);
+                        }
                     }
+                    _ => {}
                 }
             } else if self.last_size.x - position.x < volume_len {
                 if event == MouseEvent::WheelUp {
@@ -24,12 +24,44 @@ pub fn format_duration(d: &std::time::Duration) -> String {
     s.trim_end().to_string()
 }
 
+/// Returns a human readable String of a size in bytes
+///
+/// Example: `12.3 MiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} {}", bytes, UNITS[0]),
+        _ => format!("{:.1} {}", size, UNITS[unit]),
+    }
+}
+
 pub fn cache_path_for_url(url: String) -> std::path::PathBuf {
     let mut path = crate::config::cache_path("covers");
     path.push(url.split('/').last().unwrap());
     path
 }
 
+/// Returns the path of the cached copy of the cover at `url`, downloading it
+/// first if necessary, or `None` if the download failed.
+pub fn cached_cover(url: String) -> Option<std::path::PathBuf> {
+    let path = cache_path_for_url(url.clone());
+    if !path.exists() {
+        if let Err(e) = download(url.clone(), path.clone()) {
+            log::warn!("could not download cover {}: {}", url, e);
+            std::fs::remove_file(&path).ok();
+            return None;
+        }
+        crate::cache::cover_added(&path);
+    }
+    Some(path)
+}
+
 pub fn download(url: String, path: std::path::PathBuf) -> Result<(), std::io::Error> {
     let mut resp = reqwest::blocking::get(&url)
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
@@ -40,3 +72,18 @@ pub fn download(url: String, path: std::path::PathBuf) -> Result<(), std::io::Er
     std::io::copy(&mut resp, &mut file)?;
     Ok(())
 }
+
+/// Copies `text` to the system clipboard, returns whether that succeeded
+///
+/// Always fails when ncspot is built without clipboard support.
+pub fn copy_to_clipboard(text: &str) -> bool {
+    #[cfg(feature = "share_clipboard")]
+    {
+        crate::sharing::write_share(text.to_string()).is_some()
+    }
+    #[cfg(not(feature = "share_clipboard"))]
+    {
+        let _ = text;
+        false
+    }
+}